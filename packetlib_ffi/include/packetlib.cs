@@ -62,6 +62,13 @@ namespace packetlib
         [DllImport(__DllName, EntryPoint = "clone_data", CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
         internal static extern DataBuffer clone_data(DataBuffer data);
 
+        /// <summary>
+        ///  Returns a fat pointer to the JSON encoded layouts of all known packets or a null pointer if
+        ///  JSON is not supported. The data must be destroyed using [`free_data`].
+        /// </summary>
+        [DllImport(__DllName, EntryPoint = "get_packet_schema", CallingConvention = CallingConvention.Cdecl, ExactSpelling = true)]
+        internal static extern DataBuffer get_packet_schema();
+
         /// <summary>
         ///  Clones the packet.
         ///
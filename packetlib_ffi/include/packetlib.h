@@ -258,6 +258,12 @@ void free_data(struct PLIB_DataBuffer data);
  */
 struct PLIB_DataBuffer clone_data(struct PLIB_DataBuffer data);
 
+/**
+ * Returns a fat pointer to the JSON encoded layouts of all known packets or a null pointer if
+ * JSON is not supported. The data must be destroyed using [`free_data`].
+ */
+struct PLIB_DataBuffer get_packet_schema(void);
+
 /**
  * Clones the packet.
  *
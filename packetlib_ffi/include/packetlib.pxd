@@ -159,6 +159,10 @@ cdef extern from "packetlib.h":
   # - `data` must be a valid [`DataBuffer`] structure with valid data pointer.
   PLIB_DataBuffer clone_data(PLIB_DataBuffer data);
 
+  # Returns a fat pointer to the JSON encoded layouts of all known packets or a null pointer if
+  # JSON is not supported. The data must be destroyed using [`free_data`].
+  PLIB_DataBuffer get_packet_schema();
+
   # Clones the packet.
   #
   # # Safety
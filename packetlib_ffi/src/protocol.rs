@@ -130,6 +130,22 @@ pub extern "C" fn clone_data(data: DataBuffer) -> DataBuffer {
     }
 }
 
+/// Returns a fat pointer to the JSON encoded layouts of all known packets or a null pointer if
+/// JSON is not supported. The data must be destroyed using [`free_data`].
+#[no_mangle]
+pub extern "C" fn get_packet_schema() -> DataBuffer {
+    #[cfg(feature = "json")]
+    if let Ok(data) = serde_json::to_vec(PacketEX::PACKETS) {
+        let data = std::mem::ManuallyDrop::new(data);
+        return DataBuffer {
+            ptr: data.as_ptr(),
+            size: data.len(),
+            _cap: data.capacity(),
+        };
+    }
+    NULL_BUF
+}
+
 /// Clones the packet.
 ///
 /// # Safety
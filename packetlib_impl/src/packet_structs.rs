@@ -16,6 +16,11 @@ pub fn packet_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
     if flags.to_string().contains("PACKED") && xor_sub.is_none() {
         return Err(syn::Error::new(ast.ident.span(), "No magic provided"));
     }
+    let flags_list = get_flags_list(&ast.attrs)?;
+    let magic = match xor_sub {
+        Some((xor, sub)) => quote! {Some((#xor, #sub))},
+        None => quote! {None},
+    };
     let (xor, sub) = xor_sub.unwrap_or((0, 0));
 
     let crate_location = if is_internal {
@@ -26,18 +31,37 @@ pub fn packet_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
 
     let mut read = quote! {};
     let mut write = quote! {};
+    let mut fields = quote! {};
+    let mut reflect = quote! {};
 
     if let Data::Struct(data) = &ast.data {
         parse_struct_field(&mut read, &mut write, data)?;
+        fields = struct_schema(data, &crate_location)?;
+        reflect = struct_reflect(name, data, &crate_location, false);
     }
 
     let code = quote! {
         #[automatically_derived]
         impl #crate_location::protocol::PacketReadWrite for #name {
-            fn read(
+            const SCHEMA: Option<&'static #crate_location::protocol::schema::PacketSchema> =
+                Some(&#crate_location::protocol::schema::PacketSchema {
+                    name: stringify!(#name),
+                    id: #id,
+                    subid: #subid,
+                    flags: {
+                        use #crate_location::derive_reexports::*;
+                        #crate_location::protocol::Flags::from_bits_retain(0 #(| #flags_list.bits())*)
+                    },
+                    magic: #magic,
+                    fields: &[#fields],
+                });
+
+            fn read_spanned(
                 reader: &mut (impl std::io::Read + std::io::Seek),
                 flags: &#crate_location::protocol::Flags,
-                packet_type: #crate_location::protocol::PacketType
+                packet_type: #crate_location::protocol::PacketType,
+                spans: &mut #crate_location::protocol::spans::SpanRecorder,
+                ctx: &mut #crate_location::protocol::limits::DecodeContext,
             ) -> Result<Self, #crate_location::protocol::PacketError> {
                 use #crate_location::derive_reexports::*;
                 use #crate_location::protocol::PacketError as Error;
@@ -61,6 +85,8 @@ pub fn packet_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
                 Ok(buf)
             }
         }
+
+        #reflect
     };
     Ok(code.into())
 }
@@ -79,33 +105,84 @@ pub fn helper_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
     } else {
         quote! {pso2packetlib}
     };
+    let schema_location = quote! {#crate_location::protocol::schema};
 
-    match &ast.data {
+    let reflect_location = quote! {#crate_location::protocol::reflect};
+    let mut is_spanned = false;
+    let mut reflect = quote! {};
+    let mut value = quote! {};
+    let schema = match &ast.data {
         Data::Struct(_) if is_bitflags.is_some() => {
             let Some(repr_type) = is_bitflags else {
                 unreachable!()
             };
-            parse_bitflags(&mut read, &mut write, repr_type)?
+            let repr = repr_type.schema();
+            value = quote! {
+                fn as_value(&self) -> #reflect_location::Value<'_> {
+                    #reflect_location::Value::#repr(self.bits())
+                }
+            };
+            parse_bitflags(&mut read, &mut write, repr_type)?;
+            quote! {#schema_location::TypeSchema::BitFlags(&#schema_location::TypeSchema::#repr)}
         }
         Data::Struct(data) if is_flags.is_some() => {
             let Some(repr_type) = is_flags else {
                 unreachable!()
             };
-            parse_flags_struct(&mut read, &mut write, data, repr_type)?
+            let repr = repr_type.schema();
+            let flags = flags_schema(data, &repr_type);
+            reflect = struct_reflect(name, data, &crate_location, true);
+            value = struct_value(&reflect_location);
+            parse_flags_struct(&mut read, &mut write, data, repr_type)?;
+            quote! {#schema_location::TypeSchema::Flags(&#schema_location::FlagsSchema {
+                name: stringify!(#name),
+                repr: &#schema_location::TypeSchema::#repr,
+                flags: &[#flags],
+            })}
         }
-        Data::Struct(data) => parse_struct_field(&mut read, &mut write, data)?,
-        Data::Enum(data) => parse_enum(&mut read, &mut write, data, repr_type)?,
-        _ => {}
-    }
+        Data::Struct(data) => {
+            is_spanned = true;
+            reflect = struct_reflect(name, data, &crate_location, false);
+            value = struct_value(&reflect_location);
+            parse_struct_field(&mut read, &mut write, data)?;
+            let fields = struct_schema(data, &crate_location)?;
+            quote! {#schema_location::TypeSchema::Struct(&#schema_location::StructSchema {
+                name: stringify!(#name),
+                fields: &[#fields],
+            })}
+        }
+        Data::Enum(data) => {
+            let repr = repr_type.schema();
+            let (variants, default) = enum_schema(data, &repr_type)?;
+            let names = data.variants.iter().map(|v| &v.ident);
+            value = quote! {
+                fn as_value(&self) -> #reflect_location::Value<'_> {
+                    #reflect_location::Value::Enum(match self {
+                        #(Self::#names => stringify!(#names),)*
+                    })
+                }
+            };
+            parse_enum(&mut read, &mut write, data, repr_type)?;
+            quote! {#schema_location::TypeSchema::Enum(&#schema_location::EnumSchema {
+                name: stringify!(#name),
+                repr: &#schema_location::TypeSchema::#repr,
+                variants: &[#variants],
+                default: #default,
+            })}
+        }
+        _ => quote! {#schema_location::TypeSchema::Opaque},
+    };
 
-    let gen = quote! {
-        #[automatically_derived]
-        impl #crate_location::protocol::HelperReadWrite for #name {
-            fn read(
+    // structs record spans of their fields, other types are read as a whole
+    let read_fns = if is_spanned {
+        quote! {
+            fn read_spanned(
                 reader: &mut (impl std::io::Read + std::io::Seek),
                 packet_type: #crate_location::protocol::PacketType,
                 xor: u32,
-                sub: u32
+                sub: u32,
+                spans: &mut #crate_location::protocol::spans::SpanRecorder,
+                ctx: &mut #crate_location::protocol::limits::DecodeContext,
             ) -> Result<Self, #crate_location::protocol::PacketError> {
                 use #crate_location::derive_reexports::*;
                 use #crate_location::protocol::PacketError as Error;
@@ -113,6 +190,32 @@ pub fn helper_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
 
                 #read
             }
+        }
+    } else {
+        quote! {
+            fn read_spanned(
+                reader: &mut (impl std::io::Read + std::io::Seek),
+                packet_type: #crate_location::protocol::PacketType,
+                xor: u32,
+                sub: u32,
+                _: &mut #crate_location::protocol::spans::SpanRecorder,
+                _: &mut #crate_location::protocol::limits::DecodeContext,
+            ) -> Result<Self, #crate_location::protocol::PacketError> {
+                use #crate_location::derive_reexports::*;
+                use #crate_location::protocol::PacketError as Error;
+                let packet_name = stringify!(#name);
+
+                #read
+            }
+        }
+    };
+
+    let gen = quote! {
+        #[automatically_derived]
+        impl #crate_location::protocol::HelperReadWrite for #name {
+            const SCHEMA: #schema_location::TypeSchema = #schema;
+
+            #read_fns
             fn write(
                 &self,
                 writer: &mut impl std::io::Write,
@@ -127,11 +230,210 @@ pub fn helper_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<
                 #write
                 Ok(())
             }
+
+            #value
         }
+
+        #reflect
     };
     Ok(gen.into())
 }
 
+/// Generates the visitor functions of a struct. Fields of flags structs are visited as booleans.
+fn struct_reflect(name: &Ident, data: &DataStruct, crate_location: &TS2, is_flags: bool) -> TS2 {
+    let reflect_location = quote! {#crate_location::protocol::reflect};
+    let mut visit = quote! {};
+    let mut visit_mut = quote! {};
+    for (id, field) in data.fields.iter().enumerate() {
+        let (field_name, field_access) = match &field.ident {
+            Some(name) => (name.to_string(), quote! {#name}),
+            None => {
+                let index = syn::Index::from(id);
+                (id.to_string(), quote! {#index})
+            }
+        };
+        let is_manual = field.attrs.iter().any(|a| a.path().is_ident("ManualRW"));
+        let (value, value_mut) = if is_flags {
+            (
+                quote! {#reflect_location::Value::Bool(self.#field_access)},
+                quote! {#reflect_location::ValueMut::Bool(&mut self.#field_access)},
+            )
+        } else if is_manual {
+            (
+                quote! {#reflect_location::Value::Opaque},
+                quote! {#reflect_location::ValueMut::Opaque},
+            )
+        } else {
+            let field_type = &field.ty;
+            (
+                quote! {<#field_type as #crate_location::protocol::HelperReadWrite>::as_value(&self.#field_access)},
+                quote! {<#field_type as #crate_location::protocol::HelperReadWrite>::as_value_mut(&mut self.#field_access)},
+            )
+        };
+        visit.extend(quote! {visitor.visit(#field_name, #value);});
+        visit_mut.extend(quote! {visitor.visit(#field_name, #value_mut);});
+    }
+    quote! {
+        #[automatically_derived]
+        impl #reflect_location::Reflect for #name {
+            fn type_name(&self) -> &'static str {
+                stringify!(#name)
+            }
+            fn visit_fields<'a>(&'a self, visitor: &mut dyn #reflect_location::FieldVisitor<'a>) {
+                #visit
+            }
+            fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn #reflect_location::FieldVisitorMut<'a>) {
+                #visit_mut
+            }
+        }
+    }
+}
+
+/// Generates the value functions of a struct implementing `Reflect`.
+fn struct_value(reflect_location: &TS2) -> TS2 {
+    quote! {
+        fn as_value(&self) -> #reflect_location::Value<'_> {
+            #reflect_location::Value::Struct(self)
+        }
+        fn as_value_mut(&mut self) -> #reflect_location::ValueMut<'_> {
+            #reflect_location::ValueMut::Struct(self)
+        }
+    }
+}
+
+fn enum_schema(data: &DataEnum, repr_type: &Size) -> syn::Result<(TS2, TS2)> {
+    let mut variants = quote! {};
+    let mut default = quote! {None};
+    let mut discriminant = repr_type.discriminant(0);
+    for variant in &data.variants {
+        let variant_name = &variant.ident;
+        if let Some((_, Expr::Lit(x))) = &variant.discriminant {
+            let Lit::Int(int) = &x.lit else {
+                return Err(syn::Error::new(x.span(), "Expected integer literal"));
+            };
+            discriminant = repr_type.discriminant(int.base10_parse()?);
+        }
+        if variant
+            .attrs
+            .iter()
+            .any(|a| a.path().is_ident("Read_default"))
+        {
+            default = quote! {Some(stringify!(#variant_name))};
+        } else {
+            let value = discriminant.value() as u64;
+            variants.extend(quote! {(stringify!(#variant_name), #value),});
+        }
+        discriminant.increase();
+    }
+    Ok((variants, default))
+}
+
+fn flags_schema(data: &DataStruct, repr_type: &Size) -> TS2 {
+    let mut flags = quote! {};
+    let mut discriminant = repr_type.discriminant(1);
+    for field in data.fields.iter() {
+        let field_name = field.ident.as_ref().unwrap();
+        if field.attrs.iter().any(|a| a.path().is_ident("Skip")) {
+            discriminant.skip_flag();
+        }
+        let value = discriminant.value() as u64;
+        flags.extend(quote! {(stringify!(#field_name), #value),});
+        discriminant.skip_flag();
+    }
+    flags
+}
+
+fn struct_schema(data: &DataStruct, crate_location: &TS2) -> syn::Result<TS2> {
+    let schema_location = quote! {#crate_location::protocol::schema};
+    let mut fields = quote! {};
+    for (id, field) in data.fields.iter().enumerate() {
+        let field_name = match &field.ident {
+            Some(name) => name.to_string(),
+            None => id.to_string(),
+        };
+        let mut padding_before = quote! {};
+        let mut padding_after = 0i64;
+        let mut only_on = quote! {None};
+        let mut not_on = quote! {None};
+        let mut is_manual = false;
+        for attr in &field.attrs {
+            let Some(attribute_name) = attr.path().get_ident().map(|i| i.to_string()) else {
+                continue;
+            };
+            match (attribute_name.as_str(), &attr.meta) {
+                ("Seek", syn::Meta::List(list)) => {
+                    let amount: i64 = list.parse_args::<LitInt>()?.base10_parse()?;
+                    padding_before.extend(quote! {#schema_location::Padding::Seek(#amount),});
+                }
+                ("Const_u16", syn::Meta::List(list)) => {
+                    let num: u16 = list.parse_args::<LitInt>()?.base10_parse()?;
+                    padding_before.extend(quote! {#schema_location::Padding::ConstU16(#num),});
+                }
+                ("SeekAfter", syn::Meta::List(list)) => {
+                    padding_after = list.parse_args::<LitInt>()?.base10_parse()?;
+                }
+                ("OnlyOn", syn::Meta::List(list)) => {
+                    let types = packet_types(&list.tokens, crate_location);
+                    only_on = quote! {Some(&[#types])};
+                }
+                ("NotOn", syn::Meta::List(list)) => {
+                    let types = packet_types(&list.tokens, crate_location);
+                    not_on = quote! {Some(&[#types])};
+                }
+                ("ManualRW", _) => is_manual = true,
+                _ => {}
+            }
+        }
+        let ty = if is_manual {
+            quote! {#schema_location::TypeSchema::Opaque}
+        } else {
+            let field_type = &field.ty;
+            quote! {<#field_type as #crate_location::protocol::HelperReadWrite>::SCHEMA}
+        };
+        let type_name = type_name(&field.ty);
+        fields.extend(quote! {#schema_location::FieldSchema {
+            name: #field_name,
+            type_name: #type_name,
+            ty: &#ty,
+            padding_before: &[#padding_before],
+            padding_after: #padding_after,
+            only_on: #only_on,
+            not_on: #not_on,
+        },});
+    }
+    Ok(fields)
+}
+
+/// Returns the type as written in the source (without the extra spaces).
+fn type_name(ty: &Type) -> String {
+    ty.to_token_stream()
+        .to_string()
+        .replace(' ', "")
+        .replace(',', ", ")
+        .replace(';', "; ")
+}
+
+/// Converts a packet type pattern (e.g. `PacketType::NGS | PacketType::Vita`) to a list of
+/// packet types.
+fn packet_types(pattern: &TS2, crate_location: &TS2) -> TS2 {
+    let mut types = quote! {};
+    let mut last_ident = None;
+    for token in pattern.clone().into_iter().chain(std::iter::once(
+        proc_macro2::Punct::new('|', proc_macro2::Spacing::Alone).into(),
+    )) {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) => last_ident = Some(ident),
+            proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '|' => {
+                if let Some(ident) = last_ident.take() {
+                    types.extend(quote! {#crate_location::protocol::PacketType::#ident,});
+                }
+            }
+            _ => {}
+        }
+    }
+    types
+}
+
 fn parse_enum(
     read: &mut TS2,
     write: &mut TS2,
@@ -355,17 +657,20 @@ fn parse_struct_field(read: &mut TS2, write: &mut TS2, data: &DataStruct) -> syn
             let field_name = format_ident!("unnamed_{}", id);
             return_token.extend(quote! {#field_name,});
 
+            let span_name = id.to_string();
             let id = syn::Index::from(id);
             write.extend(quote! { let #field_name = self.#id;});
 
+            let mut tmp_read = quote! {};
             parse_field_type(
                 &field.ty,
-                read,
+                &mut tmp_read,
                 write,
                 &field_name,
                 &Settings::default(),
                 false,
             )?;
+            read.extend(field_span(tmp_read, &field_name, &span_name));
         }
         read.extend(quote! {Ok(Self(#return_token))});
         return Ok(());
@@ -402,6 +707,7 @@ fn parse_struct_field(read: &mut TS2, write: &mut TS2, data: &DataStruct) -> syn
             &settings,
             true,
         )?;
+        let tmp_read = field_span(tmp_read, field_name, &field_name.to_string());
 
         if let Some(data) = settings.only_on {
             read.extend(quote! {let #field_name = if matches!(packet_type, #data) {
@@ -430,13 +736,16 @@ fn parse_struct_field(read: &mut TS2, write: &mut TS2, data: &DataStruct) -> syn
 
         if settings.seek_after != 0 {
             let seek_after = settings.seek_after;
-            read.extend(quote! {reader.seek(std::io::SeekFrom::Current(#seek_after))
-                .map_err(|e| Error::PaddingError{
-                    packet_name,
-                    field_name: stringify!(#field_name),
-                    error: e,
-                })?;
-            });
+            read.extend(padding_span(
+                quote! {reader.seek(std::io::SeekFrom::Current(#seek_after))
+                    .map_err(|e| Error::PaddingError{
+                        packet_name,
+                        field_name: stringify!(#field_name),
+                        error: e,
+                    })?;
+                },
+                quote! {stringify!(#field_name)},
+            ));
             write.extend(quote! {writer.write_all(&[0u8; #seek_after as usize])
                 .map_err(|e| Error::PaddingError{
                     packet_name,
@@ -450,6 +759,44 @@ fn parse_struct_field(read: &mut TS2, write: &mut TS2, data: &DataStruct) -> syn
     Ok(())
 }
 
+/// Wraps the field read code with span recording.
+fn field_span(read: TS2, field_name: &Ident, span_name: &str) -> TS2 {
+    quote! {
+        spans.begin(reader, #span_name, SpanKind::Value)
+            .map_err(|e| Error::FieldError{
+                packet_name,
+                field_name: stringify!(#field_name),
+                error: e,
+            })?;
+        #read
+        spans.end(reader)
+            .map_err(|e| Error::FieldError{
+                packet_name,
+                field_name: stringify!(#field_name),
+                error: e,
+            })?;
+    }
+}
+
+/// Wraps the padding read code with span recording.
+fn padding_span(read: TS2, field_name: TS2) -> TS2 {
+    quote! {
+        spans.begin(reader, "padding", SpanKind::Padding)
+            .map_err(|e| Error::PaddingError{
+                packet_name,
+                field_name: #field_name,
+                error: e,
+            })?;
+        #read
+        spans.end(reader)
+            .map_err(|e| Error::PaddingError{
+                packet_name,
+                field_name: #field_name,
+                error: e,
+            })?;
+    }
+}
+
 #[derive(Default)]
 struct Settings {
     seek_after: i64,
@@ -497,13 +844,16 @@ fn get_attrs(
         }
         "Seek" => {
             let amount: i64 = list.unwrap().parse_args::<LitInt>()?.base10_parse()?;
-            read.extend(quote! {reader.seek(std::io::SeekFrom::Current(#amount))
-                .map_err(|e| Error::PaddingError{
-                    packet_name,
-                    field_name: "unknown",
-                    error: e,
-                })?;
-            });
+            read.extend(padding_span(
+                quote! {reader.seek(std::io::SeekFrom::Current(#amount))
+                    .map_err(|e| Error::PaddingError{
+                        packet_name,
+                        field_name: "unknown",
+                        error: e,
+                    })?;
+                },
+                quote! {"unknown"},
+            ));
             write.extend(quote! {writer.write_all(&[0u8; #amount as usize])
                 .map_err(|e| Error::PaddingError{
                     packet_name,
@@ -517,12 +867,25 @@ fn get_attrs(
         }
         "Const_u16" => {
             let num: u16 = list.unwrap().parse_args::<LitInt>()?.base10_parse()?;
-            read.extend(quote! {reader.seek(std::io::SeekFrom::Current(2))
-                .map_err(|e| Error::ConstantError{
-                    packet_name,
-                    const_val: #num as _,
-                    error: e,
-                })?;
+            read.extend(quote! {
+                spans.begin(reader, "const", SpanKind::Constant)
+                    .map_err(|e| Error::ConstantError{
+                        packet_name,
+                        const_val: #num as _,
+                        error: e,
+                    })?;
+                reader.seek(std::io::SeekFrom::Current(2))
+                    .map_err(|e| Error::ConstantError{
+                        packet_name,
+                        const_val: #num as _,
+                        error: e,
+                    })?;
+                spans.end(reader)
+                    .map_err(|e| Error::ConstantError{
+                        packet_name,
+                        const_val: #num as _,
+                        error: e,
+                    })?;
             });
             write.extend(quote! {writer.write_u16::<LittleEndian>(#num)
                 .map_err(|e| Error::ConstantError{
@@ -573,7 +936,7 @@ fn type_read_write(
 
     if let Some((read_fn, write_fn)) = &set.manual_rw {
         read.extend(
-            quote! { let #field_name = #read_fn(reader, packet_type, xor, sub)
+            quote! { let #field_name = #read_fn(reader, packet_type, xor, sub, spans, ctx)
                 .map_err(|e| Error::CompositeFieldError{
                     packet_name,
                     field_name: stringify!(#field_name),
@@ -593,7 +956,7 @@ fn type_read_write(
     }
 
     let out_type = TS2::from_str(&full_type_path)?;
-    read.extend(quote! {let #field_name = <#out_type as HelperReadWrite>::read(reader, packet_type, xor, sub)
+    read.extend(quote! {let #field_name = <#out_type as HelperReadWrite>::read_spanned(reader, packet_type, xor, sub, spans, ctx)
                 .map_err(|e| {
                     Error::CompositeFieldError{
                         packet_name,
@@ -649,6 +1012,31 @@ fn get_flags(attrs: &[Attribute]) -> syn::Result<TS2> {
     Ok(quote! {#attrs})
 }
 
+/// Splits the packet flags (e.g. `Flags::PACKED | Flags::OBJECT_RELATED`) into separate flags.
+fn get_flags_list(attrs: &[Attribute]) -> syn::Result<Vec<TS2>> {
+    let Some(attr) = attrs.iter().find(|a| a.path().is_ident("Flags")) else {
+        return Ok(vec![]);
+    };
+    let syn::Meta::List(list) = &attr.meta else {
+        return Err(syn::Error::new(
+            attr.span(),
+            "Invalid syntax \nPerhaps you ment Flags(..)?",
+        ));
+    };
+    let mut flags = vec![quote! {}];
+    for token in list.tokens.clone() {
+        match token {
+            proc_macro2::TokenTree::Punct(punct) if punct.as_char() == '|' => flags.push(quote! {}),
+            token => flags.last_mut().unwrap().extend(std::iter::once(token)),
+        }
+    }
+    Ok(flags
+        .into_iter()
+        .filter(|f| !f.is_empty())
+        .map(|f| quote! {(#f)})
+        .collect())
+}
+
 fn get_repr(attrs: &[Attribute]) -> syn::Result<Size> {
     let Some(attr) = attrs.iter().find(|a| a.path().is_ident("repr")) else {
         return Ok(Size::U8);
@@ -736,6 +1124,28 @@ enum Size {
     U128,
 }
 
+impl Size {
+    fn schema(&self) -> Ident {
+        let name = match self {
+            Size::U8 => "U8",
+            Size::U16 => "U16",
+            Size::U32 => "U32",
+            Size::U64 => "U64",
+            Size::U128 => "U128",
+        };
+        Ident::new(name, Span::call_site())
+    }
+    fn discriminant(&self, value: u128) -> Discriminant {
+        match self {
+            Size::U8 => Discriminant::U8(value as _),
+            Size::U16 => Discriminant::U16(value as _),
+            Size::U32 => Discriminant::U32(value as _),
+            Size::U64 => Discriminant::U64(value as _),
+            Size::U128 => Discriminant::U128(value),
+        }
+    }
+}
+
 struct AttributeList {
     fields: Punctuated<LitInt, Token![,]>,
 }
@@ -791,6 +1201,15 @@ impl Discriminant {
             Discriminant::U128(x) => *x = x.overflowing_add(1).0,
         }
     }
+    fn value(&self) -> u128 {
+        match self {
+            Discriminant::U8(x) => *x as _,
+            Discriminant::U16(x) => *x as _,
+            Discriminant::U32(x) => *x as _,
+            Discriminant::U64(x) => *x as _,
+            Discriminant::U128(x) => *x,
+        }
+    }
     fn skip_flag(&mut self) {
         match self {
             Discriminant::U8(x) => *x <<= 1,
@@ -3,7 +3,7 @@ use proc_macro2::{Span, TokenStream as TS2};
 use quote::quote;
 use syn::{
     parse::Parse, punctuated::Punctuated, spanned::Spanned, Data, DataEnum, Fields, FieldsUnnamed,
-    LitInt, MetaList, Token, Type, TypePath,
+    Ident, LitInt, MetaList, Token, Type, TypePath,
 };
 
 #[derive(Default)]
@@ -12,6 +12,9 @@ struct OutputCode {
     write: TS2,
     category: TS2,
     read_raw: TS2,
+    packets: TS2,
+    reflect: TS2,
+    reflect_mut: TS2,
 }
 
 pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Result<TokenStream> {
@@ -25,24 +28,29 @@ pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Resul
             "ProtocolRW is only defined for enums",
         ));
     };
-    parse_enum_field(&mut out_code, data)?;
-
     let crate_location = if is_internal {
         quote! {crate}
     } else {
         quote! {pso2packetlib}
     };
 
+    parse_enum_field(&mut out_code, data, &crate_location)?;
+
     let OutputCode {
         read,
         write,
         category,
         read_raw,
+        packets,
+        reflect,
+        reflect_mut,
     } = out_code;
 
     let gen = quote! {
         #[automatically_derived]
         impl #crate_location::protocol::ProtocolRW for #name {
+            const PACKETS: &'static [#crate_location::protocol::schema::PacketInfo] = &[#packets];
+
             fn write(&self, packet_type: #crate_location::protocol::PacketType) -> Vec<u8> {
                 use #crate_location::derive_reexports::*;
                 use #crate_location::protocol::PacketError;
@@ -61,6 +69,24 @@ pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Resul
             fn read(
                 input: &[u8],
                 packet_type: #crate_location::protocol::PacketType,
+            ) -> Result<Vec<Self>, #crate_location::protocol::PacketError> {
+                let mut spans = #crate_location::protocol::spans::SpanRecorder::disabled();
+                Self::read_spanned(input, packet_type, &mut spans)
+            }
+            fn read_spanned(
+                input: &[u8],
+                packet_type: #crate_location::protocol::PacketType,
+                spans: &mut #crate_location::protocol::spans::SpanRecorder,
+            ) -> Result<Vec<Self>, #crate_location::protocol::PacketError> {
+                let mut ctx = #crate_location::protocol::limits::DecodeContext::unlimited();
+                Self::read_trailing(input, packet_type, spans, &mut ctx, &mut |_, _| Ok(()))
+            }
+            fn read_trailing(
+                input: &[u8],
+                packet_type: #crate_location::protocol::PacketType,
+                spans: &mut #crate_location::protocol::spans::SpanRecorder,
+                ctx: &mut #crate_location::protocol::limits::DecodeContext,
+                trailing: &mut #crate_location::protocol::TrailingDataHandler,
             ) -> Result<Vec<Self>, #crate_location::protocol::PacketError> {
                 use #crate_location::derive_reexports::*;
                 use #crate_location::protocol::PacketError;
@@ -88,6 +114,12 @@ pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Resul
                         });
                     }
                     #read_raw
+                    // the packet span starts at the length, field spans start at the header
+                    spans.set_base(pointer as u64 - 4);
+                    spans.begin_at(0, packet_name, SpanKind::Value);
+                    spans.add(0, 4, "len", SpanKind::Length);
+                    spans.set_base(pointer as u64);
+                    spans.add(0, 4, "header", SpanKind::Value);
                     let mut buf_tmp = std::io::Cursor::new(&input[pointer..pointer + len]);
                     let header = PacketHeader::read(&mut buf_tmp, packet_type).map_err(|e| {
                         PacketError::CompositeFieldError {
@@ -97,11 +129,16 @@ pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Resul
                         }
                     })?;
                     let flags = &header.flag;
+                    // allocation limits apply per packet
+                    ctx.reset_allocated();
 
                     pointer += len;
-                    match (header.id, header.subid, packet_type) {
+                    let variant_name = match (header.id, header.subid, packet_type) {
                         #read
-                    }
+                    };
+                    spans.end_at(len as u64);
+                    let read_len = buf_tmp.position() as usize;
+                    trailing(variant_name, &buf_tmp.into_inner()[read_len..])?;
                 }
 
                 Ok(packets)
@@ -113,18 +150,46 @@ pub fn protocol_deriver(ast: &syn::DeriveInput, is_internal: bool) -> syn::Resul
                 };
                 cat
             }
+            fn as_reflect(&self) -> Option<&dyn #crate_location::protocol::reflect::Reflect> {
+                match self {
+                    #reflect
+                    _ => None,
+                }
+            }
+            fn as_reflect_mut(&mut self) -> Option<&mut dyn #crate_location::protocol::reflect::Reflect> {
+                match self {
+                    #reflect_mut
+                    _ => None,
+                }
+            }
+        }
+        impl #crate_location::protocol::PacketAny for #name {
+            fn as_any(&self) -> Option<&dyn std::any::Any> {
+                Some(self)
+            }
+            fn from_any(value: &dyn std::any::Any) -> Option<&Self> {
+                value.downcast_ref()
+            }
         }
     };
     Ok(gen.into())
 }
 
-fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<()> {
+fn parse_enum_field(
+    out_code: &mut OutputCode,
+    data: &DataEnum,
+    crate_location: &TS2,
+) -> syn::Result<()> {
     let mut category_stream = quote! {Default::default()};
+    let mut category_const = quote! {#crate_location::protocol::PacketCategory::Unknown};
     let OutputCode {
         read,
         write,
         category,
         read_raw,
+        packets,
+        reflect,
+        reflect_mut,
     } = out_code;
     for variant in &data.variants {
         let name = &variant.ident;
@@ -169,7 +234,9 @@ fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<(
             })
         }
         let mut push_string = quote! {};
+        let mut schema = quote! {None};
         if !settings.category.is_empty() {
+            category_const = settings.category.clone();
             category_stream = settings.category
         }
         match &variant.fields {
@@ -196,10 +263,17 @@ fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<(
                         ));
                     }
                     let struct_field = path.get_ident().unwrap();
-                    push_string = quote! {packets.push(Self::#name(#struct_field::read(&mut buf_tmp, flags, packet_type)?))};
+                    schema = quote! {<#struct_field as #crate_location::protocol::PacketReadWrite>::SCHEMA};
+                    push_string = quote! {packets.push(Self::#name(#struct_field::read_spanned(&mut buf_tmp, flags, packet_type, spans, ctx)?))};
                     write.extend(quote! {
                         Self::#name(packet) => packet.write(packet_type),
                     });
+                    reflect.extend(quote! {
+                        Self::#name(packet) => Some(packet),
+                    });
+                    reflect_mut.extend(quote! {
+                        Self::#name(packet) => Some(packet),
+                    });
                     category.extend(quote! {
                         Self::#name(_) => {#category_stream},
                     })
@@ -214,8 +288,9 @@ fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<(
                                 field_name: stringify!(#name),
                                 error: e
                             })?;
+                            spans.add(4, data.len() as u64, "data", SpanKind::Value);
                             (header, data)
-                        }));
+                        }))
                     };
                     write.extend(quote! {
                         Self::#name((header, data)) => {
@@ -249,6 +324,14 @@ fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<(
                     write.extend(quote! {
                         Self::#name => Ok(PacketHeader::new(#id, #subid, Flags::default()).write(packet_type)),
                     });
+                    schema = quote! {Some(&#crate_location::protocol::schema::PacketSchema {
+                        name: stringify!(#name),
+                        id: #id,
+                        subid: #subid,
+                        flags: #crate_location::protocol::Flags::empty(),
+                        magic: None,
+                        fields: &[],
+                    })};
                 }
                 category.extend(quote! {
                     Self::#name => {#category_stream},
@@ -256,6 +339,22 @@ fn parse_enum_field(out_code: &mut OutputCode, data: &DataEnum) -> syn::Result<(
             }
             _ => {}
         }
+        let packet_types = settings.packet_type.types();
+        if !settings.unknown && !packet_types.is_empty() {
+            packets.extend(quote! {#crate_location::protocol::schema::PacketInfo {
+                name: stringify!(#name),
+                id: #id,
+                subid: #subid,
+                category: #category_const,
+                packet_types: &[#(#crate_location::protocol::PacketType::#packet_types),*],
+                schema: #schema,
+            },});
+        }
+        let push_string = quote! {
+            spans.set_name(stringify!(#name));
+            #push_string;
+            stringify!(#name)
+        };
         match settings.packet_type {
             PacketType::Both => read.extend(quote! {
                 (#id, #subid, _) => {#push_string},
@@ -352,6 +451,25 @@ enum PacketType {
     Empty,
 }
 
+impl PacketType {
+    /// Returns names of the packet types that the packet is read on.
+    fn types(&self) -> Vec<Ident> {
+        let names: &[&str] = match self {
+            PacketType::Both => &["NGS", "Classic", "NA", "JP", "Vita"],
+            PacketType::Classic => &["Classic", "NA", "JP", "Vita"],
+            PacketType::Ngs => &["NGS"],
+            PacketType::Na => &["NA"],
+            PacketType::Jp => &["JP"],
+            PacketType::Vita => &["Vita"],
+            PacketType::Empty => &[],
+        };
+        names
+            .iter()
+            .map(|n| Ident::new(n, Span::call_site()))
+            .collect()
+    }
+}
+
 struct AttributeList {
     fields: Punctuated<LitInt, Token![,]>,
 }
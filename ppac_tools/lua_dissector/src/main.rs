@@ -0,0 +1,32 @@
+use std::{env, fs};
+
+use pso2packetlib::{
+    dissector,
+    protocol::{Packet, PacketType},
+};
+
+const USAGE: &str = "Usage:
+  lua_dissector {output} [ngs|classic|na|jp|vita]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(output) = args.first() else {
+        eprintln!("{USAGE}");
+        std::process::exit(1);
+    };
+    let packet_type = match args.get(1).map(|s| s.to_lowercase()).as_deref() {
+        None | Some("ngs") => PacketType::NGS,
+        Some("classic") => PacketType::Classic,
+        Some("na") => PacketType::NA,
+        Some("jp") => PacketType::JP,
+        Some("vita") => PacketType::Vita,
+        Some(_) => {
+            eprintln!("{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = fs::write(output, dissector::wireshark_lua::<Packet>(packet_type)) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    env,
+    error::Error,
+    fs::{create_dir_all, File},
+    io::{BufReader, BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use pso2packetlib::ppac::edit::{self, SplitBy};
+
+const USAGE: &str = "Usage:
+  ppac_edit merge {output} {input}...
+  ppac_edit split {input} {output dir} time {seconds}|stream|count {records}
+  ppac_edit trim {input} {output} {start} {end}";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let arg = |n: usize| args.get(n).map(String::as_str).ok_or(USAGE);
+    match arg(0)? {
+        "merge" => {
+            let output = arg(1)?;
+            let inputs = args
+                .get(2..)
+                .filter(|inputs| !inputs.is_empty())
+                .ok_or(USAGE)?
+                .iter()
+                .map(|path| Ok(BufReader::new(File::open(path)?)))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            edit::merge(inputs, BufWriter::new(File::create(output)?))?.flush()?;
+        }
+        "split" => {
+            let input = BufReader::new(File::open(arg(1)?)?);
+            let out_dir = PathBuf::from(arg(2)?);
+            create_dir_all(&out_dir)?;
+            let by = match arg(3)? {
+                "time" => SplitBy::Time(Duration::try_from_secs_f64(arg(4)?.parse()?)?),
+                "stream" => SplitBy::Stream,
+                "count" => SplitBy::Records(arg(4)?.parse()?),
+                _ => return Err(USAGE.into()),
+            };
+            let parts = edit::split(input, by, |part| {
+                let path = out_dir.join(format!("{part}.pak"));
+                println!("{path:?}");
+                Ok(BufWriter::new(File::create(path)?))
+            })?;
+            for mut part in parts {
+                part.flush()?;
+            }
+        }
+        "trim" => {
+            let start = Duration::try_from_secs_f64(arg(3)?.parse()?)?;
+            let end = Duration::try_from_secs_f64(arg(4)?.parse()?)?;
+            let input = BufReader::new(File::open(arg(1)?)?);
+            let output = BufWriter::new(File::create(arg(2)?)?);
+            edit::trim(input, output, start..end)?.flush()?;
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
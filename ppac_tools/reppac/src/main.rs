@@ -56,8 +56,14 @@ fn to_repack<T: AsRef<Path>, P: AsRef<Path>>(
     };
     println!("{out_path:?}");
     reader.set_out_type(pso2packetlib::ppac::OutputType::Raw);
-    let mut writer = PPACWriter::new(File::create(&out_path)?, reader.get_protocol_type(), to_enc)?;
+    let mut writer = PPACWriter::with_metadata(
+        File::create(&out_path)?,
+        reader.get_protocol_type(),
+        to_enc,
+        reader.get_metadata(),
+    )?;
     while let Some(packet) = reader.read()? {
+        writer.set_stream_id(packet.stream_id);
         let raw_packet = Packet::Raw(packet.data.unwrap());
         writer.write_packet(packet.time, packet.direction, &raw_packet)?;
     }
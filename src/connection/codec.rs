@@ -0,0 +1,104 @@
+//! [`tokio_util::codec`] support.
+
+use super::{ConnectionError, PrivateKey, ProtocolEngine, PublicKey};
+use crate::protocol::{PacketType, ProtocolRW};
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Codec for PSO2 packets. Handles packet framing and the encryption handshake the same way as
+/// [`crate::Connection`].
+///
+/// Client handshake can be started using [`ProtocolEngine::start_handshake`] on
+/// [`PacketCodec::engine_mut`]. The encryption request is then sent before the next encoded
+/// packet.
+///
+/// # Example
+///
+/// ```
+/// # use futures::{SinkExt, StreamExt};
+/// # use pso2packetlib::connection::PacketCodec;
+/// # use pso2packetlib::protocol::{Packet, PacketType};
+/// # use pso2packetlib::{PrivateKey, PublicKey};
+/// # use tokio_util::codec::Framed;
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() -> Result<(), pso2packetlib::connection::ConnectionError> {
+/// let (client, server) = tokio::io::duplex(1024);
+/// let codec = || PacketCodec::<Packet>::new(PacketType::NGS, PrivateKey::None, PublicKey::None);
+/// let mut client = Framed::new(client, codec());
+/// let mut server = Framed::new(server, codec());
+///
+/// client.send(Packet::InitialLoad).await?;
+/// assert_eq!(server.next().await.transpose()?, Some(Packet::InitialLoad));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct PacketCodec<P: ProtocolRW> {
+    engine: ProtocolEngine<P>,
+}
+
+impl<P: ProtocolRW> PacketCodec<P> {
+    /// Creates a new codec.
+    /// `in_keyfile` is the RSA key to decrypt encryption request.
+    /// `out_keyfile` is the RSA key to encrypt encryption request.
+    pub fn new(packet_type: PacketType, in_keyfile: PrivateKey, out_keyfile: PublicKey) -> Self {
+        Self {
+            engine: ProtocolEngine::new(packet_type, in_keyfile, out_keyfile),
+        }
+    }
+
+    /// Creates a new codec from an existing protocol engine.
+    pub fn from_engine(engine: ProtocolEngine<P>) -> Self {
+        Self { engine }
+    }
+
+    /// Returns a reference to the underlying protocol engine.
+    pub fn engine(&self) -> &ProtocolEngine<P> {
+        &self.engine
+    }
+
+    /// Returns a mutable reference to the underlying protocol engine.
+    pub fn engine_mut(&mut self) -> &mut ProtocolEngine<P> {
+        &mut self.engine
+    }
+
+    /// Returns the underlying protocol engine.
+    pub fn into_engine(self) -> ProtocolEngine<P> {
+        self.engine
+    }
+}
+
+impl<P: ProtocolRW> Decoder for PacketCodec<P> {
+    type Item = P;
+    type Error = ConnectionError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // the engine keeps its own buffer, because the stream cipher must be applied only once
+        if !src.is_empty() {
+            self.engine.receive_data(src)?;
+            src.clear();
+        }
+        self.engine.poll_packet()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(packet) => Ok(Some(packet)),
+            None if self.engine.buffered_len() == 0 => Ok(None),
+            None => Err(ConnectionError::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "incomplete packet remaining on stream",
+            ))),
+        }
+    }
+}
+
+impl<P: ProtocolRW, T: ProtocolRW> Encoder<T> for PacketCodec<P> {
+    type Error = ConnectionError;
+
+    fn encode(&mut self, item: T, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        self.engine.send_packet(&item)?;
+        dst.extend_from_slice(&self.engine.take_pending());
+        Ok(())
+    }
+}
@@ -1,67 +1,191 @@
-use crate::encryption::{Decryptor, Encryptor, LengthType};
+use crate::encryption::{Decryptor, EncryptionError, Encryptor, LengthType};
+use std::future::Future;
+#[cfg(not(feature = "tokio"))]
 use std::io::{Read, Write};
 use super::ConnectionError;
+#[cfg(feature = "tokio")]
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Wake, Waker},
+};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 
+/// Read side of the underlying stream.
+///
+/// If `tokio` feature is disabled this trait is implemented for any [`std::io::Read`] type,
+/// otherwise it is implemented for any [`tokio::io::AsyncRead`] type.
 pub trait ConnReadAsync {
-    fn readable_conn(&self) -> impl std::future::Future<Output = std::io::Result<()>> + Send;
+    /// Reads data from the stream, waiting for it if necessary.
+    fn read_conn(&mut self, buf: &mut [u8]) -> impl Future<Output = std::io::Result<usize>> + Send;
+    /// Reads data from the stream. For async streams this function returns
+    /// [`std::io::ErrorKind::WouldBlock`] if no data is available.
     fn try_read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize>;
 }
+
+/// Write side of the underlying stream.
+///
+/// If `tokio` feature is disabled this trait is implemented for any [`std::io::Write`] type,
+/// otherwise it is implemented for any [`tokio::io::AsyncWrite`] type.
 pub trait ConnWriteAsync {
-    fn writable_conn(&self) -> impl std::future::Future<Output = std::io::Result<()>> + Send;
+    /// Writes data to the stream, waiting for it if necessary.
+    fn write_conn(&mut self, buf: &[u8]) -> impl Future<Output = std::io::Result<usize>> + Send;
+    /// Writes data to the stream. For async streams this function returns
+    /// [`std::io::ErrorKind::WouldBlock`] if the stream is not ready.
     fn try_write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize>;
+    /// Flushes the stream, waiting for it if necessary.
+    fn flush_conn(&mut self) -> impl Future<Output = std::io::Result<()>> + Send;
+    /// Flushes the stream. For async streams this function returns
+    /// [`std::io::ErrorKind::WouldBlock`] if the stream is not ready.
+    fn try_flush_conn(&mut self) -> std::io::Result<()>;
 }
 
-impl ConnReadAsync for std::net::TcpStream {
-    async fn readable_conn(&self) -> std::io::Result<()> {
-        unimplemented!()
-    }
+/// Stream that knows the address of the other side.
+pub trait PeerAddress {
+    /// Returns the address of the other side of the stream.
+    fn peer_address(&self) -> std::io::Result<std::net::SocketAddr>;
+}
+
+/// Stream that can be split into independent read and write halves.
+pub trait SplitStream: Sized {
+    /// Read half of the stream.
+    type ReadHalf: ConnReadAsync + Send;
+    /// Write half of the stream.
+    type WriteHalf: ConnWriteAsync + Send;
+    /// Splits the stream.
+    fn split_stream(self) -> std::io::Result<(Self::ReadHalf, Self::WriteHalf)>;
+}
 
+#[cfg(not(feature = "tokio"))]
+impl<T: Read + Send> ConnReadAsync for T {
+    async fn read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read(buf)
+    }
     fn try_read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         self.read(buf)
     }
 }
-impl ConnWriteAsync for std::net::TcpStream {
-    async fn writable_conn(&self) -> std::io::Result<()> {
-        unimplemented!()
+#[cfg(not(feature = "tokio"))]
+impl<T: Write + Send> ConnWriteAsync for T {
+    async fn write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write(buf)
     }
     fn try_write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.write(buf)
     }
+    async fn flush_conn(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
+    fn try_flush_conn(&mut self) -> std::io::Result<()> {
+        self.flush()
+    }
 }
+
+// used to poll async streams exactly once
+#[cfg(feature = "tokio")]
+struct NoopWaker;
+#[cfg(feature = "tokio")]
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+#[cfg(feature = "tokio")]
+fn poll_once<T>(
+    f: impl FnOnce(&mut Context<'_>) -> Poll<std::io::Result<T>>,
+) -> std::io::Result<T> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    match f(&mut cx) {
+        Poll::Ready(r) => r,
+        Poll::Pending => Err(std::io::ErrorKind::WouldBlock.into()),
+    }
+}
+
 #[cfg(feature = "tokio")]
-impl ConnReadAsync for tokio::net::TcpStream {
-    async fn readable_conn(&self) -> std::io::Result<()> {
-        self.readable().await
+impl<T: AsyncRead + Unpin + Send> ConnReadAsync for T {
+    async fn read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.read(buf).await
     }
     fn try_read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.try_read(buf)
+        let mut buf = ReadBuf::new(buf);
+        poll_once(|cx| Pin::new(&mut *self).poll_read(cx, &mut buf))?;
+        Ok(buf.filled().len())
     }
 }
 #[cfg(feature = "tokio")]
-impl ConnWriteAsync for tokio::net::TcpStream {
-    async fn writable_conn(&self) -> std::io::Result<()> {
-        self.writable().await
+impl<T: AsyncWrite + Unpin + Send> ConnWriteAsync for T {
+    async fn write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write(buf).await
     }
     fn try_write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.try_write(buf)
+        poll_once(|cx| Pin::new(&mut *self).poll_write(cx, buf))
+    }
+    async fn flush_conn(&mut self) -> std::io::Result<()> {
+        self.flush().await
+    }
+    fn try_flush_conn(&mut self) -> std::io::Result<()> {
+        poll_once(|cx| Pin::new(&mut *self).poll_flush(cx))
     }
 }
+
+macro_rules! peer_address {
+    ($($name:ty),+) => {
+        $(
+            impl PeerAddress for $name {
+                fn peer_address(&self) -> std::io::Result<std::net::SocketAddr> {
+                    self.peer_addr()
+                }
+            }
+        )+
+    };
+}
+
+peer_address!(std::net::TcpStream);
 #[cfg(feature = "tokio")]
-impl ConnReadAsync for tokio::net::tcp::OwnedReadHalf {
-    async fn readable_conn(&self) -> std::io::Result<()> {
-        self.readable().await
+peer_address!(
+    tokio::net::TcpStream,
+    tokio::net::tcp::OwnedReadHalf,
+    tokio::net::tcp::OwnedWriteHalf
+);
+
+#[cfg(not(feature = "tokio"))]
+impl SplitStream for std::net::TcpStream {
+    type ReadHalf = Self;
+    type WriteHalf = Self;
+    fn split_stream(self) -> std::io::Result<(Self, Self)> {
+        Ok((self.try_clone()?, self))
     }
-    fn try_read_conn(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.try_read(buf)
+}
+#[cfg(all(unix, not(feature = "tokio")))]
+impl SplitStream for std::os::unix::net::UnixStream {
+    type ReadHalf = Self;
+    type WriteHalf = Self;
+    fn split_stream(self) -> std::io::Result<(Self, Self)> {
+        Ok((self.try_clone()?, self))
     }
 }
 #[cfg(feature = "tokio")]
-impl ConnWriteAsync for tokio::net::tcp::OwnedWriteHalf {
-    async fn writable_conn(&self) -> std::io::Result<()> {
-        self.writable().await
+impl SplitStream for tokio::net::TcpStream {
+    type ReadHalf = tokio::net::tcp::OwnedReadHalf;
+    type WriteHalf = tokio::net::tcp::OwnedWriteHalf;
+    fn split_stream(self) -> std::io::Result<(Self::ReadHalf, Self::WriteHalf)> {
+        Ok(self.into_split())
     }
-    fn try_write_conn(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.try_write(buf)
+}
+#[cfg(all(unix, feature = "tokio"))]
+impl SplitStream for tokio::net::UnixStream {
+    type ReadHalf = tokio::net::unix::OwnedReadHalf;
+    type WriteHalf = tokio::net::unix::OwnedWriteHalf;
+    fn split_stream(self) -> std::io::Result<(Self::ReadHalf, Self::WriteHalf)> {
+        Ok(self.into_split())
+    }
+}
+#[cfg(feature = "tokio")]
+impl SplitStream for tokio::io::DuplexStream {
+    type ReadHalf = tokio::io::ReadHalf<Self>;
+    type WriteHalf = tokio::io::WriteHalf<Self>;
+    fn split_stream(self) -> std::io::Result<(Self::ReadHalf, Self::WriteHalf)> {
+        Ok(tokio::io::split(self))
     }
 }
 
@@ -69,6 +193,8 @@ impl ConnWriteAsync for tokio::net::tcp::OwnedWriteHalf {
 pub struct ConnectionReader {
     read_buffer: Vec<u8>,
     packet_length: usize,
+    // amount of bytes in the read buffer that were already passed through a stream cipher
+    decrypted_length: usize,
 }
 
 #[derive(Default, Debug)]
@@ -91,6 +217,11 @@ impl ConnectionReader {
         let mut buf = [0; 4096];
         loop {
             let read_bytes = stream.try_read_conn(&mut buf)?;
+            if read_bytes == 0 {
+                return Err(ConnectionError::Io(
+                    std::io::ErrorKind::ConnectionAborted.into(),
+                ));
+            }
             if let Some(packet) = self.handle_data(dec, &buf[..read_bytes])? {
                 return Ok(packet);
             }
@@ -108,9 +239,8 @@ impl ConnectionReader {
             }
         }
         loop {
-            stream.readable_conn().await?;
             let mut buf = [0; 4096];
-            let read_bytes = match stream.try_read_conn(&mut buf) {
+            let read_bytes = match stream.read_conn(&mut buf).await {
                 Ok(0) => return Err(ConnectionError::Io(std::io::ErrorKind::ConnectionAborted.into())),
                 Ok(n) => n,
                 Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => continue,
@@ -121,26 +251,43 @@ impl ConnectionReader {
             }
         }
     }
+    /// Appends received stream data to the read buffer.
+    pub fn push_data(&mut self, dec: &mut impl Decryptor, buf: &[u8]) -> Result<(), EncryptionError> {
+        self.read_buffer.extend_from_slice(buf);
+        self.decrypt_stream(dec)
+    }
+    // stream data is decrypted only when the cipher is known, so data received alongside the
+    // encryption request is decrypted after the encryption is set up
+    fn decrypt_stream(&mut self, dec: &mut impl Decryptor) -> Result<(), EncryptionError> {
+        if dec.is_rc4() && self.decrypted_length < self.read_buffer.len() {
+            let decrypted_stream = dec.decrypt(&self.read_buffer[self.decrypted_length..])?;
+            self.read_buffer[self.decrypted_length..].copy_from_slice(&decrypted_stream);
+            self.decrypted_length = self.read_buffer.len();
+        }
+        Ok(())
+    }
     fn handle_data(
         &mut self,
         dec: &mut impl Decryptor,
         buf: &[u8],
     ) -> Result<Option<Vec<u8>>, ConnectionError> {
-        if dec.is_rc4() {
-            let mut decrypted_stream = dec.decrypt(buf)?;
-            self.read_buffer.append(&mut decrypted_stream);
-        } else {
-            self.read_buffer.extend_from_slice(buf);
-        }
-        self.get_packet_data(dec)
+        self.push_data(dec, buf)?;
+        Ok(self.get_packet_data(dec)?)
+    }
+    /// Returns the number of received bytes that aren't part of a returned packet yet.
+    pub fn buffered_len(&self) -> usize {
+        self.read_buffer.len()
     }
-    fn get_packet_data(&mut self, dec: &mut impl Decryptor) -> Result<Option<Vec<u8>>, ConnectionError> {
+    /// Returns the next complete packet from the read buffer (if any).
+    pub fn get_packet_data(&mut self, dec: &mut impl Decryptor) -> Result<Option<Vec<u8>>, EncryptionError> {
         let mut output_data = vec![0u8; 0];
+        self.decrypt_stream(dec)?;
         if self.packet_length == 0 {
             self.get_length(dec);
         }
         if self.read_buffer.len() >= self.packet_length && self.packet_length != 0 {
             output_data.extend(self.read_buffer.drain(..self.packet_length));
+            self.decrypted_length = self.decrypted_length.saturating_sub(self.packet_length);
             self.packet_length = 0;
             let output_data = if dec.is_rc4() {
                 output_data
@@ -168,6 +315,19 @@ impl ConnectionReader {
 }
 
 impl ConnectionWriter {
+    /// Returns the pending (encrypted) data.
+    pub fn pending_data(&self) -> &[u8] {
+        &self.write_buffer
+    }
+    /// Removes `amount` bytes from the start of pending data.
+    pub fn consume(&mut self, amount: usize) {
+        let amount = amount.min(self.write_buffer.len());
+        self.write_buffer.drain(..amount);
+    }
+    /// Takes all pending data.
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.write_buffer)
+    }
     pub fn prepare_data(&mut self, data: &[u8], enc: &mut impl Encryptor) -> Result<(), ConnectionError> {
         self.write_buffer.extend_from_slice(&enc.encrypt(data)?);
         Ok(())
@@ -175,9 +335,12 @@ impl ConnectionWriter {
     pub fn flush(&mut self, stream: &mut (impl ConnWriteAsync + Send)) -> std::io::Result<()> {
         while !self.write_buffer.is_empty() {
             let wrote_bytes = stream.try_write_conn(&self.write_buffer)?;
+            if wrote_bytes == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
             self.write_buffer.drain(..wrote_bytes).count();
         }
-        Ok(())
+        stream.try_flush_conn()
     }
     #[cfg(feature = "tokio")]
     pub async fn flush_async(
@@ -185,14 +348,14 @@ impl ConnectionWriter {
         stream: &mut (impl ConnWriteAsync + Send),
     ) -> std::io::Result<()> {
         while !self.write_buffer.is_empty() {
-            stream.writable_conn().await?;
-            let wrote_bytes = match stream.try_write_conn(&self.write_buffer) {
+            let wrote_bytes = match stream.write_conn(&self.write_buffer).await {
+                Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
                 Ok(n) => n,
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => 0,
                 Err(e) => return Err(e),
             };
             self.write_buffer.drain(..wrote_bytes).count();
         }
-        Ok(())
+        stream.flush_conn().await
     }
 }
@@ -0,0 +1,293 @@
+//! Sans-IO protocol state machine.
+
+use super::{
+    conn_impl::{ConnectionReader, ConnectionWriter},
+    ConnectionError, KeyLogEntry, PrivateKey, PublicKey,
+};
+use crate::{
+    encryption::{encrypt, Encryption},
+    protocol::{
+        limits::DecodeLimits, login::EncryptionRequestPacket, Packet, PacketType, ProtocolRW,
+    },
+};
+
+/// Protocol state machine that is not tied to any kind of stream.
+///
+/// Received bytes are pushed in using [`ProtocolEngine::receive_data`] and decrypted packets are
+/// pulled out using [`ProtocolEngine::poll_packet`]. Packets to send are pushed in using
+/// [`ProtocolEngine::send_packet`] and encrypted bytes are pulled out using
+/// [`ProtocolEngine::pending_data`] and [`ProtocolEngine::consume`] (or
+/// [`ProtocolEngine::take_pending`]).
+///
+/// RSA/AES handshake is handled the same way as in [`crate::Connection`]. Client side of the
+/// handshake can be started using [`ProtocolEngine::start_handshake`].
+///
+/// # Example
+///
+/// ```
+/// # use pso2packetlib::connection::ProtocolEngine;
+/// # use pso2packetlib::protocol::{Packet, PacketType};
+/// # use pso2packetlib::{PrivateKey, PublicKey};
+/// # fn main() -> Result<(), pso2packetlib::connection::ConnectionError> {
+/// let mut client = ProtocolEngine::<Packet>::new(PacketType::NGS, PrivateKey::None, PublicKey::None);
+/// let mut server = ProtocolEngine::<Packet>::new(PacketType::NGS, PrivateKey::None, PublicKey::None);
+///
+/// client.send_packet(&Packet::InitialLoad)?;
+/// server.receive_data(&client.take_pending())?;
+/// assert_eq!(server.poll_packet()?, Some(Packet::InitialLoad));
+/// assert_eq!(server.poll_packet()?, None);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ProtocolEngine<P: ProtocolRW> {
+    pub(super) encryption: Encryption,
+    pub(super) read: ConnectionReader,
+    pub(super) write: ConnectionWriter,
+    pub(super) read_packets: Vec<P>,
+    pub(super) in_keyfile: PrivateKey,
+    pub(super) out_keyfile: PublicKey,
+    pub(super) packet_type: PacketType,
+    pub(super) expected_key: Option<Vec<u8>>,
+    pub(super) key_log_entry: Option<KeyLogEntry>,
+    pub(super) decode_limits: Option<DecodeLimits>,
+}
+
+impl<P: ProtocolRW> ProtocolEngine<P> {
+    /// Creates a new protocol engine.
+    /// `in_keyfile` is the RSA key to decrypt encryption request.
+    /// `out_keyfile` is the RSA key to encrypt encryption request.
+    pub fn new(packet_type: PacketType, in_keyfile: PrivateKey, out_keyfile: PublicKey) -> Self {
+        Self {
+            encryption: Encryption::None,
+            read: ConnectionReader::default(),
+            write: ConnectionWriter::default(),
+            read_packets: Vec::new(),
+            in_keyfile,
+            out_keyfile,
+            packet_type,
+            expected_key: None,
+            key_log_entry: None,
+            decode_limits: None,
+        }
+    }
+
+    /// Returns the current packet type.
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    /// Changes connection type.
+    pub fn change_packet_type(&mut self, packet_type: PacketType) {
+        self.packet_type = packet_type;
+    }
+
+    /// Sets the limits of the decoded packet data. `None` (default) disables the limits.
+    pub fn set_decode_limits(&mut self, limits: Option<DecodeLimits>) {
+        self.decode_limits = limits;
+    }
+
+    /// Replaces the current encryption (e.g. to continue a connection using a known key).
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.encryption = encryption;
+    }
+
+    /// Returns the encryption key (for [`Packet::EncryptionResponse`]).
+    pub fn get_key(&self) -> Vec<u8> {
+        self.encryption.get_key()
+    }
+
+    /// Pushes received stream data.
+    pub fn receive_data(&mut self, data: &[u8]) -> Result<(), ConnectionError> {
+        Ok(self.read.push_data(&mut self.encryption, data)?)
+    }
+
+    /// Returns the number of received bytes that don't form a complete packet yet.
+    pub fn buffered_len(&self) -> usize {
+        self.read.buffered_len()
+    }
+
+    /// Returns the next parsed packet or [`None`] if more data is needed.
+    pub fn poll_packet(&mut self) -> Result<Option<P>, ConnectionError> {
+        if let Some(packet) = self.next_buffered_packet() {
+            return Ok(Some(packet));
+        }
+        match self.poll_data()? {
+            Some(data) => self.parse_data(&data).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the next decrypted packet data or [`None`] if more data is needed.
+    ///
+    /// # Note
+    ///
+    /// Returned data must be passed to [`ProtocolEngine::parse_data`] before polling the next
+    /// packet, otherwise the encryption handshake might be missed.
+    pub fn poll_data(&mut self) -> Result<Option<Vec<u8>>, ConnectionError> {
+        Ok(self.read.get_packet_data(&mut self.encryption)?)
+    }
+
+    /// Returns the next packet that was read alongside a previously parsed packet.
+    pub fn next_buffered_packet(&mut self) -> Option<P> {
+        if self.read_packets.is_empty() {
+            return None;
+        }
+        Some(self.read_packets.remove(0))
+    }
+
+    /// Parses decrypted packet data and handles the encryption handshake.
+    pub fn parse_data(&mut self, data: &[u8]) -> Result<P, ConnectionError> {
+        let (packet, handshake) = parse_received(
+            data,
+            self.packet_type,
+            self.decode_limits.as_ref(),
+            &self.in_keyfile,
+            &mut self.expected_key,
+            &mut self.read_packets,
+        )?;
+        if let Some((encryption, entry)) = handshake {
+            self.encryption = encryption;
+            self.key_log_entry = Some(entry);
+        }
+        Ok(packet)
+    }
+
+    /// Starts the client side of the encryption handshake. Generates the encryption secret, sends
+    /// RSA encrypted [`Packet::EncryptionRequest`] using `out_keyfile` and enables the encryption.
+    /// Received [`Packet::EncryptionResponse`] is validated against the sent secret. Returns the
+    /// unencrypted packet data.
+    ///
+    /// NGS and NA use AES encryption, other packet types use RC4 encryption.
+    #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc")))
+    )]
+    pub fn start_handshake(&mut self) -> Result<Vec<u8>, ConnectionError> {
+        let dec_data = Encryption::generate_client_data(self.packet_type)?;
+        let rsa_data = encrypt(&dec_data, &self.out_keyfile)?;
+        self.key_log_entry = Some(KeyLogEntry::new(&rsa_data, &dec_data));
+        let packet = Packet::EncryptionRequest(EncryptionRequestPacket {
+            rsa_data: rsa_data.into(),
+        })
+        .write(self.packet_type);
+        self.write.prepare_data(&packet, &mut Encryption::None)?;
+        self.encryption =
+            Encryption::from_dec_data(&dec_data, matches!(self.packet_type, PacketType::NGS))?;
+        self.expected_key = Some(self.encryption.get_key());
+        Ok(packet)
+    }
+
+    /// Returns the session secret that was negotiated since the last call (e.g. to write it to a
+    /// [`super::KeyLog`]).
+    pub fn take_key_log_entry(&mut self) -> Option<KeyLogEntry> {
+        self.key_log_entry.take()
+    }
+
+    /// Returns `true` if the client handshake was started, but the
+    /// [`Packet::EncryptionResponse`] was not yet received.
+    pub fn is_handshake_pending(&self) -> bool {
+        self.expected_key.is_some()
+    }
+
+    /// Encrypts a packet and appends it to the pending data. Returns the unencrypted packet data.
+    pub fn send_packet(&mut self, packet: &impl ProtocolRW) -> Result<Vec<u8>, ConnectionError> {
+        self.send_packet_replaced(packet).map(|(data, _)| data)
+    }
+
+    /// Same as [`ProtocolEngine::send_packet`], but also returns the encryption request that
+    /// replaced the packet.
+    pub(super) fn send_packet_replaced(
+        &mut self,
+        packet: &impl ProtocolRW,
+    ) -> Result<(Vec<u8>, Option<Packet>), ConnectionError> {
+        match replace_enc_request(packet, &self.out_keyfile, self.packet_type)? {
+            Some((encryption, new_packet, entry)) => {
+                self.encryption = encryption;
+                self.key_log_entry = Some(entry);
+                let data = new_packet.write(self.packet_type);
+                self.write.prepare_data(&data, &mut Encryption::None)?;
+                Ok((data, Some(new_packet)))
+            }
+            None => {
+                let data = packet.write(self.packet_type);
+                self.write.prepare_data(&data, &mut self.encryption)?;
+                Ok((data, None))
+            }
+        }
+    }
+
+    /// Returns the encrypted data that is waiting to be sent.
+    pub fn pending_data(&self) -> &[u8] {
+        self.write.pending_data()
+    }
+
+    /// Marks `amount` bytes of pending data as sent.
+    pub fn consume(&mut self, amount: usize) {
+        self.write.consume(amount)
+    }
+
+    /// Takes all encrypted data that is waiting to be sent.
+    pub fn take_pending(&mut self) -> Vec<u8> {
+        self.write.take_pending()
+    }
+}
+
+/// Parses decrypted packet data, appending all packets except the first one to `read_packets`.
+/// Received encryption response is validated against `expected_key` and received encryption
+/// request is decrypted using `in_keyfile`. Returns the first packet and, if the request was
+/// decrypted, the encryption created from the request secret with the key log entry.
+pub(super) fn parse_received<P: ProtocolRW>(
+    data: &[u8],
+    packet_type: PacketType,
+    limits: Option<&DecodeLimits>,
+    in_keyfile: &PrivateKey,
+    expected_key: &mut Option<Vec<u8>>,
+    read_packets: &mut Vec<P>,
+) -> Result<(P, Option<(Encryption, KeyLogEntry)>), ConnectionError> {
+    let mut packets = super::read_packets(data, packet_type, limits)?;
+    let mut packet = packets.remove(0);
+    read_packets.append(&mut packets);
+    if let Some(key) = packet.as_enc_response() {
+        if let Some(expected_key) = expected_key.take() {
+            if key != expected_key {
+                return Err(ConnectionError::InvalidEncryptionResponse);
+            }
+        }
+    }
+    let mut handshake = None;
+    if let Some(data) = packet.mut_enc_data() {
+        if !matches!(in_keyfile, PrivateKey::None) {
+            let dec_data = Encryption::decrypt_rsa_data(data, in_keyfile)?;
+            let encryption =
+                Encryption::from_dec_data(&dec_data, matches!(packet_type, PacketType::NGS))?;
+            handshake = Some((encryption, KeyLogEntry::new(data, &dec_data)));
+            *data = dec_data;
+        }
+    }
+    Ok((packet, handshake))
+}
+
+/// Replaces the encryption request with the one RSA encrypted using `out_keyfile`. Returns the
+/// encryption created from the request secret, the new request and the key log entry or `None` if
+/// the packet is not replaced.
+pub(super) fn replace_enc_request(
+    packet: &impl ProtocolRW,
+    out_keyfile: &PublicKey,
+    packet_type: PacketType,
+) -> Result<Option<(Encryption, Packet, KeyLogEntry)>, ConnectionError> {
+    if !packet.is_enc_data() || matches!(out_keyfile, PublicKey::None) {
+        return Ok(None);
+    }
+    let rsa_data = packet
+        .as_enc_data()
+        .expect("is_enc_data returned true while as_enc_data returned None");
+    let encryption = Encryption::from_dec_data(rsa_data, matches!(packet_type, PacketType::NGS))?;
+    let enc_data = encrypt(rsa_data, out_keyfile)?;
+    let entry = KeyLogEntry::new(&enc_data, rsa_data);
+    let new_packet = Packet::EncryptionRequest(EncryptionRequestPacket {
+        rsa_data: enc_data.into(),
+    });
+    Ok(Some((encryption, new_packet, entry)))
+}
@@ -0,0 +1,156 @@
+//! Session secret logging.
+
+use crate::hex::{from_hex, to_hex};
+use std::{
+    io::{BufRead, Write},
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const LABEL: &str = "PSO2_SECRET";
+/// Number of bytes of the encrypted request that are used to identify the session.
+const REQUEST_ID_LEN: usize = 32;
+
+/// Negotiated session secret.
+///
+/// Each entry is stored as a single line in the form of
+/// `PSO2_SECRET <request id> <secret> <unix time in ns> <peer address or ->`, where the request
+/// id is the start of the RSA encrypted [`crate::protocol::Packet::EncryptionRequest`] data and
+/// the secret is the decrypted data. Binary values are hex encoded. Lines starting with `#` are
+/// comments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyLogEntry {
+    /// Start of the RSA encrypted request data.
+    pub request_id: Vec<u8>,
+    /// Decrypted request data.
+    pub secret: Vec<u8>,
+    /// When the secret was negotiated.
+    pub time: Duration,
+    /// Address of the other side of the connection (if known).
+    pub peer: Option<SocketAddr>,
+}
+
+/// Shared sink for session secrets. Similar to `SSLKEYLOGFILE`.
+///
+/// Clones of this sink write to the same underlying writer, so one key log can be shared between
+/// connections.
+#[derive(Clone)]
+pub struct KeyLog {
+    writer: Arc<Mutex<dyn Write + Send>>,
+}
+
+impl KeyLogEntry {
+    /// Creates a new entry from the RSA encrypted request data and the decrypted secret.
+    pub fn new(rsa_data: &[u8], secret: &[u8]) -> Self {
+        Self {
+            request_id: Self::request_id(rsa_data).to_vec(),
+            secret: secret.to_vec(),
+            time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default(),
+            peer: None,
+        }
+    }
+
+    /// Returns `true` if the entry belongs to the RSA encrypted request data.
+    pub fn matches(&self, rsa_data: &[u8]) -> bool {
+        !self.request_id.is_empty() && self.request_id == Self::request_id(rsa_data)
+    }
+
+    fn request_id(rsa_data: &[u8]) -> &[u8] {
+        &rsa_data[..rsa_data.len().min(REQUEST_ID_LEN)]
+    }
+}
+
+impl std::fmt::Display for KeyLogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{LABEL} {} {} {} ",
+            to_hex(&self.request_id),
+            to_hex(&self.secret),
+            self.time.as_nanos()
+        )?;
+        match self.peer {
+            Some(peer) => write!(f, "{peer}"),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+impl FromStr for KeyLogEntry {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            || std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid key log line");
+        let mut parts = s.split_whitespace();
+        if parts.next() != Some(LABEL) {
+            return Err(invalid());
+        }
+        let request_id = from_hex(parts.next().ok_or_else(invalid)?).ok_or_else(invalid)?;
+        let secret = from_hex(parts.next().ok_or_else(invalid)?).ok_or_else(invalid)?;
+        let time = match parts.next() {
+            Some(time) => time.parse::<u128>().map_err(|_| invalid())?,
+            None => 0,
+        };
+        let time = Duration::new((time / 1_000_000_000) as u64, (time % 1_000_000_000) as u32);
+        let peer = match parts.next() {
+            Some("-") | None => None,
+            Some(peer) => Some(peer.parse().map_err(|_| invalid())?),
+        };
+        Ok(Self {
+            request_id,
+            secret,
+            time,
+            peer,
+        })
+    }
+}
+
+impl KeyLog {
+    /// Creates a new key log that writes to the provided writer.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Opens a key log file. New entries are appended to the end of the file.
+    pub fn create<PT: AsRef<std::path::Path>>(path: PT) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Writes an entry.
+    pub fn log(&self, entry: &KeyLogEntry) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{entry}")?;
+        writer.flush()
+    }
+}
+
+impl std::fmt::Debug for KeyLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyLog").finish_non_exhaustive()
+    }
+}
+
+/// Reads all entries from a key log. Empty lines and comments are skipped.
+pub fn read_key_log(reader: impl BufRead) -> std::io::Result<Vec<KeyLogEntry>> {
+    let mut entries = vec![];
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        entries.push(line.parse()?);
+    }
+    Ok(entries)
+}
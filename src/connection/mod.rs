@@ -2,29 +2,61 @@
 
 pub use crate::encryption::EncryptionError;
 
+#[cfg(feature = "codec")]
+mod codec;
 pub(crate) mod conn_impl;
+mod engine;
+mod keylog;
+mod sink;
 #[cfg(feature = "split_connection")]
 use crate::encryption::{DecryptorType, EncryptorType};
 #[cfg(feature = "ppac")]
-use crate::ppac::{Direction, PPACWriter};
-use crate::{
-    encryption::{encrypt, Encryption},
-    protocol::{login::EncryptionRequestPacket, Packet, PacketType, ProtocolRW},
-};
+use crate::ppac::FileWriter;
+use crate::protocol::{limits::DecodeLimits, Direction, PacketError, PacketType, ProtocolRW};
+#[cfg(feature = "codec")]
+#[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
+pub use codec::PacketCodec;
+pub use conn_impl::{ConnReadAsync, ConnWriteAsync, PeerAddress, SplitStream};
+#[cfg(feature = "split_connection")]
 use conn_impl::{ConnectionReader, ConnectionWriter};
+pub use engine::ProtocolEngine;
+pub use keylog::{read_key_log, KeyLog, KeyLogEntry};
 use rsa::{
     pkcs8::{DecodePrivateKey, DecodePublicKey},
     BigUint, RsaPrivateKey, RsaPublicKey,
 };
+#[cfg(feature = "split_connection")]
+use sink::DispatchSink;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use sink::JsonSink;
+use sink::{written_packet, Sinks};
+pub use sink::{CapturedPacket, ChannelSink, PacketSink, RingBuffer};
 #[cfg(all(feature = "split_connection", not(feature = "tokio")))]
 use std::sync::mpsc::{Receiver, Sender};
-#[cfg(all(feature = "split_connection", feature = "ppac"))]
+#[cfg(feature = "split_connection")]
 use std::sync::{Arc, Mutex};
 #[cfg(all(feature = "split_connection", feature = "tokio"))]
-use tokio::{
-    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
-    sync::mpsc::{UnboundedReceiver as Receiver, UnboundedSender as Sender},
-};
+use tokio::sync::mpsc::{UnboundedReceiver as Receiver, UnboundedSender as Sender};
+
+/// Default stream used by [`Connection`].
+#[cfg(not(feature = "tokio"))]
+pub type DefaultStream = std::net::TcpStream;
+/// Default stream used by [`Connection`].
+#[cfg(feature = "tokio")]
+pub type DefaultStream = tokio::net::TcpStream;
+/// Default stream used by [`ConnectionRead`].
+#[cfg(all(feature = "split_connection", not(feature = "tokio")))]
+pub type DefaultReadHalf = std::net::TcpStream;
+/// Default stream used by [`ConnectionRead`].
+#[cfg(all(feature = "split_connection", feature = "tokio"))]
+pub type DefaultReadHalf = tokio::net::tcp::OwnedReadHalf;
+/// Default stream used by [`ConnectionWrite`].
+#[cfg(all(feature = "split_connection", not(feature = "tokio")))]
+pub type DefaultWriteHalf = std::net::TcpStream;
+/// Default stream used by [`ConnectionWrite`].
+#[cfg(all(feature = "split_connection", feature = "tokio"))]
+pub type DefaultWriteHalf = tokio::net::tcp::OwnedWriteHalf;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConnectionError {
@@ -34,29 +66,26 @@ pub enum ConnectionError {
     EncryptionError(#[from] crate::encryption::EncryptionError),
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    #[error("encryption response doesn't match the sent secret")]
+    InvalidEncryptionResponse,
     #[cfg(feature = "ppac")]
     #[error("error occured while storing a packet: {0}")]
     PPACError(#[from] crate::ppac::PPACError),
 }
 
 /// Represents a connection between a client and a server.
+///
+/// `S` is the underlying stream. If `tokio` feature is disabled it can be any
+/// [`std::io::Read`] + [`std::io::Write`] type, otherwise it can be any
+/// [`tokio::io::AsyncRead`] + [`tokio::io::AsyncWrite`] type.
 #[derive(Debug)]
-pub struct Connection<P: ProtocolRW + Send> {
-    // this is probably not the best way to do this
-    #[cfg(not(feature = "tokio"))]
-    stream: std::net::TcpStream,
-    #[cfg(feature = "tokio")]
-    stream: tokio::net::TcpStream,
-    encryption: Encryption,
-    read: ConnectionReader,
-    write: ConnectionWriter,
-    read_packets: Vec<P>,
-    in_keyfile: PrivateKey,
-    out_keyfile: PublicKey,
-    packet_type: PacketType,
-    #[cfg(feature = "ppac")]
-    ppac: Option<PPACWriter<std::fs::File>>,
+pub struct Connection<P: ProtocolRW + Send, S = DefaultStream> {
+    stream: S,
+    engine: ProtocolEngine<P>,
+    key_log: Option<(KeyLog, Option<std::net::SocketAddr>)>,
+    sinks: Sinks<P>,
     #[cfg(feature = "ppac")]
+    ppac: Option<FileWriter>,
     direction: Direction,
 }
 
@@ -130,16 +159,11 @@ impl<P: ProtocolRW + Send> Connection<P> {
         };
         Self {
             stream,
-            encryption: Encryption::None,
-            read: ConnectionReader::default(),
-            write: ConnectionWriter::default(),
-            read_packets: Vec::new(),
-            in_keyfile,
-            out_keyfile,
-            packet_type,
+            engine: ProtocolEngine::new(packet_type, in_keyfile, out_keyfile),
+            key_log: None,
+            sinks: Sinks::default(),
             #[cfg(feature = "ppac")]
             ppac: None,
-            #[cfg(feature = "ppac")]
             direction: Direction::ToServer,
         }
     }
@@ -157,51 +181,118 @@ impl<P: ProtocolRW + Send> Connection<P> {
     ) -> Self {
         Self {
             stream,
-            encryption: Encryption::None,
-            read: ConnectionReader::default(),
-            write: ConnectionWriter::default(),
-            read_packets: Vec::new(),
-            in_keyfile,
-            out_keyfile,
-            packet_type,
+            engine: ProtocolEngine::new(packet_type, in_keyfile, out_keyfile),
+            key_log: None,
+            sinks: Sinks::default(),
             #[cfg(feature = "ppac")]
             ppac: None,
-            #[cfg(feature = "ppac")]
             direction: Direction::ToServer,
         }
     }
+}
 
+impl<P: ProtocolRW + Send, S: PeerAddress> Connection<P, S> {
     /// Returns the ip address of the client.
     pub fn get_ip(&self) -> std::io::Result<std::net::Ipv4Addr> {
-        let ip = self.stream.peer_addr()?.ip();
-        let ip = match ip {
-            std::net::IpAddr::V4(x) => x,
-            std::net::IpAddr::V6(_) => std::net::Ipv4Addr::UNSPECIFIED,
-        };
-        Ok(ip)
+        get_ip(&self.stream)
+    }
+
+    /// Returns the address of the other side of the connection.
+    pub fn get_peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.stream.peer_address()
+    }
+
+    /// Sets a key log that receives negotiated session secrets. Entries are tagged with the
+    /// address of the other side of the connection.
+    pub fn set_key_log(&mut self, key_log: KeyLog) {
+        let peer = self.stream.peer_address().ok();
+        self.key_log = Some((key_log, peer));
+    }
+}
+
+impl<P: ProtocolRW + Send, S: ConnReadAsync + ConnWriteAsync + Send> Connection<P, S> {
+    /// Creates a new connection from any supported stream.
+    /// `in_keyfile` is the RSA key to decrypt encryption request.
+    /// `out_keyfile` is the RSA key to encrypt encryption request.
+    pub fn from_stream(
+        stream: S,
+        packet_type: PacketType,
+        in_keyfile: PrivateKey,
+        out_keyfile: PublicKey,
+    ) -> Self {
+        Self {
+            stream,
+            engine: ProtocolEngine::new(packet_type, in_keyfile, out_keyfile),
+            key_log: None,
+            sinks: Sinks::default(),
+            #[cfg(feature = "ppac")]
+            ppac: None,
+            direction: Direction::ToServer,
+        }
+    }
+
+    /// Returns the current connection type.
+    pub fn packet_type(&self) -> PacketType {
+        self.engine.packet_type()
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Sets a key log that receives negotiated session secrets. Entries are tagged with the
+    /// provided address.
+    pub fn set_key_log_with_peer(&mut self, key_log: KeyLog, peer: Option<std::net::SocketAddr>) {
+        self.key_log = Some((key_log, peer));
+    }
+
+    /// Adds a sink that receives every packet read or written by the connection.
+    ///
+    /// Sinks are called in the order they were added. Errors returned by sinks are returned by the
+    /// read or write function.
+    pub fn add_sink(&mut self, sink: impl PacketSink<P> + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Sets the direction of the `write` side of the connection (used by packet sinks and storage
+    /// files). Default is [`Direction::ToServer`].
+    pub fn set_direction(&mut self, direction: Direction) {
+        self.direction = direction;
     }
 
     /// Changes connection type.
     pub fn change_packet_type(&mut self, packet_type: PacketType) {
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
+        if let Some(writer) = &self.ppac {
             let _ = writer.change_packet_type(packet_type);
         }
-        self.packet_type = packet_type;
+        self.engine.change_packet_type(packet_type);
+    }
+
+    /// Sets the limits of the data decoded from the peer. `None` (default) disables the limits.
+    pub fn set_decode_limits(&mut self, limits: Option<DecodeLimits>) {
+        self.engine.set_decode_limits(limits);
     }
 
     /// Splits the connection into separate read and write components.
     #[cfg(feature = "split_connection")]
     #[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
-    pub fn into_split(self) -> std::io::Result<(ConnectionRead<P>, ConnectionWrite)> {
-        #[cfg(feature = "tokio")]
-        let (read, write) = self.stream.into_split();
-        #[cfg(not(feature = "tokio"))]
-        let (read, write) = (self.stream.try_clone()?, self.stream);
+    #[allow(clippy::type_complexity)]
+    pub fn into_split(
+        self,
+    ) -> std::io::Result<(
+        ConnectionRead<P, S::ReadHalf>,
+        ConnectionWrite<S::WriteHalf>,
+    )>
+    where
+        S: SplitStream,
+        P: 'static,
+    {
+        let (read, write) = self.stream.split_stream()?;
+        let sinks = (!self.sinks.is_empty()).then(|| Arc::new(Mutex::new(self.sinks)));
         #[cfg(feature = "ppac")]
-        let ppac = self
-            .ppac
-            .map(|p| std::sync::Arc::new(std::sync::Mutex::new(p)));
+        let ppac = self.ppac;
         #[cfg(feature = "tokio")]
         let ((reader_send, writer_recv), (writer_send, reader_recv)) = (
             tokio::sync::mpsc::unbounded_channel(),
@@ -218,32 +309,48 @@ impl<P: ProtocolRW + Send> Connection<P> {
         #[cfg(not(feature = "tokio"))]
         let ((readpt_send, writept_recv), (writept_send, readpt_recv)) =
             (std::sync::mpsc::channel(), std::sync::mpsc::channel());
-        let (enc, dec) = self.encryption.into_split();
+        let ProtocolEngine {
+            encryption,
+            read: conn_read,
+            write: conn_write,
+            read_packets,
+            in_keyfile,
+            out_keyfile,
+            packet_type,
+            expected_key,
+            key_log_entry: _,
+            decode_limits,
+        } = self.engine;
+        let (enc, dec) = encryption.into_split();
         let reader = ConnectionRead {
             stream: read,
             enc_channel: (reader_send, reader_recv),
             packettype_channel: (readpt_send, readpt_recv),
             encryption: dec,
-            read: self.read,
-            read_packets: self.read_packets,
-            in_keyfile: self.in_keyfile.clone(),
-            packet_type: self.packet_type,
+            read: conn_read,
+            read_packets,
+            in_keyfile,
+            packet_type,
+            decode_limits,
+            expected_key,
+            key_log: self.key_log.clone(),
+            sinks: sinks.clone(),
             #[cfg(feature = "ppac")]
             ppac: ppac.clone(),
-            #[cfg(feature = "ppac")]
             direction: self.direction,
         };
         let writer = ConnectionWrite {
             stream: write,
             enc_channel: (writer_send, writer_recv),
             packettype_channel: (writept_send, writept_recv),
-            write: self.write,
+            write: conn_write,
             encryption: enc,
-            out_keyfile: self.out_keyfile,
-            packet_type: self.packet_type,
+            out_keyfile,
+            packet_type,
+            key_log: self.key_log,
+            sinks: sinks.map(|sinks| sinks as Arc<Mutex<dyn DispatchSink>>),
             #[cfg(feature = "ppac")]
             ppac,
-            #[cfg(feature = "ppac")]
             direction: self.direction,
         };
         Ok((reader, writer))
@@ -255,63 +362,64 @@ impl<P: ProtocolRW + Send> Connection<P> {
     ///
     /// If `tokio` feature is enabled this function becomes nonblocking
     pub fn read_packet(&mut self) -> Result<P, ConnectionError> {
-        if !self.read_packets.is_empty() {
-            return Ok(self.read_packets.remove(0));
+        if let Some(packet) = self.engine.next_buffered_packet() {
+            return Ok(packet);
         }
         let data = self
+            .engine
             .read
-            .try_read_data(&mut self.stream, &mut self.encryption)?;
+            .try_read_data(&mut self.stream, &mut self.engine.encryption)?;
+        let direction = match self.direction {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
-            let direction = match self.direction {
-                Direction::ToServer => Direction::ToClient,
-                Direction::ToClient => Direction::ToServer,
-            };
+        if let Some(writer) = &self.ppac {
             writer.write_data(crate::ppac::get_now(), direction, &data)?;
         }
-        self.parse_packet(&data)
+        let packet = self.engine.parse_data(&data);
+        let packets: Vec<_> = packet.iter().chain(&self.engine.read_packets).collect();
+        self.sinks.dispatch(direction, &data, &packets)?;
+        let packet = packet?;
+        self.log_key()?;
+        Ok(packet)
     }
 
     /// Reads a packet from the stream.
     #[cfg(feature = "tokio")]
     #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
     pub async fn read_packet_async(&mut self) -> Result<P, ConnectionError> {
-        if !self.read_packets.is_empty() {
-            return Ok(self.read_packets.remove(0));
+        if let Some(packet) = self.engine.next_buffered_packet() {
+            return Ok(packet);
         }
         let data = self
+            .engine
             .read
-            .read_data_async(&mut self.stream, &mut self.encryption)
+            .read_data_async(&mut self.stream, &mut self.engine.encryption)
             .await?;
+        let direction = match self.direction {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
-            let direction = match self.direction {
-                Direction::ToServer => Direction::ToClient,
-                Direction::ToClient => Direction::ToServer,
-            };
+        if let Some(writer) = &self.ppac {
             writer.write_data(crate::ppac::get_now(), direction, &data)?;
         }
-        self.parse_packet(&data)
-    }
-    fn parse_packet(&mut self, data: &[u8]) -> Result<P, ConnectionError> {
-        let mut packets = P::read(data, self.packet_type)?;
-        let mut packet = packets.remove(0);
-        self.read_packets.append(&mut packets);
-        if let Some(data) = packet.mut_enc_data() {
-            if !matches!(&self.in_keyfile, PrivateKey::None) {
-                let dec_data = Encryption::decrypt_rsa_data(data, &self.in_keyfile)?;
-                self.encryption = Encryption::from_dec_data(
-                    &dec_data,
-                    matches!(self.packet_type, PacketType::NGS),
-                )?;
-                *data = dec_data;
-            }
-        }
+        let packet = self.engine.parse_data(&data);
+        let packets: Vec<_> = packet.iter().chain(&self.engine.read_packets).collect();
+        self.sinks.dispatch(direction, &data, &packets)?;
+        let packet = packet?;
+        self.log_key()?;
         Ok(packet)
     }
 
     /// Creates a packet storage file. `direction` is the direction of the `write` side of the
     /// connection.
+    ///
+    /// # Note
+    ///
+    /// If `tokio` feature is enabled packets are written on a separate thread, so the async runtime
+    /// isn't blocked by the file IO.
     #[cfg(feature = "ppac")]
     #[cfg_attr(docsrs, doc(cfg(feature = "ppac")))]
     pub fn create_ppac<PT: AsRef<std::path::Path>>(
@@ -319,15 +427,65 @@ impl<P: ProtocolRW + Send> Connection<P> {
         path: PT,
         direction: Direction,
     ) -> Result<(), ConnectionError> {
-        self.ppac = Some(PPACWriter::new(
-            std::fs::File::create(path)?,
-            self.packet_type,
-            true,
-        )?);
+        self.ppac = Some(crate::ppac::create_file(path, self.engine.packet_type())?);
         self.direction = direction;
         Ok(())
     }
 
+    /// Starts the client side of the encryption handshake.
+    /// `out_keyfile` is used to encrypt the generated encryption request.
+    ///
+    /// Encryption is enabled right after sending the request. Received
+    /// [`crate::protocol::Packet::EncryptionResponse`] is validated against the sent secret.
+    ///
+    /// # Note
+    ///
+    /// If `tokio` feature is enabled this function becomes nonblocking
+    #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc")))
+    )]
+    pub fn start_handshake(&mut self) -> Result<(), ConnectionError> {
+        self.prepare_handshake()?;
+        self.engine.write.flush(&mut self.stream)?;
+        Ok(())
+    }
+
+    /// Starts the client side of the encryption handshake.
+    /// `out_keyfile` is used to encrypt the generated encryption request.
+    ///
+    /// Encryption is enabled right after sending the request. Received
+    /// [`crate::protocol::Packet::EncryptionResponse`] is validated against the sent secret.
+    #[cfg(all(
+        feature = "tokio",
+        any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc")
+    ))]
+    #[cfg_attr(
+        docsrs,
+        doc(cfg(all(
+            feature = "tokio",
+            any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc")
+        )))
+    )]
+    pub async fn start_handshake_async(&mut self) -> Result<(), ConnectionError> {
+        self.prepare_handshake()?;
+        self.engine.write.flush_async(&mut self.stream).await?;
+        Ok(())
+    }
+
+    #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
+    fn prepare_handshake(&mut self) -> Result<(), ConnectionError> {
+        let data = self.engine.start_handshake()?;
+        self.log_key()?;
+        #[cfg(feature = "ppac")]
+        if let Some(writer) = &self.ppac {
+            writer.write_data(crate::ppac::get_now(), self.direction, &data)?;
+        }
+        self.sinks.dispatch(self.direction, &data, &[])?;
+        Ok(())
+    }
+
     /// Sends a packet.
     ///
     /// # Note
@@ -335,7 +493,7 @@ impl<P: ProtocolRW + Send> Connection<P> {
     /// If `tokio` feature is enabled this function becomes nonblocking
     pub fn write_packet(&mut self, packet: &impl ProtocolRW) -> Result<(), ConnectionError> {
         self.prepare_data(packet)?;
-        self.write.flush(&mut self.stream)?;
+        self.engine.write.flush(&mut self.stream)?;
         Ok(())
     }
 
@@ -347,48 +505,46 @@ impl<P: ProtocolRW + Send> Connection<P> {
         packet: &(impl ProtocolRW + Sync),
     ) -> Result<(), ConnectionError> {
         self.prepare_data(packet)?;
-        self.write.flush_async(&mut self.stream).await?;
+        self.engine.write.flush_async(&mut self.stream).await?;
         Ok(())
     }
 
-    fn prepare_data(&mut self, packet: &impl ProtocolRW) -> Result<(), ConnectionError> {
-        let _packet = if packet.is_enc_data() && !matches!(&self.out_keyfile, PublicKey::None) {
-            let rsa_data = packet
-                .as_enc_data()
-                .expect("is_enc_data returned true while as_enc_data returned None");
-            let mut new_packet = EncryptionRequestPacket::default();
-            let enc =
-                Encryption::from_dec_data(rsa_data, matches!(self.packet_type, PacketType::NGS))?;
-            self.encryption = enc;
-            new_packet.rsa_data = encrypt(rsa_data, &self.out_keyfile)?.into();
-            let packet = Packet::EncryptionRequest(new_packet).write(self.packet_type);
-            self.write.prepare_data(&packet, &mut Encryption::None)?;
-            packet
-        } else {
-            let packet = packet.write(self.packet_type);
-            self.write.prepare_data(&packet, &mut self.encryption)?;
-            packet
-        };
+    pub(crate) fn prepare_data(&mut self, packet: &impl ProtocolRW) -> Result<(), ConnectionError> {
+        let (data, request) = self.engine.send_packet_replaced(packet)?;
+        self.log_key()?;
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
-            writer.write_data(crate::ppac::get_now(), self.direction, &_packet)?;
+        if let Some(writer) = &self.ppac {
+            writer.write_data(crate::ppac::get_now(), self.direction, &data)?;
+        }
+        let packet = written_packet(packet, request.as_ref());
+        self.sinks.dispatch_written(self.direction, &data, packet)?;
+        Ok(())
+    }
+
+    fn log_key(&mut self) -> std::io::Result<()> {
+        let Some(mut entry) = self.engine.take_key_log_entry() else {
+            return Ok(());
+        };
+        if let Some((key_log, peer)) = &self.key_log {
+            entry.peer = *peer;
+            key_log.log(&entry)?;
         }
         Ok(())
     }
 
     /// Returns the encryption key (for [`Packet::EncryptionResponse`]).
     pub fn get_key(&mut self) -> Vec<u8> {
-        self.encryption.get_key()
+        self.engine.get_key()
     }
     /// Writes all pending packets.
     pub fn flush(&mut self) -> std::io::Result<()> {
-        self.write.flush(&mut self.stream)
+        self.engine.write.flush(&mut self.stream)
     }
     /// Writes all pending packets.
     #[cfg(feature = "tokio")]
     #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
     pub async fn flush_async(&mut self) -> std::io::Result<()> {
-        self.write.flush_async(&mut self.stream).await
+        self.engine.write.flush_async(&mut self.stream).await
     }
 }
 
@@ -396,11 +552,8 @@ impl<P: ProtocolRW + Send> Connection<P> {
 #[cfg(feature = "split_connection")]
 #[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
 #[derive(Debug)]
-pub struct ConnectionRead<P: ProtocolRW + Send> {
-    #[cfg(not(feature = "tokio"))]
-    stream: std::net::TcpStream,
-    #[cfg(feature = "tokio")]
-    stream: OwnedReadHalf,
+pub struct ConnectionRead<P: ProtocolRW + Send, R = DefaultReadHalf> {
+    stream: R,
     enc_channel: (Sender<EncryptorType>, Receiver<DecryptorType>),
     packettype_channel: (Sender<PacketType>, Receiver<PacketType>),
     read: ConnectionReader,
@@ -408,9 +561,12 @@ pub struct ConnectionRead<P: ProtocolRW + Send> {
     read_packets: Vec<P>,
     in_keyfile: PrivateKey,
     packet_type: PacketType,
+    decode_limits: Option<DecodeLimits>,
+    expected_key: Option<Vec<u8>>,
+    key_log: Option<(KeyLog, Option<std::net::SocketAddr>)>,
+    sinks: Option<Arc<Mutex<Sinks<P>>>>,
     #[cfg(feature = "ppac")]
-    ppac: Option<Arc<Mutex<PPACWriter<std::fs::File>>>>,
-    #[cfg(feature = "ppac")]
+    ppac: Option<FileWriter>,
     direction: Direction,
 }
 
@@ -418,53 +574,63 @@ pub struct ConnectionRead<P: ProtocolRW + Send> {
 #[cfg(feature = "split_connection")]
 #[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
 #[derive(Debug)]
-pub struct ConnectionWrite {
-    #[cfg(not(feature = "tokio"))]
-    stream: std::net::TcpStream,
-    #[cfg(feature = "tokio")]
-    stream: OwnedWriteHalf,
+pub struct ConnectionWrite<W = DefaultWriteHalf> {
+    stream: W,
     enc_channel: (Sender<DecryptorType>, Receiver<EncryptorType>),
     packettype_channel: (Sender<PacketType>, Receiver<PacketType>),
     write: ConnectionWriter,
     encryption: EncryptorType,
     out_keyfile: PublicKey,
     packet_type: PacketType,
+    key_log: Option<(KeyLog, Option<std::net::SocketAddr>)>,
+    sinks: Option<Arc<Mutex<dyn DispatchSink>>>,
     #[cfg(feature = "ppac")]
-    ppac: Option<Arc<Mutex<PPACWriter<std::fs::File>>>>,
-    #[cfg(feature = "ppac")]
+    ppac: Option<FileWriter>,
     direction: Direction,
 }
 
 #[cfg(feature = "split_connection")]
 #[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
 impl<P: ProtocolRW + Send> ConnectionRead<P> {
+    /// Same as [`std::net::TcpStream::set_nonblocking`]. Does nothing if `tokio` feature is
+    /// enabled.
+    pub fn set_nonblocking(&self, _nonblocking: bool) -> std::io::Result<()> {
+        #[cfg(not(feature = "tokio"))]
+        self.stream.set_nonblocking(_nonblocking)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "split_connection")]
+#[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
+impl<P: ProtocolRW + Send, R: PeerAddress> ConnectionRead<P, R> {
     /// Returns the ip address of the client.
     pub fn get_ip(&self) -> std::io::Result<std::net::Ipv4Addr> {
-        let ip = self.stream.peer_addr()?.ip();
-        let ip = match ip {
-            std::net::IpAddr::V4(x) => x,
-            std::net::IpAddr::V6(_) => std::net::Ipv4Addr::UNSPECIFIED,
-        };
-        Ok(ip)
+        get_ip(&self.stream)
+    }
+
+    /// Returns the address of the other side of the connection.
+    pub fn get_peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.stream.peer_address()
     }
+}
 
+#[cfg(feature = "split_connection")]
+#[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
+impl<P: ProtocolRW + Send, R: ConnReadAsync + Send> ConnectionRead<P, R> {
     /// Changes connection type. Automatically changes the other side.
     pub fn change_packet_type(&mut self, packet_type: PacketType) {
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
-            let mut lock = writer.lock().unwrap();
-            let _ = lock.change_packet_type(packet_type);
+        if let Some(writer) = &self.ppac {
+            let _ = writer.change_packet_type(packet_type);
         }
         self.packet_type = packet_type;
         let _ = self.packettype_channel.0.send(packet_type);
     }
 
-    /// Same as [`std::net::TcpStream::set_nonblocking`]. Does nothing if `tokio` feature is
-    /// enabled.
-    pub fn set_nonblocking(&self, _nonblocking: bool) -> std::io::Result<()> {
-        #[cfg(not(feature = "tokio"))]
-        self.stream.set_nonblocking(_nonblocking)?;
-        Ok(())
+    /// Sets the limits of the data decoded from the peer. `None` (default) disables the limits.
+    pub fn set_decode_limits(&mut self, limits: Option<DecodeLimits>) {
+        self.decode_limits = limits;
     }
 
     /// Inserts a packet storage file. `direction` is the direction of the `write` side of the
@@ -473,14 +639,27 @@ impl<P: ProtocolRW + Send> ConnectionRead<P> {
     #[cfg_attr(docsrs, doc(cfg(feature = "ppac")))]
     pub fn set_ppac(
         &mut self,
-        ppac: Arc<Mutex<PPACWriter<std::fs::File>>>,
+        ppac: Arc<Mutex<crate::ppac::PPACWriter<std::fs::File>>>,
         direction: Direction,
     ) -> std::io::Result<()> {
-        self.ppac = Some(ppac);
+        self.ppac = Some(FileWriter::Blocking(ppac));
         self.direction = direction;
         Ok(())
     }
 
+    /// Inserts a packet storage file that is written on a separate thread. `direction` is the
+    /// direction of the `write` side of the connection.
+    #[cfg(all(feature = "ppac", feature = "tokio"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "ppac", feature = "tokio"))))]
+    pub fn set_background_ppac(
+        &mut self,
+        ppac: Arc<Mutex<crate::ppac::BackgroundPPACWriter<std::fs::File>>>,
+        direction: Direction,
+    ) {
+        self.ppac = Some(FileWriter::Background(ppac));
+        self.direction = direction;
+    }
+
     /// Reads a packet from stream.
     ///
     /// # Note
@@ -501,16 +680,21 @@ impl<P: ProtocolRW + Send> ConnectionRead<P> {
         if let Ok(packet_type) = self.packettype_channel.1.try_recv() {
             self.packet_type = packet_type
         }
+        let direction = match self.direction {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
         #[cfg(feature = "ppac")]
         if let Some(writer) = &self.ppac {
-            let direction = match self.direction {
-                Direction::ToServer => Direction::ToClient,
-                Direction::ToClient => Direction::ToServer,
-            };
-            let mut lock = writer.lock().unwrap();
-            lock.write_data(crate::ppac::get_now(), direction, &data)?;
+            writer.write_data(crate::ppac::get_now(), direction, &data)?;
+        }
+        let packet = self.parse_packet(&data);
+        if let Some(sinks) = &self.sinks {
+            let packets: Vec<_> = packet.iter().chain(&self.read_packets).collect();
+            let mut lock = sinks.lock().unwrap();
+            lock.dispatch(direction, &data, &packets)?;
         }
-        self.parse_packet(&data)
+        packet
     }
     /// Reads a packet from stream.
     #[cfg(feature = "tokio")]
@@ -538,33 +722,39 @@ impl<P: ProtocolRW + Send> ConnectionRead<P> {
                 }
             }
         };
+        let direction = match self.direction {
+            Direction::ToServer => Direction::ToClient,
+            Direction::ToClient => Direction::ToServer,
+        };
         #[cfg(feature = "ppac")]
         if let Some(writer) = &self.ppac {
-            let direction = match self.direction {
-                Direction::ToServer => Direction::ToClient,
-                Direction::ToClient => Direction::ToServer,
-            };
-            let mut lock = writer.lock().unwrap();
-            lock.write_data(crate::ppac::get_now(), direction, &data)?;
+            writer.write_data(crate::ppac::get_now(), direction, &data)?;
         }
-        self.parse_packet(&data)
+        let packet = self.parse_packet(&data);
+        if let Some(sinks) = &self.sinks {
+            let packets: Vec<_> = packet.iter().chain(&self.read_packets).collect();
+            let mut lock = sinks.lock().unwrap();
+            lock.dispatch(direction, &data, &packets)?;
+        }
+        packet
     }
     fn parse_packet(&mut self, data: &[u8]) -> Result<P, ConnectionError> {
-        let mut packets = P::read(data, self.packet_type)?;
-        let mut packet = packets.remove(0);
-        self.read_packets.append(&mut packets);
-        if let Some(data) = packet.mut_enc_data() {
-            if !matches!(&self.in_keyfile, PrivateKey::None) {
-                let dec_data = Encryption::decrypt_rsa_data(data, &self.in_keyfile)?;
-                let (enc, dec) = Encryption::from_dec_data(
-                    &dec_data,
-                    matches!(self.packet_type, PacketType::NGS),
-                )?
-                .into_split();
-                *data = dec_data;
-                let _ = self.enc_channel.0.send(enc);
-                self.encryption = dec;
+        let (packet, handshake) = engine::parse_received(
+            data,
+            self.packet_type,
+            self.decode_limits.as_ref(),
+            &self.in_keyfile,
+            &mut self.expected_key,
+            &mut self.read_packets,
+        )?;
+        if let Some((encryption, mut entry)) = handshake {
+            let (enc, dec) = encryption.into_split();
+            if let Some((key_log, peer)) = &self.key_log {
+                entry.peer = *peer;
+                key_log.log(&entry)?;
             }
+            let _ = self.enc_channel.0.send(enc);
+            self.encryption = dec;
         }
         Ok(packet)
     }
@@ -586,49 +776,69 @@ impl<P: ProtocolRW + Send> ConnectionRead<P> {
 #[cfg(feature = "split_connection")]
 #[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
 impl ConnectionWrite {
+    /// Same as [`std::net::TcpStream::set_nonblocking`]. Does nothing if `tokio` feature is
+    /// enabled.
+    pub fn set_nonblocking(&self, _nonblocking: bool) -> std::io::Result<()> {
+        #[cfg(not(feature = "tokio"))]
+        self.stream.set_nonblocking(_nonblocking)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "split_connection")]
+#[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
+impl<W: PeerAddress> ConnectionWrite<W> {
     /// Returns the ip address of the client.
     pub fn get_ip(&self) -> std::io::Result<std::net::Ipv4Addr> {
-        let ip = self.stream.peer_addr()?.ip();
-        let ip = match ip {
-            std::net::IpAddr::V4(x) => x,
-            std::net::IpAddr::V6(_) => std::net::Ipv4Addr::UNSPECIFIED,
-        };
-        Ok(ip)
+        get_ip(&self.stream)
+    }
+
+    /// Returns the address of the other side of the connection.
+    pub fn get_peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.stream.peer_address()
     }
+}
 
+#[cfg(feature = "split_connection")]
+#[cfg_attr(docsrs, doc(cfg(feature = "split_connection")))]
+impl<W: ConnWriteAsync + Send> ConnectionWrite<W> {
     /// Changes connection type. Automatically changes the other side.
     pub fn change_packet_type(&mut self, packet_type: PacketType) {
         #[cfg(feature = "ppac")]
-        if let Some(writer) = &mut self.ppac {
-            let mut lock = writer.lock().unwrap();
-            let _ = lock.change_packet_type(packet_type);
+        if let Some(writer) = &self.ppac {
+            let _ = writer.change_packet_type(packet_type);
         }
         self.packet_type = packet_type;
         let _ = self.packettype_channel.0.send(packet_type);
     }
 
-    /// Same as [`std::net::TcpStream::set_nonblocking`]. Does nothing if `tokio` feature is
-    /// enabled.
-    pub fn set_nonblocking(&self, _nonblocking: bool) -> std::io::Result<()> {
-        #[cfg(not(feature = "tokio"))]
-        self.stream.set_nonblocking(_nonblocking)?;
-        Ok(())
-    }
-
     /// Inserts a packet storage file. `direction` is the direction of the `write` side of the
     /// connection.
     #[cfg(feature = "ppac")]
     #[cfg_attr(docsrs, doc(cfg(feature = "ppac")))]
     pub fn set_ppac(
         &mut self,
-        ppac: Arc<Mutex<PPACWriter<std::fs::File>>>,
+        ppac: Arc<Mutex<crate::ppac::PPACWriter<std::fs::File>>>,
         direction: Direction,
     ) -> std::io::Result<()> {
-        self.ppac = Some(ppac);
+        self.ppac = Some(FileWriter::Blocking(ppac));
         self.direction = direction;
         Ok(())
     }
 
+    /// Inserts a packet storage file that is written on a separate thread. `direction` is the
+    /// direction of the `write` side of the connection.
+    #[cfg(all(feature = "ppac", feature = "tokio"))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "ppac", feature = "tokio"))))]
+    pub fn set_background_ppac(
+        &mut self,
+        ppac: Arc<Mutex<crate::ppac::BackgroundPPACWriter<std::fs::File>>>,
+        direction: Direction,
+    ) {
+        self.ppac = Some(FileWriter::Background(ppac));
+        self.direction = direction;
+    }
+
     /// Sends a packet.
     ///
     /// # Note
@@ -660,29 +870,34 @@ impl ConnectionWrite {
         if let Ok(packet_type) = self.packettype_channel.1.try_recv() {
             self.packet_type = packet_type
         }
-        let _packet = if packet.is_enc_data() && !matches!(&self.out_keyfile, PublicKey::None) {
-            let rsa_data = packet
-                .as_enc_data()
-                .expect("is_enc_data returned true while as_enc_data returned None");
-            let mut new_packet = EncryptionRequestPacket::default();
-            let (enc, dec) =
-                Encryption::from_dec_data(rsa_data, matches!(self.packet_type, PacketType::NGS))?
-                    .into_split();
-            let _ = self.enc_channel.0.send(dec);
-            self.encryption = enc;
-            new_packet.rsa_data = encrypt(rsa_data, &self.out_keyfile)?.into();
-            let packet = Packet::EncryptionRequest(new_packet).write(self.packet_type);
-            self.write.prepare_data(&packet, &mut EncryptorType::None)?;
-            packet
-        } else {
-            let packet = packet.write(self.packet_type);
-            self.write.prepare_data(&packet, &mut self.encryption)?;
-            packet
-        };
+        let (data, request) =
+            match engine::replace_enc_request(packet, &self.out_keyfile, self.packet_type)? {
+                Some((encryption, new_packet, mut entry)) => {
+                    let (enc, dec) = encryption.into_split();
+                    let _ = self.enc_channel.0.send(dec);
+                    self.encryption = enc;
+                    if let Some((key_log, peer)) = &self.key_log {
+                        entry.peer = *peer;
+                        key_log.log(&entry)?;
+                    }
+                    let data = new_packet.write(self.packet_type);
+                    self.write.prepare_data(&data, &mut EncryptorType::None)?;
+                    (data, Some(new_packet))
+                }
+                None => {
+                    let data = packet.write(self.packet_type);
+                    self.write.prepare_data(&data, &mut self.encryption)?;
+                    (data, None)
+                }
+            };
         #[cfg(feature = "ppac")]
         if let Some(writer) = &self.ppac {
-            let mut lock = writer.lock().unwrap();
-            lock.write_data(crate::ppac::get_now(), self.direction, &_packet)?;
+            writer.write_data(crate::ppac::get_now(), self.direction, &data)?;
+        }
+        if let Some(sinks) = &self.sinks {
+            let packet = written_packet(packet, request.as_ref());
+            let mut lock = sinks.lock().unwrap();
+            lock.dispatch_any(self.direction, &data, packet)?;
         }
 
         Ok(())
@@ -709,6 +924,26 @@ impl ConnectionWrite {
     }
 }
 
+fn read_packets<P: ProtocolRW>(
+    data: &[u8],
+    packet_type: PacketType,
+    limits: Option<&DecodeLimits>,
+) -> Result<Vec<P>, PacketError> {
+    match limits {
+        Some(limits) => P::read_limited(data, packet_type, limits),
+        None => P::read(data, packet_type),
+    }
+}
+
+fn get_ip(stream: &impl PeerAddress) -> std::io::Result<std::net::Ipv4Addr> {
+    let ip = stream.peer_address()?.ip();
+    let ip = match ip {
+        std::net::IpAddr::V4(x) => x,
+        std::net::IpAddr::V6(_) => std::net::Ipv4Addr::UNSPECIFIED,
+    };
+    Ok(ip)
+}
+
 impl PublicKey {
     pub fn into_key(&self) -> rsa::errors::Result<Option<RsaPublicKey>> {
         match self {
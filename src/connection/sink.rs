@@ -0,0 +1,325 @@
+//! Packet observers.
+
+use crate::protocol::{Direction, Packet, PacketAny, ProtocolRW};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(not(feature = "tokio"))]
+use std::sync::mpsc::{channel, Receiver, Sender};
+#[cfg(feature = "tokio")]
+use tokio::sync::mpsc::{
+    unbounded_channel as channel, UnboundedReceiver as Receiver, UnboundedSender as Sender,
+};
+
+/// Observer of the packets passing through a [`super::Connection`].
+pub trait PacketSink<P>: Send {
+    /// Called for every packet that was read or written. `time` is the time since the Unix epoch,
+    /// `data` is the unencrypted packet data and `packet` is the parsed packet (if it could be
+    /// parsed). Written packets are passed as they were provided to the connection (except for the
+    /// encryption request replaced by the RSA encrypted one), so `packet` is `None` if the packet
+    /// type differs from the connection packet type.
+    fn on_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&P>,
+    ) -> std::io::Result<()>;
+}
+
+/// Packet passed to a [`PacketSink`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CapturedPacket<P> {
+    /// When the packet was read or written.
+    pub time: Duration,
+    /// Where the packet was heading.
+    pub direction: Direction,
+    /// Unencrypted packet data.
+    pub data: Vec<u8>,
+    /// Parsed packet (if it could be parsed).
+    pub packet: Option<P>,
+}
+
+/// Sink that keeps the last `capacity` packets in memory.
+///
+/// Clones of this sink share the same buffer, so a clone can be kept to inspect the packets
+/// passed to the connection.
+#[derive(Debug)]
+pub struct RingBuffer<P> {
+    buffer: Arc<Mutex<VecDeque<CapturedPacket<P>>>>,
+    capacity: usize,
+}
+
+/// Sink that sends packets to a channel. Packets are dropped if the receiver is closed.
+///
+/// If `tokio` feature is enabled the channel is a [`tokio::sync::mpsc::unbounded_channel`],
+/// otherwise it is a [`std::sync::mpsc::channel`].
+#[derive(Debug)]
+pub struct ChannelSink<P> {
+    sender: Sender<CapturedPacket<P>>,
+}
+
+/// Sink that writes packets as JSON lines.
+///
+/// Each line is an object with `time` (seconds since the Unix epoch), `direction`, `packet`
+/// (parsed packet or `null`) and `data` (hex encoded packet data if the packet couldn't be parsed
+/// or `null`) fields. The writer is flushed after every line.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug)]
+pub struct JsonSink<W> {
+    writer: W,
+}
+
+/// Sinks attached to a connection.
+pub(crate) struct Sinks<P> {
+    sinks: Vec<Box<dyn PacketSink<P>>>,
+}
+
+/// Type erased [`Sinks`] (used by the write half of the split connection). Packets are passed
+/// using [`crate::protocol::PacketAny::as_any`].
+#[cfg(feature = "split_connection")]
+pub(crate) trait DispatchSink: Send + std::fmt::Debug {
+    fn dispatch_any(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&dyn std::any::Any>,
+    ) -> std::io::Result<()>;
+}
+
+impl<P> RingBuffer<P> {
+    /// Creates a new ring buffer that keeps at most `capacity` packets.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buffer: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Returns the stored packets (oldest first).
+    pub fn packets(&self) -> Vec<CapturedPacket<P>>
+    where
+        P: Clone,
+    {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Removes and returns the stored packets (oldest first).
+    pub fn take(&self) -> Vec<CapturedPacket<P>> {
+        self.lock().drain(..).collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<CapturedPacket<P>>> {
+        self.buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<P> Clone for RingBuffer<P> {
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+impl<P: Clone + Send> PacketSink<P> for RingBuffer<P> {
+    fn on_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&P>,
+    ) -> std::io::Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut buffer = self.lock();
+        if buffer.len() >= self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(CapturedPacket {
+            time,
+            direction,
+            data: data.to_vec(),
+            packet: packet.cloned(),
+        });
+        Ok(())
+    }
+}
+
+impl<P> ChannelSink<P> {
+    /// Creates a new sink and the receiving side of the channel.
+    pub fn new() -> (Self, Receiver<CapturedPacket<P>>) {
+        let (sender, receiver) = channel();
+        (Self { sender }, receiver)
+    }
+}
+
+impl<P: Clone + Send> PacketSink<P> for ChannelSink<P> {
+    fn on_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&P>,
+    ) -> std::io::Result<()> {
+        let _ = self.sender.send(CapturedPacket {
+            time,
+            direction,
+            data: data.to_vec(),
+            packet: packet.cloned(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ppac")]
+impl<P: ProtocolRW, W: std::io::Write + Send> PacketSink<P> for crate::ppac::PPACWriter<W> {
+    fn on_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+        _: Option<&P>,
+    ) -> std::io::Result<()> {
+        self.write_data_unchecked(time, direction, data)
+            .map_err(|e| match e {
+                crate::ppac::PPACError::IOError(e) => e,
+                e => std::io::Error::other(e),
+            })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<W: std::io::Write> JsonSink<W> {
+    /// Creates a new sink that writes to the provided writer.
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<P: serde::Serialize, W: std::io::Write + Send> PacketSink<P> for JsonSink<W> {
+    fn on_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&P>,
+    ) -> std::io::Result<()> {
+        #[derive(serde::Serialize)]
+        struct Line<'a, P> {
+            time: f64,
+            direction: Direction,
+            packet: Option<&'a P>,
+            data: Option<String>,
+        }
+        let line = Line {
+            time: time.as_secs_f64(),
+            direction,
+            packet,
+            data: packet.is_none().then(|| crate::hex::to_hex(data)),
+        };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+impl<P> Sinks<P> {
+    pub(crate) fn push(&mut self, sink: Box<dyn PacketSink<P>>) {
+        self.sinks.push(sink);
+    }
+
+    #[cfg(feature = "split_connection")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+}
+
+impl<P> Default for Sinks<P> {
+    fn default() -> Self {
+        Self { sinks: vec![] }
+    }
+}
+
+impl<P> std::fmt::Debug for Sinks<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sinks")
+            .field("len", &self.sinks.len())
+            .finish()
+    }
+}
+
+impl<P: ProtocolRW> Sinks<P> {
+    /// Passes every packet in `data` to the sinks. `packets` are the packets parsed from `data`
+    /// (in the same order), missing packets are passed as `None`.
+    pub(crate) fn dispatch(
+        &mut self,
+        direction: Direction,
+        mut data: &[u8],
+        packets: &[&P],
+    ) -> std::io::Result<()> {
+        if self.sinks.is_empty() {
+            return Ok(());
+        }
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut packets = packets.iter().copied();
+        while data.len() > 4 {
+            let len = (&data[..4]).read_u32::<LittleEndian>()? as usize;
+            if len < 4 || len > data.len() {
+                break;
+            }
+            let (packet_data, rest) = data.split_at(len);
+            data = rest;
+            let packet = packets.next();
+            for sink in &mut self.sinks {
+                sink.on_packet(time, direction, packet_data, packet)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes written `data` to the sinks. `packet` is the written packet (see [`written_packet`]).
+    pub(crate) fn dispatch_written(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&dyn std::any::Any>,
+    ) -> std::io::Result<()> {
+        let packet = packet.and_then(P::from_any);
+        self.dispatch(direction, data, packet.as_slice())
+    }
+}
+
+/// Returns the packet that was written: the encryption request that replaced `packet` or `packet`
+/// itself.
+pub(crate) fn written_packet<'a>(
+    packet: &'a impl ProtocolRW,
+    request: Option<&'a Packet>,
+) -> Option<&'a dyn std::any::Any> {
+    match request {
+        Some(request) => request.as_any(),
+        None => packet.as_any(),
+    }
+}
+
+#[cfg(feature = "split_connection")]
+impl<P: ProtocolRW + Send> DispatchSink for Sinks<P> {
+    fn dispatch_any(
+        &mut self,
+        direction: Direction,
+        data: &[u8],
+        packet: Option<&dyn std::any::Any>,
+    ) -> std::io::Result<()> {
+        self.dispatch_written(direction, data, packet)
+    }
+}
@@ -1,7 +1,9 @@
 pub use crate::{
     asciistring::{AsciiString, StringRW},
     protocol::{
-        read_magic, write_magic, Flags, HelperReadWrite, PacketHeader, PacketReadWrite, PacketType,
+        read_magic,
+        spans::{SpanKind, SpanRecorder},
+        write_magic, Flags, HelperReadWrite, PacketHeader, PacketReadWrite, PacketType,
     },
 };
 pub use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -0,0 +1,637 @@
+//! Wireshark dissector generation.
+//!
+//! [`wireshark_lua`] generates a Lua dissector from the packet layouts provided by the derive
+//! macros (see [`crate::protocol::schema`]). The dissector parses the packet header and bodies of
+//! all known packets. Fields of manually implemented types are not dissected, the remaining data
+//! of such packet is shown as undissected.
+//!
+//! The traffic is encrypted, so the dissector only works on decrypted captures, e.g. PPAC files
+//! exported using [`crate::pcap::export_to_pcapng`].
+//!
+//! # Usage
+//!
+//! Write the output to a file in the Wireshark plugin directory (e.g.
+//! `~/.local/lib/wireshark/plugins/pso2.lua`). TCP ports and the packet type can be changed in
+//! the protocol preferences.
+//!
+//! ```
+//! # use pso2packetlib::{dissector, protocol::{Packet, PacketType}};
+//! let lua = dissector::wireshark_lua::<Packet>(PacketType::NGS);
+//! assert!(lua.contains("Proto(\"pso2\""));
+//! ```
+
+use crate::protocol::{
+    schema::{FieldSchema, Padding, TypeSchema},
+    Flags, PacketType, ProtocolRW,
+};
+use std::fmt::Write;
+
+/// Default TCP ports of the dissector.
+const DEFAULT_PORTS: &str = "12000-12999";
+/// Packet types selectable in the preferences.
+const PACKET_TYPES: [PacketType; 5] = [
+    PacketType::NGS,
+    PacketType::Classic,
+    PacketType::NA,
+    PacketType::JP,
+    PacketType::Vita,
+];
+
+/// Common functions of the dissector.
+const PRELUDE: &str = r#"local bxor = bit and bit.bxor or load("return function(a, b) return a ~ b end")()
+
+-- reads a length (or count) of a variable length type
+local function magic(tvb, offset, ctx)
+    return (bxor(tvb(offset, 4):le_uint(), ctx.xor) - ctx.sub) % 4294967296
+end
+
+local function half(v)
+    local sign = v >= 0x8000 and -1 or 1
+    local exp = math.floor(v / 0x400) % 0x20
+    local frac = v % 0x400
+    if exp == 0 then
+        return sign * frac * 2 ^ -24
+    elseif exp == 31 then
+        return frac == 0 and sign * math.huge or 0 / 0
+    end
+    return sign * (1 + frac / 0x400) * 2 ^ (exp - 15)
+end
+
+local function has_flag(v, mask)
+    return math.floor(v / mask) % 2 == 1
+end
+
+local function pad4(len)
+    return (4 - len % 4) % 4
+end
+
+local function check_count(tvb, offset, count)
+    if count > tvb:len() - offset then
+        error(string.format("invalid element count %d", count))
+    end
+end
+
+local function subtree(tree, tvb, offset, label)
+    if offset < tvb:len() then
+        return tree:add(pso2, tvb(offset), label)
+    end
+    return tree:add(pso2, tvb(0, 0), label)
+end
+
+local function undissected(tree, tvb, offset, label)
+    if offset < tvb:len() then
+        tree:add(tvb(offset), label .. ": [undissected data]")
+    end
+end
+"#;
+
+/// Header and main dissector functions.
+const DISSECTOR: &str = r#"local function header_len(tvb, pinfo, offset)
+    return math.max(tvb(offset, 4):le_uint(), 8)
+end
+
+local first_pdu = true
+
+local function dissect_pdu(tvb, pinfo, root)
+    local packet_type = packet_types[pso2.prefs.packet_type]
+    local tree = root:add(pso2, tvb())
+    tree:add_le(f["header.length"], tvb(0, 4))
+    local id, subid, flags_range
+    if packet_type == "NGS" then
+        flags_range = tvb(4, 1)
+        id = tvb(5, 1):uint()
+        subid = tvb(6, 2):le_uint()
+        tree:add_le(f["header.flags"], flags_range)
+        tree:add(f["header.id"], tvb(5, 1))
+        tree:add_le(f["header.subid"], tvb(6, 2))
+    else
+        flags_range = tvb(6, 1)
+        id = tvb(4, 1):uint()
+        subid = tvb(5, 1):uint()
+        tree:add(f["header.id"], tvb(4, 1))
+        tree:add(f["header.subid"], tvb(5, 1))
+        tree:add_le(f["header.flags"], flags_range)
+    end
+    for _, flag in ipairs(header_flags) do
+        tree:add(flags_range, flag[1] .. ": " .. tostring(has_flag(flags_range:uint(), flag[2])))
+    end
+
+    local packet = packets[packet_type][id * 0x10000 + subid]
+    local name = packet and packet.name or string.format("Unknown (0x%02X, 0x%04X)", id, subid)
+    tree:append_text(": " .. name)
+    pinfo.cols.info:append((first_pdu and "" or ", ") .. name)
+    first_pdu = false
+    if not packet or not packet.dissect then
+        undissected(tree, tvb, 8, "Data")
+        return tvb:len()
+    end
+
+    local ctx = { packet_type = packet_type, xor = packet.xor, sub = packet.sub }
+    local ok, offset = pcall(packet.dissect, tvb, tree, 8, ctx)
+    if not ok then
+        tree:add_expert_info(PI_MALFORMED, PI_ERROR, tostring(offset))
+    elseif offset and offset < tvb:len() then
+        -- packets are padded to 4 bytes
+        tree:add(tvb(offset), tvb:len() - offset < 4 and "Padding" or "Trailing data")
+    end
+    return tvb:len()
+end
+
+function pso2.dissector(tvb, pinfo, tree)
+    pinfo.cols.protocol = "PSO2"
+    pinfo.cols.info:clear()
+    first_pdu = true
+    dissect_tcp_pdus(tvb, tree, 4, header_len, dissect_pdu, true)
+end
+
+local tcp_port = DissectorTable.get("tcp.port")
+
+function pso2.prefs_changed()
+    tcp_port:remove_all(pso2)
+    tcp_port:add(pso2.prefs.ports, pso2)
+end
+
+tcp_port:add(pso2.prefs.ports, pso2)
+"#;
+
+/// Generates a Wireshark Lua dissector for the protocol enum `P`. `packet_type` is the default
+/// packet type of the dissector.
+pub fn wireshark_lua<P: ProtocolRW>(packet_type: PacketType) -> String {
+    let mut gen = Generator::default();
+    for packet in P::PACKETS {
+        if let Some(schema) = packet.schema {
+            gen.add_struct(schema.name, schema.fields);
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "-- PSO2 protocol dissector.");
+    let _ = writeln!(
+        out,
+        "-- Generated by pso2packetlib {}. Do not edit.",
+        env!("CARGO_PKG_VERSION")
+    );
+    out.push('\n');
+    out.push_str("local pso2 = Proto(\"pso2\", \"Phantasy Star Online 2\")\n\n");
+
+    // preferences
+    out.push_str("local packet_types = {");
+    for name in PACKET_TYPES.iter().map(type_name) {
+        let _ = write!(out, " {name:?},");
+    }
+    out.push_str(" }\n");
+    let default = PACKET_TYPES
+        .iter()
+        .position(|&t| t == packet_type)
+        .unwrap_or(0)
+        + 1;
+    out.push_str("pso2.prefs.packet_type = Pref.enum(\"Packet type\", ");
+    let _ = write!(out, "{default}, \"Client version of the packets\", {{");
+    for (i, name) in PACKET_TYPES.iter().map(type_name).enumerate() {
+        let _ = write!(out, " {{ {}, {name:?}, {} }},", i + 1, i + 1);
+    }
+    out.push_str(" }, false)\n");
+    let _ = writeln!(
+        out,
+        "pso2.prefs.ports = Pref.range(\"TCP ports\", {DEFAULT_PORTS:?}, \"Ports of the decrypted PSO2 traffic\", 65535)"
+    );
+    out.push('\n');
+    out.push_str(PRELUDE);
+    out.push('\n');
+
+    // fields
+    out.push_str("local f = pso2.fields\n");
+    out.push_str("f[\"header.length\"] = ProtoField.uint32(\"pso2.length\", \"Length\")\n");
+    out.push_str("f[\"header.id\"] = ProtoField.uint8(\"pso2.id\", \"Id\", base.HEX)\n");
+    out.push_str("f[\"header.subid\"] = ProtoField.uint16(\"pso2.subid\", \"Subid\", base.HEX)\n");
+    out.push_str("f[\"header.flags\"] = ProtoField.uint8(\"pso2.flags\", \"Flags\", base.HEX)\n");
+    out.push_str("local header_flags = {");
+    for (name, flag) in Flags::all().iter_names() {
+        let _ = write!(out, " {{ {name:?}, {} }},", flag.bits());
+    }
+    out.push_str(" }\n");
+    for (name, fields) in &gen.structs {
+        for field in fields.iter() {
+            if let Some(decl) = field_decl(name, field) {
+                out.push_str(&decl);
+            }
+        }
+    }
+    out.push('\n');
+
+    // struct dissectors
+    out.push_str("local dissect = {}\n");
+    for (name, fields) in &gen.structs {
+        let mut body = Lua {
+            indent: 1,
+            ..Default::default()
+        };
+        for field in fields.iter() {
+            gen.write_field(&mut body, name, field);
+        }
+        let _ = writeln!(
+            out,
+            "\ndissect[{name:?}] = function(tvb, tree, offset, ctx)"
+        );
+        out.push_str(&body.out);
+        out.push_str("    return offset\nend\n");
+    }
+    out.push('\n');
+
+    // packet table
+    out.push_str("local packets = {}\n");
+    out.push_str(
+        "for _, packet_type in ipairs(packet_types) do\n    packets[packet_type] = {}\nend\n",
+    );
+    out.push_str("local function add_packet(types, id, subid, name, dissector, xor, sub)\n");
+    out.push_str("    for _, packet_type in ipairs(types) do\n");
+    out.push_str("        local key = id * 0x10000 + subid\n");
+    out.push_str("        if not packets[packet_type][key] then\n");
+    out.push_str("            packets[packet_type][key] = { name = name, dissect = dissect[dissector], xor = xor, sub = sub }\n");
+    out.push_str("        end\n    end\nend\n");
+    for packet in P::PACKETS {
+        let types = packet
+            .packet_types
+            .iter()
+            .map(|t| format!("{:?}", type_name(t)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (dissector, (xor, sub)) = match packet.schema {
+            Some(schema) => (
+                format!("{:?}", gen.struct_name(schema.name, schema.fields)),
+                schema.magic.unwrap_or_default(),
+            ),
+            None => ("nil".to_string(), (0, 0)),
+        };
+        let _ = writeln!(
+            out,
+            "add_packet({{ {types} }}, 0x{:02X}, 0x{:04X}, {:?}, {dissector}, {xor}, {sub})",
+            packet.id, packet.subid, packet.name
+        );
+    }
+    out.push('\n');
+    out.push_str(DISSECTOR);
+    out
+}
+
+/// Collected struct layouts.
+#[derive(Default)]
+struct Generator {
+    /// Unique names and fields of structs.
+    structs: Vec<(String, &'static [FieldSchema])>,
+    /// Original names of the structs.
+    names: Vec<&'static str>,
+}
+
+/// Lua code with indentation.
+#[derive(Default)]
+struct Lua {
+    out: String,
+    indent: usize,
+}
+
+impl Lua {
+    fn line(&mut self, line: impl AsRef<str>) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+    fn open(&mut self, line: impl AsRef<str>) {
+        self.line(line);
+        self.indent += 1;
+    }
+    fn close(&mut self) {
+        self.indent -= 1;
+        self.line("end");
+    }
+}
+
+impl Generator {
+    /// Registers a struct and all structs that it contains.
+    fn add_struct(&mut self, name: &'static str, fields: &'static [FieldSchema]) {
+        if self.find_struct(name, fields).is_some() {
+            return;
+        }
+        let unique_name = match self.names.iter().filter(|&&n| n == name).count() {
+            0 => name.to_string(),
+            n => format!("{name}_{}", n + 1),
+        };
+        self.structs.push((unique_name, fields));
+        self.names.push(name);
+        for field in fields {
+            self.add_type(field.ty);
+        }
+    }
+
+    fn add_type(&mut self, ty: &'static TypeSchema) {
+        match ty {
+            TypeSchema::Vec(value)
+            | TypeSchema::VecUSize { value, .. }
+            | TypeSchema::Array { value, .. } => self.add_type(value),
+            TypeSchema::Struct(s) => self.add_struct(s.name, s.fields),
+            _ => {}
+        }
+    }
+
+    fn find_struct(&self, name: &str, fields: &[FieldSchema]) -> Option<&str> {
+        self.structs
+            .iter()
+            .zip(&self.names)
+            .find(|((_, f), &n)| n == name && *f == fields)
+            .map(|((n, _), _)| n.as_str())
+    }
+
+    fn struct_name(&self, name: &str, fields: &[FieldSchema]) -> &str {
+        self.find_struct(name, fields)
+            .expect("struct should be registered")
+    }
+
+    fn write_field(&self, lua: &mut Lua, struct_name: &str, field: &FieldSchema) {
+        let condition = match (field.only_on, field.not_on) {
+            (Some(types), _) => Some(types_condition(types, "==", " or ")),
+            (_, Some(types)) => Some(types_condition(types, "~=", " and ")),
+            _ => None,
+        };
+        if let Some(condition) = &condition {
+            lua.open(format!("if {condition} then"));
+        }
+        for padding in field.padding_before {
+            match padding {
+                Padding::Seek(amount) => lua.line(format!("offset = offset + {amount}")),
+                Padding::ConstU16(_) => lua.line("offset = offset + 2"),
+            }
+        }
+        let key = format!("{struct_name}.{}", field.name);
+        self.write_type(lua, field.ty, &key, field.name);
+        if field.padding_after != 0 {
+            lua.line(format!("offset = offset + {}", field.padding_after));
+        }
+        if condition.is_some() {
+            lua.close();
+        }
+    }
+
+    /// Writes code that dissects a value at `offset` and moves `offset` after it.
+    fn write_type(&self, lua: &mut Lua, ty: &TypeSchema, key: &str, label: &str) {
+        let field = format!("f[{key:?}]");
+        match ty {
+            TypeSchema::Enum(e) => self.write_type(lua, e.repr, key, label),
+            TypeSchema::BitFlags(repr) => self.write_type(lua, repr, key, label),
+            TypeSchema::Flags(flags) => {
+                let size = int_size(flags.repr).unwrap_or(0);
+                lua.open("do");
+                lua.line(format!(
+                    "local item = tree:add_le({field}, tvb(offset, {size}))"
+                ));
+                if size <= 4 {
+                    lua.line(format!("local value = tvb(offset, {size}):le_uint()"));
+                    for (name, bit) in flags.flags {
+                        lua.line(format!(
+                            "item:add(tvb(offset, {size}), \"{name}: \" .. tostring(has_flag(value, {bit})))"
+                        ));
+                    }
+                }
+                lua.line(format!("offset = offset + {size}"));
+                lua.close();
+            }
+            TypeSchema::F16 => {
+                lua.line(format!(
+                    "tree:add({field}, tvb(offset, 2), half(tvb(offset, 2):le_uint()))"
+                ));
+                lua.line("offset = offset + 2");
+            }
+            TypeSchema::U128 | TypeSchema::I128 | TypeSchema::Ipv4Addr => {
+                let size = int_size(ty).unwrap_or(0);
+                lua.line(format!("tree:add({field}, tvb(offset, {size}))"));
+                lua.line(format!("offset = offset + {size}"));
+            }
+            TypeSchema::String | TypeSchema::AsciiString => {
+                let (char_size, padding, getter) = if matches!(ty, TypeSchema::String) {
+                    (2, "2 * (len % 2)", "le_ustring")
+                } else {
+                    (1, "3 - (len - 1) % 4", "string")
+                };
+                lua.open("do");
+                lua.line("local len = magic(tvb, offset, ctx)");
+                lua.line("offset = offset + 4");
+                lua.open("if len ~= 0 then");
+                lua.line(format!("check_count(tvb, offset, len * {char_size})"));
+                lua.line(format!("local range = tvb(offset, len * {char_size})"));
+                lua.line(format!("tree:add({field}, range, range:{getter}())"));
+                lua.line(format!("offset = offset + len * {char_size} + {padding}"));
+                lua.close();
+                lua.close();
+            }
+            TypeSchema::FixedString(len) | TypeSchema::FixedAsciiString(len) => {
+                let (len, getter) = if matches!(ty, TypeSchema::FixedString(_)) {
+                    (len * 2, "le_ustring")
+                } else {
+                    (*len, "string")
+                };
+                if len != 0 {
+                    lua.line(format!(
+                        "tree:add({field}, tvb(offset, {len}), tvb(offset, {len}):{getter}())"
+                    ));
+                    lua.line(format!("offset = offset + {len}"));
+                }
+            }
+            TypeSchema::Bytes { padding } => {
+                lua.open("do");
+                lua.line("local len = magic(tvb, offset, ctx)");
+                lua.line("offset = offset + 4");
+                lua.line("check_count(tvb, offset, len)");
+                lua.open("if len ~= 0 then");
+                lua.line(format!("tree:add({field}, tvb(offset, len))"));
+                lua.close();
+                if *padding {
+                    lua.line("offset = offset + len + pad4(len)");
+                } else {
+                    lua.line("offset = offset + len");
+                }
+                lua.close();
+            }
+            TypeSchema::FixedBytes { len, padding } => {
+                if *len != 0 {
+                    lua.line(format!("tree:add({field}, tvb(offset, {len}))"));
+                }
+                let len = if *padding {
+                    len.next_multiple_of(4)
+                } else {
+                    *len
+                };
+                lua.line(format!("offset = offset + {len}"));
+            }
+            TypeSchema::Vec(value) => {
+                lua.open("do");
+                lua.line("local start = offset");
+                lua.line("local count = magic(tvb, offset, ctx)");
+                lua.line("offset = offset + 4");
+                lua.line("check_count(tvb, offset, count)");
+                self.write_list(lua, value, key, label);
+                lua.line("offset = offset + pad4(offset - start - 4)");
+                lua.line("list:set_len(offset - start)");
+                lua.close();
+            }
+            TypeSchema::VecUSize { len, value } => {
+                let size = int_size(len).unwrap_or(0);
+                lua.open("do");
+                lua.line("local start = offset");
+                lua.line(format!("local count = tvb(offset, {size}):le_uint()"));
+                lua.line(format!("offset = offset + {size}"));
+                lua.line("check_count(tvb, offset, count)");
+                self.write_list(lua, value, key, label);
+                lua.line("list:set_len(offset - start)");
+                lua.close();
+            }
+            TypeSchema::Array { len, value } => {
+                lua.open("do");
+                lua.line("local start = offset");
+                lua.line(format!("local count = {len}"));
+                self.write_list(lua, value, key, label);
+                lua.line("list:set_len(offset - start)");
+                lua.close();
+            }
+            TypeSchema::Struct(s) => {
+                let name = self.struct_name(s.name, s.fields);
+                lua.open("do");
+                lua.line("local start = offset");
+                lua.line(format!(
+                    "local sub = subtree(tree, tvb, offset, \"{label}: {}\")",
+                    s.name
+                ));
+                lua.line(format!("offset = dissect[{name:?}](tvb, sub, offset, ctx)"));
+                lua.open("if not offset then");
+                lua.line("return nil");
+                lua.close();
+                lua.line("sub:set_len(offset - start)");
+                lua.close();
+            }
+            TypeSchema::Opaque => {
+                lua.line(format!("undissected(tree, tvb, offset, \"{label}\")"));
+                lua.open("do");
+                lua.line("return nil");
+                lua.close();
+            }
+            _ => {
+                let size = int_size(ty).unwrap_or(0);
+                lua.line(format!("tree:add_le({field}, tvb(offset, {size}))"));
+                lua.line(format!("offset = offset + {size}"));
+            }
+        }
+    }
+
+    /// Writes a loop over `count` elements. Creates a `list` subtree.
+    fn write_list(&self, lua: &mut Lua, value: &TypeSchema, key: &str, label: &str) {
+        lua.line(format!(
+            "local list = subtree(tree, tvb, start, \"{label} (\" .. count .. \")\")"
+        ));
+        lua.open("for i = 1, count do");
+        lua.line("local tree = list");
+        self.write_type(lua, value, key, &format!("{label}[\" .. i - 1 .. \"]"));
+        lua.close();
+    }
+}
+
+/// Returns the declaration of the field used for leaf values of a struct field.
+fn field_decl(struct_name: &str, field: &FieldSchema) -> Option<String> {
+    let mut ty = field.ty;
+    let mut values = String::new();
+    let mut display = "";
+    loop {
+        match ty {
+            TypeSchema::Vec(value)
+            | TypeSchema::VecUSize { value, .. }
+            | TypeSchema::Array { value, .. } => ty = value,
+            TypeSchema::Enum(e) => {
+                values.push_str(", {");
+                for (name, value) in e.variants {
+                    let _ = write!(values, " [{value}] = {name:?},");
+                }
+                values.push_str(" }");
+                display = "base.DEC";
+                ty = e.repr;
+            }
+            TypeSchema::Flags(flags) => {
+                display = "base.HEX";
+                ty = flags.repr;
+            }
+            TypeSchema::BitFlags(repr) => {
+                display = "base.HEX";
+                ty = repr;
+            }
+            _ => break,
+        }
+    }
+    let field_type = match ty {
+        TypeSchema::U8 => "uint8",
+        TypeSchema::I8 => "int8",
+        TypeSchema::U16 => "uint16",
+        TypeSchema::I16 => "int16",
+        TypeSchema::U32 | TypeSchema::Duration => "uint32",
+        TypeSchema::I32 => "int32",
+        TypeSchema::U64 | TypeSchema::WinTime => "uint64",
+        TypeSchema::I64 => "int64",
+        TypeSchema::F16 | TypeSchema::F32 => "float",
+        TypeSchema::F64 => "double",
+        TypeSchema::Ipv4Addr => "ipv4",
+        TypeSchema::String
+        | TypeSchema::AsciiString
+        | TypeSchema::FixedString(_)
+        | TypeSchema::FixedAsciiString(_) => "string",
+        TypeSchema::U128
+        | TypeSchema::I128
+        | TypeSchema::Bytes { .. }
+        | TypeSchema::FixedBytes { .. } => "bytes",
+        _ => return None,
+    };
+    let abbrev = format!("pso2.{}.{}", struct_name.to_lowercase(), field.name);
+    let args = match (display, values.is_empty()) {
+        ("", _) => String::new(),
+        (display, true) => format!(", {display}"),
+        (display, false) => format!(", {display}{values}"),
+    };
+    Some(format!(
+        "f[\"{struct_name}.{}\"] = ProtoField.{field_type}({abbrev:?}, {:?}{args})\n",
+        field.name, field.name
+    ))
+}
+
+/// Returns the size of an integer type.
+fn int_size(ty: &TypeSchema) -> Option<usize> {
+    Some(match ty {
+        TypeSchema::U8 | TypeSchema::I8 => 1,
+        TypeSchema::U16 | TypeSchema::I16 | TypeSchema::F16 => 2,
+        TypeSchema::U32
+        | TypeSchema::I32
+        | TypeSchema::F32
+        | TypeSchema::Ipv4Addr
+        | TypeSchema::Duration => 4,
+        TypeSchema::U64 | TypeSchema::I64 | TypeSchema::F64 | TypeSchema::WinTime => 8,
+        TypeSchema::U128 | TypeSchema::I128 => 16,
+        TypeSchema::Enum(e) => return int_size(e.repr),
+        TypeSchema::Flags(f) => return int_size(f.repr),
+        TypeSchema::BitFlags(repr) => return int_size(repr),
+        _ => return None,
+    })
+}
+
+fn types_condition(types: &[PacketType], op: &str, join: &str) -> String {
+    types
+        .iter()
+        .map(|t| format!("ctx.packet_type {op} {:?}", type_name(t)))
+        .collect::<Vec<_>>()
+        .join(join)
+}
+
+fn type_name(packet_type: &PacketType) -> &'static str {
+    match packet_type {
+        PacketType::NGS => "NGS",
+        PacketType::Classic => "Classic",
+        PacketType::NA => "NA",
+        PacketType::JP => "JP",
+        PacketType::Vita => "Vita",
+        PacketType::Raw => "Raw",
+    }
+}
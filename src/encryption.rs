@@ -1,6 +1,38 @@
+//! Packet encryption.
+//!
+//! This module can be used to decrypt captured traffic without a live
+//! [`crate::Connection`]. Decrypt the [`crate::protocol::Packet::EncryptionRequest`] data using
+//! [`Encryption::from_rsa_data`] (or [`Encryption::from_dec_data`] if the data is already
+//! decrypted) and pass the result to [`StreamDecryptor`]. Each direction of the connection needs
+//! its own [`Encryption`] instance.
+//!
+//! # Example
+//!
+//! ```
+//! # use pso2packetlib::encryption::{Encryption, StreamDecryptor};
+//! # use pso2packetlib::protocol::{Packet, PacketEncryption, PacketType, ProtocolRW};
+//! # use pso2packetlib::PrivateKey;
+//! # fn main() -> Result<(), pso2packetlib::encryption::EncryptionError> {
+//! # let (to_server, key) = (vec![], PrivateKey::None);
+//! // data sent by the client
+//! let mut client = StreamDecryptor::new();
+//! client.push_data(&to_server)?;
+//! while let Some(data) = client.next_packet()? {
+//!     let Ok(packets) = Packet::read(&data, PacketType::NGS) else {
+//!         continue;
+//!     };
+//!     if let Some(rsa_data) = packets.first().and_then(|p| p.as_enc_data()) {
+//!         client.set_encryption(Encryption::from_rsa_data(rsa_data, &key, true)?);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
 #![allow(unused_variables)]
 #![allow(unused_imports)]
-use crate::connection::{PrivateKey, PublicKey};
+use crate::connection::{conn_impl::ConnectionReader, PrivateKey, PublicKey};
+use crate::protocol::PacketType;
 #[cfg(any(feature = "base_enc", feature = "ngs_enc"))]
 use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 #[cfg(any(feature = "base_enc", feature = "ngs_enc"))]
@@ -34,6 +66,10 @@ pub enum EncryptionError {
     /// AES decryption unpadding failed.
     #[error("AES decryption unpadding failed")]
     UnpadError,
+    /// Client encryption is not supported for the packet type (or the required feature is not
+    /// enabled).
+    #[error("client encryption is not supported for {0:?}")]
+    UnsupportedPacketType(PacketType),
     /// Error occured during ZSTD operations.
     #[error("error occured while performing ZSTD operations: {error}")]
     ZSTDError {
@@ -42,17 +78,34 @@ pub enum EncryptionError {
     },
 }
 
+/// Packet encryptor.
 pub trait Encryptor {
+    /// Encrypts packet data.
     fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, EncryptionError>;
 }
+/// Packet decryptor.
 pub trait Decryptor {
+    /// Decrypts packet data (or stream data if [`Decryptor::is_rc4`] returns `true`).
     fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>, EncryptionError>;
+    /// Returns `true` if the decryptor is a stream cipher.
     fn is_rc4(&self) -> bool {
         false
     }
+    /// Returns the location of the packet length.
     fn get_len_type(&self) -> LengthType;
 }
 
+/// Decryptor of the captured stream data.
+///
+/// Stream data is pushed in using [`StreamDecryptor::push_data`] and decrypted packet data is
+/// pulled out using [`StreamDecryptor::next_packet`].
+#[derive(Debug, Default)]
+pub struct StreamDecryptor {
+    reader: ConnectionReader,
+    decryptor: Encryption,
+}
+
+/// Packet encryption state.
 #[derive(Debug, Default)]
 pub enum Encryption {
     #[default]
@@ -65,11 +118,15 @@ pub enum Encryption {
     Rc4((Rc4Dec, Rc4Enc)),
 }
 
+/// Location of the packet length.
 pub enum LengthType {
+    /// Packet length is stored in the first 4 bytes.
     Default,
+    /// Packet length is stored at the offset 0x44.
     Aes,
 }
 
+/// Encrypting half of the [`Encryption`].
 #[cfg(feature = "split_connection")]
 #[derive(Debug, Default)]
 pub enum EncryptorType {
@@ -83,6 +140,7 @@ pub enum EncryptorType {
     Rc4(Rc4Enc),
 }
 
+/// Decrypting half of the [`Encryption`].
 #[cfg(feature = "split_connection")]
 #[derive(Debug, Default)]
 pub enum DecryptorType {
@@ -96,7 +154,39 @@ pub enum DecryptorType {
     Rc4(Rc4Dec),
 }
 
+impl StreamDecryptor {
+    /// Creates a new decryptor without encryption.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new decryptor with the provided encryption.
+    pub fn with_encryption(encryption: Encryption) -> Self {
+        Self {
+            reader: ConnectionReader::default(),
+            decryptor: encryption,
+        }
+    }
+
+    /// Changes encryption. Data that was not yet returned will be decrypted using the new
+    /// encryption.
+    pub fn set_encryption(&mut self, encryption: Encryption) {
+        self.decryptor = encryption;
+    }
+
+    /// Pushes captured stream data.
+    pub fn push_data(&mut self, data: &[u8]) -> Result<(), EncryptionError> {
+        self.reader.push_data(&mut self.decryptor, data)
+    }
+
+    /// Returns the next decrypted packet data or [`None`] if more data is needed.
+    pub fn next_packet(&mut self) -> Result<Option<Vec<u8>>, EncryptionError> {
+        self.reader.get_packet_data(&mut self.decryptor)
+    }
+}
+
 impl Encryption {
+    /// Decrypts RSA encrypted data (e.g. from [`crate::protocol::Packet::EncryptionRequest`]).
     pub fn decrypt_rsa_data(packet: &[u8], key: &PrivateKey) -> Result<Vec<u8>, EncryptionError> {
         #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
         let private_key = match key.into_key()? {
@@ -111,6 +201,21 @@ impl Encryption {
         let dec_data = packet.to_vec();
         Ok(dec_data)
     }
+    /// Sets up encryption from RSA encrypted data of
+    /// [`crate::protocol::Packet::EncryptionRequest`].
+    pub fn from_rsa_data(
+        data: &[u8],
+        key: &PrivateKey,
+        is_ngs: bool,
+    ) -> Result<Self, EncryptionError> {
+        Self::from_dec_data(&Self::decrypt_rsa_data(data, key)?, is_ngs)
+    }
+    /// Sets up encryption from decrypted data of
+    /// [`crate::protocol::Packet::EncryptionRequest`].
+    ///
+    /// If the data is longer than 0x30 bytes AES encryption is used (NGS variant if `is_ngs` is
+    /// `true`), otherwise RC4 encryption is used. If the required feature is not enabled no
+    /// encryption is used.
     pub fn from_dec_data(data: &[u8], is_ngs: bool) -> Result<Self, EncryptionError> {
         #[cfg(any(feature = "base_enc", feature = "ngs_enc"))]
         if data.len() > 0x30 {
@@ -164,6 +269,31 @@ impl Encryption {
         }
         Ok(Self::None)
     }
+    /// Generates unencrypted client encryption data (i.e. data before RSA encryption in
+    /// [`crate::protocol::Packet::EncryptionRequest`]) for the provided packet type.
+    ///
+    /// NGS and NA use AES encryption, other packet types use RC4 encryption.
+    #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
+    pub fn generate_client_data(packet_type: PacketType) -> Result<Vec<u8>, EncryptionError> {
+        match packet_type {
+            #[cfg(feature = "ngs_enc")]
+            PacketType::NGS => generate_aes_data(),
+            #[cfg(feature = "base_enc")]
+            PacketType::NA => generate_aes_data(),
+            #[cfg(feature = "vita_enc")]
+            PacketType::Classic | PacketType::JP | PacketType::Vita => {
+                use rand::RngCore;
+                use rc4::{KeyInit, StreamCipher};
+                let mut data = vec![0u8; 0x20];
+                rand::thread_rng().fill_bytes(&mut data);
+                let (secret, rc4_key) = data.split_at_mut(0x10);
+                Rc4::<U16>::new((&*rc4_key).into()).apply_keystream(secret);
+                Ok(data)
+            }
+            _ => Err(EncryptionError::UnsupportedPacketType(packet_type)),
+        }
+    }
+    /// Splits the encryption into separate encrypting and decrypting halves.
     #[cfg(feature = "split_connection")]
     pub fn into_split(self) -> (EncryptorType, DecryptorType) {
         match self {
@@ -176,6 +306,7 @@ impl Encryption {
             Encryption::Rc4((dec, enc)) => (EncryptorType::Rc4(enc), DecryptorType::Rc4(dec)),
         }
     }
+    /// Returns the encryption key (for [`crate::protocol::Packet::EncryptionResponse`]).
     pub fn get_key(&self) -> Vec<u8> {
         match self {
             Self::None => Vec::new(),
@@ -331,6 +462,7 @@ impl Decryptor for DecryptorType {
     }
 }
 
+/// NA AES encryption.
 #[cfg(feature = "base_enc")]
 #[derive(Debug, Clone)]
 pub struct Aes {
@@ -394,6 +526,7 @@ impl Encryptor for Aes {
     }
 }
 
+/// NGS AES encryption.
 #[cfg(feature = "ngs_enc")]
 #[derive(Debug, Clone)]
 pub struct AesNgs {
@@ -472,6 +605,7 @@ impl Encryptor for AesNgs {
     }
 }
 
+/// Classic RC4 encryptor.
 #[cfg(feature = "vita_enc")]
 pub struct Rc4Enc {
     encryptor: Box<Rc4<U16>>,
@@ -493,6 +627,7 @@ impl Debug for Rc4Enc {
     }
 }
 
+/// Classic RC4 decryptor.
 #[cfg(feature = "vita_enc")]
 pub struct Rc4Dec {
     decryptor: Box<Rc4<U16>>,
@@ -520,6 +655,26 @@ impl Debug for Rc4Dec {
     }
 }
 
+#[cfg(any(feature = "base_enc", feature = "ngs_enc"))]
+fn generate_aes_data() -> Result<Vec<u8>, EncryptionError> {
+    use rand::RngCore;
+    let mut rng = rand::thread_rng();
+    let iv: [u8; 0x10] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F,
+    ];
+    let mut key = [0u8; 0x20];
+    rng.fill_bytes(&mut key);
+    let mut data = vec![0u8; 0x30];
+    rng.fill_bytes(&mut data[..0x20]);
+    cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+        .encrypt_padded_mut::<Pkcs7>(&mut data, 0x20)
+        .map_err(|_| EncryptionError::PadError)?;
+    data.extend_from_slice(&key);
+    Ok(data)
+}
+
+/// Encrypts data using the RSA public key.
 #[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
 pub fn encrypt(packet: &[u8], out_key: &PublicKey) -> Result<Vec<u8>, EncryptionError> {
     let out_key = match out_key.into_key()? {
@@ -532,6 +687,7 @@ pub fn encrypt(packet: &[u8], out_key: &PublicKey) -> Result<Vec<u8>, Encryption
     Ok(enc_data)
 }
 
+/// Encrypts data using the RSA public key.
 #[cfg(not(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc")))]
 pub fn encrypt(packet: &[u8], _: &PublicKey) -> Result<Vec<u8>, EncryptionError> {
     Ok(packet.to_vec())
@@ -1,6 +1,13 @@
 use crate::{
     asciistring::StringRW,
-    protocol::{read_magic, write_magic, HelperReadWrite, PacketError},
+    protocol::{
+        limits::DecodeContext,
+        read_magic,
+        reflect::{Value, ValueMut},
+        schema::TypeSchema,
+        spans::{SpanKind, SpanRecorder},
+        write_magic, HelperReadWrite, PacketError,
+    },
     AsciiString,
 };
 use std::{
@@ -57,7 +64,7 @@ pub struct FixedVec<const N: usize, T> {
     data: Vec<T>,
 }
 
-trait SizeProvider {
+trait SizeProvider: HelperReadWrite {
     fn to_size(reader: &mut (impl std::io::Read + std::io::Seek)) -> Result<u32, PacketError>;
     fn to_data(size: usize) -> Vec<u8>;
 }
@@ -141,11 +148,15 @@ impl<const N: usize> Display for FixedString<N> {
     }
 }
 impl<const N: usize> HelperReadWrite for FixedString<N> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::FixedString(N);
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         Ok(Self {
             string: <String as StringRW>::read_fixed(reader, N as _).map_err(|e| {
@@ -173,6 +184,13 @@ impl<const N: usize> HelperReadWrite for FixedString<N> {
                 error: e,
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::String(&self.string)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::String(&mut self.string)
+    }
 }
 #[cfg(feature = "serde")]
 impl<'de, const N: usize> serde::Deserialize<'de> for FixedString<N> {
@@ -214,11 +232,15 @@ impl<const N: usize> Display for FixedAsciiString<N> {
     }
 }
 impl<const N: usize> HelperReadWrite for FixedAsciiString<N> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::FixedAsciiString(N);
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         Ok(Self {
             string: AsciiString::read_fixed(reader, N as _).map_err(|e| {
@@ -246,6 +268,13 @@ impl<const N: usize> HelperReadWrite for FixedAsciiString<N> {
                 error: e,
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::String(&self.string)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::AsciiString(&mut self.string)
+    }
 }
 #[cfg(feature = "serde")]
 impl<'de, const N: usize> serde::Deserialize<'de> for FixedAsciiString<N> {
@@ -276,11 +305,15 @@ impl From<Duration> for WinTime {
 }
 const WIN_FT_TIME_TO_TIMESTAMP: u64 = 0x0295_E964_8864;
 impl HelperReadWrite for WinTime {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::WinTime;
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: crate::protocol::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         Ok(Self {
             time: Duration::from_millis(
@@ -310,6 +343,13 @@ impl HelperReadWrite for WinTime {
                 error: e.into(),
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Duration(self.time)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Duration(&mut self.time)
+    }
 }
 
 impl<const N: usize, T> Deref for FixedVec<N, T> {
@@ -335,23 +375,41 @@ impl<const N: usize, T> From<FixedVec<N, T>> for Vec<T> {
     }
 }
 impl<const N: usize, T: HelperReadWrite + Default> HelperReadWrite for FixedVec<N, T> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::Array {
+        len: N,
+        value: &T::SCHEMA,
+    };
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         let mut data = vec![];
         data.reserve_exact(N);
 
-        for _ in 0..N {
-            data.push(T::read(reader, packet_type, xor, sub).map_err(|e| {
-                PacketError::CompositeFieldError {
-                    packet_name: "FixedVec",
-                    field_name: "value",
-                    error: e.into(),
-                }
-            })?);
+        let map_err = |e| PacketError::FieldError {
+            packet_name: "FixedVec",
+            field_name: "value",
+            error: e,
+        };
+        for i in 0..N {
+            spans
+                .begin_index(reader, i, SpanKind::Value)
+                .map_err(map_err)?;
+            data.push(
+                T::read_spanned(reader, packet_type, xor, sub, spans, ctx).map_err(|e| {
+                    PacketError::CompositeFieldError {
+                        packet_name: "FixedVec",
+                        field_name: "value",
+                        error: e.into(),
+                    }
+                })?,
+            );
+            spans.end(reader).map_err(map_err)?;
         }
         Ok(Self { data })
     }
@@ -375,6 +433,13 @@ impl<const N: usize, T: HelperReadWrite + Default> HelperReadWrite for FixedVec<
 
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::List(self.iter().map(|v| v.as_value()).collect())
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::List(self.iter_mut().map(|v| v.as_value_mut()).collect())
+    }
 }
 #[cfg(feature = "serde")]
 impl<'de, const N: usize, T: serde::Deserialize<'de>> serde::Deserialize<'de> for FixedVec<N, T> {
@@ -412,17 +477,36 @@ impl<S, T> From<VecUSize<S, T>> for Vec<T> {
     }
 }
 impl<S: SizeProvider, T: HelperReadWrite> HelperReadWrite for VecUSize<S, T> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::VecUSize {
+        len: &S::SCHEMA,
+        value: &T::SCHEMA,
+    };
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
+        let map_err = |field_name| {
+            move |e| PacketError::FieldError {
+                packet_name: "VecUSize",
+                field_name,
+                error: e,
+            }
+        };
+        spans
+            .begin(reader, "len", SpanKind::Length)
+            .map_err(map_err("len"))?;
         let len = S::to_size(reader).map_err(|e| PacketError::CompositeFieldError {
             packet_name: "VecUSize",
             field_name: "len",
             error: e.into(),
         })?;
+        spans.end(reader).map_err(map_err("len"))?;
+        ctx.check_elements("VecUSize", len as usize, std::mem::size_of::<T>())?;
         let mut data = vec![];
         data.reserve_exact(len as usize);
 
@@ -433,14 +517,20 @@ impl<S: SizeProvider, T: HelperReadWrite> HelperReadWrite for VecUSize<S, T> {
         //         field_name: "pre_read",
         //         error: e,
         //     })?;
-        for _ in 0..len {
-            data.push(T::read(reader, packet_type, xor, sub).map_err(|e| {
-                PacketError::CompositeFieldError {
-                    packet_name: "VecUSize",
-                    field_name: "value",
-                    error: e.into(),
-                }
-            })?);
+        for i in 0..len {
+            spans
+                .begin_index(reader, i as usize, SpanKind::Value)
+                .map_err(map_err("value"))?;
+            data.push(
+                T::read_spanned(reader, packet_type, xor, sub, spans, ctx).map_err(|e| {
+                    PacketError::CompositeFieldError {
+                        packet_name: "VecUSize",
+                        field_name: "value",
+                        error: e.into(),
+                    }
+                })?,
+            );
+            spans.end(reader).map_err(map_err("value"))?;
         }
         // let seek2 = reader
         //     .stream_position()
@@ -507,6 +597,13 @@ impl<S: SizeProvider, T: HelperReadWrite> HelperReadWrite for VecUSize<S, T> {
 
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::List(self.iter().map(|v| v.as_value()).collect())
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::List(self.iter_mut().map(|v| v.as_value_mut()).collect())
+    }
 }
 
 impl<const NO_PADDING: bool> Deref for Bytes<NO_PADDING> {
@@ -532,18 +629,39 @@ impl<const NO_PADDING: bool> From<Bytes<NO_PADDING>> for Vec<u8> {
     }
 }
 impl<const NO_PADDING: bool> HelperReadWrite for Bytes<NO_PADDING> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::Bytes {
+        padding: !NO_PADDING,
+    };
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
+        let map_err = |field_name| {
+            move |e| PacketError::FieldError {
+                packet_name: "Bytes",
+                field_name,
+                error: e,
+            }
+        };
+        spans
+            .begin(reader, "len", SpanKind::Length)
+            .map_err(map_err("len"))?;
         let len = read_magic(reader, sub, xor).map_err(|e| PacketError::FieldLengthError {
             packet_name: "Bytes",
             field_name: "len",
             error: e,
         })?;
+        spans.end(reader).map_err(map_err("len"))?;
+        ctx.check_elements("Bytes", len as usize, 1)?;
         let mut bytes = vec![0; len as usize];
+        spans
+            .begin(reader, "data", SpanKind::Value)
+            .map_err(map_err("bytes"))?;
         reader
             .read_exact(&mut bytes)
             .map_err(|e| PacketError::FieldError {
@@ -551,7 +669,11 @@ impl<const NO_PADDING: bool> HelperReadWrite for Bytes<NO_PADDING> {
                 field_name: "bytes",
                 error: e,
             })?;
+        spans.end(reader).map_err(map_err("bytes"))?;
         if !NO_PADDING {
+            spans
+                .begin(reader, "padding", SpanKind::Padding)
+                .map_err(map_err("padding"))?;
             reader
                 .seek(std::io::SeekFrom::Current(
                     (len.next_multiple_of(4) - len) as i64,
@@ -561,6 +683,7 @@ impl<const NO_PADDING: bool> HelperReadWrite for Bytes<NO_PADDING> {
                     field_name: "padding",
                     error: e,
                 })?;
+            spans.end(reader).map_err(map_err("padding"))?;
         }
         Ok(Self { bytes })
     }
@@ -599,6 +722,13 @@ impl<const NO_PADDING: bool> HelperReadWrite for Bytes<NO_PADDING> {
 
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Bytes(&self.bytes)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Bytes(&mut self.bytes)
+    }
 }
 #[cfg(feature = "serde")]
 impl<'de, const NO_PADDING: bool> serde::Deserialize<'de> for Bytes<NO_PADDING> {
@@ -633,11 +763,18 @@ impl<const N: usize, const NO_PADDING: bool> From<FixedBytes<N, NO_PADDING>> for
     }
 }
 impl<const N: usize, const NO_PADDING: bool> HelperReadWrite for FixedBytes<N, NO_PADDING> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::FixedBytes {
+        len: N,
+        padding: !NO_PADDING,
+    };
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         let mut bytes = vec![0; N];
         reader
@@ -697,6 +834,13 @@ impl<const N: usize, const NO_PADDING: bool> HelperReadWrite for FixedBytes<N, N
         }
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Bytes(&self.bytes)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Bytes(&mut self.bytes)
+    }
 }
 #[cfg(feature = "serde")]
 impl<'de, const N: usize, const NO_PADDING: bool> serde::Deserialize<'de>
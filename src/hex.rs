@@ -0,0 +1,22 @@
+//! Hex encoding of binary data in text formats.
+
+use std::fmt::Write;
+
+/// Encodes data as a lowercase hex string.
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+/// Decodes a hex string. Returns [`None`] if the string is not valid hex.
+pub(crate) fn from_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
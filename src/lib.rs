@@ -5,13 +5,23 @@
 pub mod asciistring;
 #[cfg(feature = "connection")]
 pub mod connection;
+pub mod dissector;
 #[cfg(feature = "connection")]
-pub(crate) mod encryption;
+#[cfg_attr(docsrs, doc(cfg(feature = "connection")))]
+pub mod encryption;
 pub mod fixed_types;
+#[cfg(any(feature = "connection", all(feature = "ppac", feature = "serde")))]
+mod hex;
+#[cfg(feature = "pcap")]
+#[cfg_attr(docsrs, doc(cfg(feature = "pcap")))]
+pub mod pcap;
 #[cfg(feature = "ppac")]
 #[cfg_attr(docsrs, doc(cfg(feature = "ppac")))]
 pub mod ppac;
 pub mod protocol;
+#[cfg(feature = "proxy")]
+#[cfg_attr(docsrs, doc(cfg(feature = "proxy")))]
+pub mod proxy;
 
 #[doc(hidden)]
 pub mod derive_reexports;
@@ -99,7 +109,8 @@ pub use pso2packetlib_impl::PacketRW;
 /// - `#[Skip]`. If applied to a field struct field, then this attribute will skip one bit of the
 ///   flags.
 /// - `#[ManualRW(_readfn_, _writefn_)]` sets the read/write functions for the variant. Specified
-///   functions must have the same prototype as the [`protocol::HelperReadWrite`] functions.
+///   functions must have the same prototype as the [`protocol::HelperReadWrite::read_spanned`] and
+///   [`protocol::HelperReadWrite::write`] functions.
 /// - `#[OnlyOn(_`[`protocol::PacketType`]`_)]`. If set then the field will only be read/written if
 ///   the reader packet type matches the specified packet type.
 /// - `#[NotOn(_`[`protocol::PacketType`]`_)]`. If set then the field will only be read/written if
@@ -0,0 +1,748 @@
+//! Import and export of network captures.
+//!
+//! Captures are read from `pcap` or `pcapng` files (e.g. from `tcpdump`), TCP streams on the PSO2
+//! ports are reassembled and decrypted using the server private key or the session secrets from a
+//! [`crate::connection::KeyLog`].
+//!
+//! PPAC files can be exported to `pcapng` using [`export_to_pcapng`] to inspect the decrypted
+//! traffic in Wireshark.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use pso2packetlib::pcap::PcapImporter;
+//! # use pso2packetlib::ppac::PPACWriter;
+//! # use pso2packetlib::protocol::{Packet, PacketType};
+//! # use pso2packetlib::PrivateKey;
+//! # fn main() -> Result<(), pso2packetlib::pcap::PcapError> {
+//! let importer = PcapImporter::<Packet>::new(PacketType::NGS, PrivateKey::Path("key.pem".into()));
+//! let mut writer = PPACWriter::new(std::fs::File::create("capture.pak")?, PacketType::NGS, true)?;
+//! let stats = importer.import_to_ppac(std::fs::File::open("capture.pcap")?, &mut writer)?;
+//! println!("imported {} packets", stats.packets);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    connection::{KeyLogEntry, PrivateKey},
+    encryption::{Encryption, EncryptionError, StreamDecryptor},
+    ppac::{Direction, OutputType, PPACError, PPACReader, PPACWriter},
+    protocol::{PacketType, ProtocolRW},
+};
+use pcap_file::{
+    pcap::PcapReader,
+    pcapng::{
+        blocks::{
+            enhanced_packet::EnhancedPacketBlock,
+            interface_description::{InterfaceDescriptionBlock, InterfaceDescriptionOption},
+            section_header::{SectionHeaderBlock, SectionHeaderOption},
+        },
+        Block, PcapNgReader, PcapNgWriter,
+    },
+    DataLink,
+};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    marker::PhantomData,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    ops::RangeInclusive,
+    time::Duration,
+};
+
+const PCAPNG_MAGIC: [u8; 4] = [0x0A, 0x0D, 0x0D, 0x0A];
+/// Maximum number of out-of-order segments kept while waiting for missing data.
+const MAX_PENDING_SEGMENTS: usize = 1024;
+
+/// Error type returned by [`PcapImporter`] and [`export_to_pcapng`].
+#[derive(Debug, thiserror::Error)]
+pub enum PcapError {
+    /// Error occured while reading the capture file.
+    #[error("capture file error: {0}")]
+    PcapFileError(#[from] pcap_file::PcapError),
+    /// Error occured while writing the PPAC file.
+    #[error(transparent)]
+    PPACError(#[from] PPACError),
+    /// IO error occured (i.e. [`std::io::Error`]).
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+    /// Interface has an unsupported timestamp resolution.
+    #[error("unsupported timestamp resolution: {0:#X}")]
+    InvalidTimestampResolution(u8),
+}
+
+/// Information about a captured session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// Sequential session number (starting from 0). Used as the PPAC stream id.
+    pub id: u32,
+    /// Address of the client.
+    pub client: SocketAddrV4,
+    /// Address of the server.
+    pub server: SocketAddrV4,
+    /// Capture time of the first segment.
+    pub start: Duration,
+}
+
+/// Import statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// Number of found sessions.
+    pub sessions: usize,
+    /// Number of sessions that couldn't be decrypted (e.g. the key didn't match or too much data
+    /// was missing from the capture).
+    pub failed_sessions: usize,
+    /// Number of imported packets.
+    pub packets: usize,
+}
+
+/// Converter of network captures.
+///
+/// Only IPv4 TCP sessions on the server ports are imported. Sessions that were already running
+/// when the capture started are skipped, because the encryption request is required to decrypt
+/// them.
+#[derive(Debug)]
+pub struct PcapImporter<P: ProtocolRW> {
+    packet_type: PacketType,
+    key: PrivateKey,
+    key_log: Vec<KeyLogEntry>,
+    server_ports: RangeInclusive<u16>,
+    _phantom: PhantomData<P>,
+}
+
+#[derive(Debug, Default)]
+struct TcpStream {
+    next_seq: u32,
+    pending: Vec<(u32, Vec<u8>)>,
+}
+
+#[derive(Debug, PartialEq)]
+enum SessionState {
+    Handshake,
+    Encrypted,
+    Failed,
+}
+
+#[derive(Debug)]
+struct Session {
+    info: SessionInfo,
+    state: SessionState,
+    to_server: TcpStream,
+    to_client: TcpStream,
+    client: StreamDecryptor,
+    server: StreamDecryptor,
+}
+
+struct Segment<'a> {
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+    seq: u32,
+    syn: bool,
+    ack: bool,
+    data: &'a [u8],
+}
+
+struct ImportState<'a, F> {
+    sessions: HashMap<(SocketAddrV4, SocketAddrV4), Session>,
+    stats: ImportStats,
+    callback: &'a mut F,
+}
+
+// Importer implementation
+//--------------------------------------
+
+impl<P: ProtocolRW> PcapImporter<P> {
+    /// Creates a new importer. `key` is the RSA key to decrypt encryption request. It can be
+    /// [`PrivateKey::None`] if all session secrets are provided using
+    /// [`PcapImporter::add_key_log`].
+    pub fn new(packet_type: PacketType, key: PrivateKey) -> Self {
+        Self {
+            packet_type,
+            key,
+            key_log: vec![],
+            server_ports: 12000..=12999,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the ports that are treated as server ports. Default is `12000..=12999`.
+    pub fn set_server_ports(&mut self, ports: RangeInclusive<u16>) {
+        self.server_ports = ports;
+    }
+
+    /// Adds known session secrets (e.g. from [`crate::connection::read_key_log`]). Sessions with a
+    /// known secret are decrypted without the private key.
+    pub fn add_key_log(&mut self, entries: impl IntoIterator<Item = KeyLogEntry>) {
+        self.key_log.extend(entries);
+    }
+
+    /// Reads a `pcap` or `pcapng` capture and calls `callback` for every decrypted packet.
+    pub fn import<R, F>(&self, mut reader: R, mut callback: F) -> Result<ImportStats, PcapError>
+    where
+        R: Read,
+        F: FnMut(&SessionInfo, Duration, Direction, &[u8]) -> Result<(), PcapError>,
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let reader = std::io::Cursor::new(magic).chain(reader);
+        let mut state = ImportState {
+            sessions: HashMap::new(),
+            stats: ImportStats::default(),
+            callback: &mut callback,
+        };
+        if magic == PCAPNG_MAGIC {
+            let mut reader = PcapNgReader::new(reader)?;
+            // (link type, timestamp resolution)
+            let mut interfaces: Vec<(DataLink, u8)> = vec![];
+            let mut last_time = Duration::ZERO;
+            while let Some(block) = reader.next_block() {
+                match block? {
+                    Block::SectionHeader(_) => interfaces.clear(),
+                    Block::InterfaceDescription(idb) => {
+                        let tsresol = idb
+                            .options
+                            .iter()
+                            .find_map(|o| match o {
+                                InterfaceDescriptionOption::IfTsResol(r) => Some(*r),
+                                _ => None,
+                            })
+                            .unwrap_or(6);
+                        interfaces.push((idb.linktype, tsresol));
+                    }
+                    Block::EnhancedPacket(epb) => {
+                        let Some(&(linktype, tsresol)) = interfaces.get(epb.interface_id as usize)
+                        else {
+                            continue;
+                        };
+                        last_time = convert_timestamp(epb.timestamp.as_nanos(), tsresol)
+                            .ok_or(PcapError::InvalidTimestampResolution(tsresol))?;
+                        self.process_frame(&mut state, linktype, last_time, &epb.data)?;
+                    }
+                    Block::SimplePacket(spb) => {
+                        let Some(&(linktype, _)) = interfaces.first() else {
+                            continue;
+                        };
+                        self.process_frame(&mut state, linktype, last_time, &spb.data)?;
+                    }
+                    _ => {}
+                }
+            }
+        } else {
+            let mut reader = PcapReader::new(reader)?;
+            let linktype = reader.header().datalink;
+            while let Some(packet) = reader.next_packet() {
+                let packet = packet?;
+                self.process_frame(&mut state, linktype, packet.timestamp, &packet.data)?;
+            }
+        }
+        Ok(state.stats)
+    }
+
+    /// Reads a `pcap` or `pcapng` capture and writes all decrypted packets to the PPAC file. The
+    /// stream id of each packet is set to its session id.
+    pub fn import_to_ppac<R: Read, W: Write>(
+        &self,
+        reader: R,
+        writer: &mut PPACWriter<W>,
+    ) -> Result<ImportStats, PcapError> {
+        self.import(reader, |session, time, direction, data| {
+            writer.set_stream_id(session.id);
+            writer.write_data_unchecked(time, direction, data)?;
+            Ok(())
+        })
+    }
+
+    fn process_frame<F>(
+        &self,
+        state: &mut ImportState<F>,
+        linktype: DataLink,
+        time: Duration,
+        frame: &[u8],
+    ) -> Result<(), PcapError>
+    where
+        F: FnMut(&SessionInfo, Duration, Direction, &[u8]) -> Result<(), PcapError>,
+    {
+        let Some(segment) = parse_frame(linktype, frame) else {
+            return Ok(());
+        };
+        let (direction, key) = if self.server_ports.contains(&segment.dst.port()) {
+            (Direction::ToServer, (segment.src, segment.dst))
+        } else if self.server_ports.contains(&segment.src.port()) {
+            (Direction::ToClient, (segment.dst, segment.src))
+        } else {
+            return Ok(());
+        };
+
+        if segment.syn {
+            if matches!(direction, Direction::ToServer) && !segment.ack {
+                let next_seq = segment.seq.wrapping_add(1);
+                // retransmitted SYN
+                if matches!(state.sessions.get(&key), Some(s) if s.to_server.next_seq == next_seq) {
+                    return Ok(());
+                }
+                state.sessions.insert(
+                    key,
+                    Session {
+                        info: SessionInfo {
+                            id: state.stats.sessions as u32,
+                            client: key.0,
+                            server: key.1,
+                            start: time,
+                        },
+                        state: SessionState::Handshake,
+                        to_server: TcpStream::new(next_seq),
+                        to_client: TcpStream::default(),
+                        client: StreamDecryptor::new(),
+                        server: StreamDecryptor::new(),
+                    },
+                );
+                state.stats.sessions += 1;
+            } else if let Some(session) = state.sessions.get_mut(&key) {
+                session.to_client = TcpStream::new(segment.seq.wrapping_add(1));
+            }
+            return Ok(());
+        }
+
+        let Some(session) = state.sessions.get_mut(&key) else {
+            return Ok(());
+        };
+        if session.state == SessionState::Failed || segment.data.is_empty() {
+            return Ok(());
+        }
+        let data = match direction {
+            Direction::ToServer => session.to_server.push_segment(segment.seq, segment.data),
+            Direction::ToClient => session.to_client.push_segment(segment.seq, segment.data),
+        };
+        // the gap is never filled, so the rest of the session can't be decrypted
+        let Some(data) = data else {
+            session.state = SessionState::Failed;
+            state.stats.failed_sessions += 1;
+            return Ok(());
+        };
+        if data.is_empty() {
+            return Ok(());
+        }
+        match self.process_data(session, state.callback, time, direction, &data) {
+            Ok(packets) => state.stats.packets += packets,
+            Err(SessionError::Encryption) => {
+                session.state = SessionState::Failed;
+                state.stats.failed_sessions += 1;
+            }
+            Err(SessionError::Import(e)) => return Err(e),
+        }
+        Ok(())
+    }
+
+    fn process_data<F>(
+        &self,
+        session: &mut Session,
+        callback: &mut F,
+        time: Duration,
+        direction: Direction,
+        data: &[u8],
+    ) -> Result<usize, SessionError>
+    where
+        F: FnMut(&SessionInfo, Duration, Direction, &[u8]) -> Result<(), PcapError>,
+    {
+        let mut count = 0;
+        let Session {
+            info,
+            state,
+            client,
+            server,
+            ..
+        } = session;
+        let (stream, other) = match direction {
+            Direction::ToServer => (client, server),
+            Direction::ToClient => (server, client),
+        };
+        stream.push_data(data)?;
+        while let Some(packet) = stream.next_packet()? {
+            callback(info, time, direction, &packet)?;
+            count += 1;
+            if *state != SessionState::Handshake || !matches!(direction, Direction::ToServer) {
+                continue;
+            }
+            let Ok(packets) = P::read(&packet, self.packet_type) else {
+                continue;
+            };
+            let Some(rsa_data) = packets.first().and_then(|p| p.as_enc_data()) else {
+                continue;
+            };
+            let dec_data = match self.key_log.iter().find(|e| e.matches(rsa_data)) {
+                Some(entry) => entry.secret.clone(),
+                None => Encryption::decrypt_rsa_data(rsa_data, &self.key)?,
+            };
+            let is_ngs = matches!(self.packet_type, PacketType::NGS);
+            stream.set_encryption(Encryption::from_dec_data(&dec_data, is_ngs)?);
+            // all server data up to this point was sent before the server received the key
+            other.set_encryption(Encryption::from_dec_data(&dec_data, is_ngs)?);
+            *state = SessionState::Encrypted;
+        }
+        Ok(count)
+    }
+}
+
+enum SessionError {
+    // session can't be decrypted
+    Encryption,
+    Import(PcapError),
+}
+
+impl From<EncryptionError> for SessionError {
+    fn from(_: EncryptionError) -> Self {
+        Self::Encryption
+    }
+}
+
+impl From<PcapError> for SessionError {
+    fn from(value: PcapError) -> Self {
+        Self::Import(value)
+    }
+}
+
+// TCP reassembly implementation
+//--------------------------------------
+
+impl TcpStream {
+    fn new(next_seq: u32) -> Self {
+        Self {
+            next_seq,
+            pending: vec![],
+        }
+    }
+
+    /// Returns the in-order data that became available after this segment or [`None`] if too many
+    /// segments are waiting for missing data.
+    fn push_segment(&mut self, seq: u32, data: &[u8]) -> Option<Vec<u8>> {
+        let mut out = vec![];
+        if self.pending.len() >= MAX_PENDING_SEGMENTS {
+            return None;
+        }
+        self.pending.push((seq, data.to_vec()));
+        while let Some(pos) = self
+            .pending
+            .iter()
+            .position(|(seq, _)| (seq.wrapping_sub(self.next_seq) as i32) <= 0)
+        {
+            let (seq, data) = self.pending.swap_remove(pos);
+            // retransmitted bytes are skipped
+            let skip = self.next_seq.wrapping_sub(seq) as usize;
+            if skip < data.len() {
+                out.extend_from_slice(&data[skip..]);
+                self.next_seq = seq.wrapping_add(data.len() as u32);
+            }
+        }
+        Some(out)
+    }
+}
+
+// Frame parsing
+//--------------------------------------
+
+fn convert_timestamp(raw: u128, tsresol: u8) -> Option<Duration> {
+    let nanos = if tsresol & 0x80 != 0 {
+        raw.checked_mul(1_000_000_000)? >> (tsresol & 0x7F)
+    } else if tsresol <= 9 {
+        raw.checked_mul(10u128.pow(9 - tsresol as u32))?
+    } else {
+        raw / 10u128.checked_pow(tsresol as u32 - 9)?
+    };
+    Some(Duration::new(
+        (nanos / 1_000_000_000) as u64,
+        (nanos % 1_000_000_000) as u32,
+    ))
+}
+
+fn parse_frame(linktype: DataLink, frame: &[u8]) -> Option<Segment<'_>> {
+    let ip = match linktype {
+        DataLink::ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            // VLAN tags
+            while ethertype == 0x8100 || ethertype == 0x88A8 {
+                offset += 4;
+                ethertype = u16::from_be_bytes(frame.get(offset..offset + 2)?.try_into().ok()?);
+            }
+            if ethertype != 0x0800 {
+                return None;
+            }
+            frame.get(offset + 2..)?
+        }
+        DataLink::LINUX_SLL => {
+            if frame.get(14..16)? != [0x08, 0x00] {
+                return None;
+            }
+            &frame[16..]
+        }
+        DataLink::NULL | DataLink::LOOP => frame.get(4..)?,
+        DataLink::RAW | DataLink::IPV4 => frame,
+        _ => return None,
+    };
+    parse_ipv4(ip)
+}
+
+fn parse_ipv4(ip: &[u8]) -> Option<Segment<'_>> {
+    if ip.len() < 20 || ip[0] >> 4 != 4 || ip[9] != 6 {
+        return None;
+    }
+    // fragmented packets are not supported
+    let fragment = u16::from_be_bytes([ip[6], ip[7]]);
+    if fragment & 0x3FFF != 0 {
+        return None;
+    }
+    let header_len = (ip[0] & 0xF) as usize * 4;
+    let total_len = u16::from_be_bytes([ip[2], ip[3]]) as usize;
+    let src = Ipv4Addr::new(ip[12], ip[13], ip[14], ip[15]);
+    let dst = Ipv4Addr::new(ip[16], ip[17], ip[18], ip[19]);
+    // total length can be 0 with TSO
+    let tcp = match total_len {
+        0 => ip.get(header_len..)?,
+        _ => ip.get(header_len..total_len.min(ip.len()))?,
+    };
+    if tcp.len() < 20 {
+        return None;
+    }
+    let data_offset = (tcp[12] >> 4) as usize * 4;
+    let flags = tcp[13];
+    Some(Segment {
+        src: SocketAddrV4::new(src, u16::from_be_bytes([tcp[0], tcp[1]])),
+        dst: SocketAddrV4::new(dst, u16::from_be_bytes([tcp[2], tcp[3]])),
+        seq: u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]),
+        syn: flags & 0x02 != 0,
+        ack: flags & 0x10 != 0,
+        data: tcp.get(data_offset..)?,
+    })
+}
+
+// Exporter implementation
+//--------------------------------------
+
+/// Server endpoint of the exported streams if the file doesn't store it.
+const EXPORT_SERVER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 12000);
+/// Client endpoint of the first exported stream if the file doesn't store it.
+const EXPORT_CLIENT: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 2), 50000);
+/// Maximum payload of an exported TCP segment.
+const MAX_SEGMENT: usize = 0xFFFF - 40;
+
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+#[derive(Debug)]
+struct ExportStream {
+    client: SocketAddrV4,
+    server: SocketAddrV4,
+    client_seq: u32,
+    server_seq: u32,
+}
+
+/// Exports a PPAC file to a `pcapng` capture (e.g. for Wireshark).
+///
+/// Every stream of the file is written as a synthetic IPv4 TCP connection, that starts with a
+/// handshake and carries the decrypted packets with their original timestamps and directions.
+/// Endpoints from the file metadata are used if they are IPv4 addresses, streams after the first
+/// one use the following client ports. Packet type and other metadata are stored as section
+/// comments. Returns the underlying writer and the number of exported packets.
+pub fn export_to_pcapng<R: Read, P: ProtocolRW, W: Write>(
+    mut reader: PPACReader<R, P>,
+    writer: W,
+) -> Result<(W, usize), PcapError> {
+    let metadata = reader.get_metadata().clone();
+    let mut comments = vec![format!("packet type: {:?}", reader.get_protocol_type())];
+    if let Some(build) = &metadata.client_build {
+        comments.push(format!("client build: {build}"));
+    }
+    if let Some(time) = metadata.start_time {
+        comments.push(format!("start time: {}", time.as_secs_f64()));
+    }
+    if let Some(server) = metadata.server {
+        comments.push(format!("server: {server}"));
+    }
+    if let Some(client) = metadata.client {
+        comments.push(format!("client: {client}"));
+    }
+    if let Some(tool) = &metadata.tool {
+        comments.push(format!("tool: {tool}"));
+    }
+    if let Some(notes) = &metadata.notes {
+        comments.push(format!("notes: {notes}"));
+    }
+    let section = SectionHeaderBlock {
+        options: comments
+            .into_iter()
+            .map(|c| SectionHeaderOption::Comment(c.into()))
+            .chain([SectionHeaderOption::UserApplication(
+                concat!("pso2packetlib ", env!("CARGO_PKG_VERSION")).into(),
+            )])
+            .collect(),
+        ..Default::default()
+    };
+    let mut writer = PcapNgWriter::with_section_header(writer, section)?;
+    writer.write_pcapng_block(InterfaceDescriptionBlock {
+        linktype: DataLink::IPV4,
+        snaplen: 0,
+        options: vec![
+            InterfaceDescriptionOption::IfName("pso2".into()),
+            InterfaceDescriptionOption::IfTsResol(9),
+        ],
+    })?;
+    let server = match metadata.server {
+        Some(SocketAddr::V4(server)) => server,
+        _ => EXPORT_SERVER,
+    };
+    let client = match metadata.client {
+        Some(SocketAddr::V4(client)) => client,
+        _ => EXPORT_CLIENT,
+    };
+    let mut streams: HashMap<u32, ExportStream> = HashMap::new();
+    let mut packets = 0;
+    reader.set_out_type(OutputType::Raw);
+    while let Some(packet) = reader.read()? {
+        let Some(data) = packet.data else {
+            continue;
+        };
+        let time = packet.time;
+        let stream = match streams.get_mut(&packet.stream_id) {
+            Some(stream) => stream,
+            None => {
+                let port = client.port().wrapping_add(streams.len() as u16);
+                let stream = ExportStream {
+                    client: SocketAddrV4::new(*client.ip(), port),
+                    server,
+                    client_seq: 0,
+                    server_seq: 0,
+                };
+                write_segment(
+                    &mut writer,
+                    time,
+                    &stream,
+                    Direction::ToServer,
+                    TCP_SYN,
+                    &[],
+                )?;
+                write_segment(
+                    &mut writer,
+                    time,
+                    &stream,
+                    Direction::ToClient,
+                    TCP_SYN | TCP_ACK,
+                    &[],
+                )?;
+                let stream = streams.entry(packet.stream_id).or_insert(stream);
+                stream.client_seq = 1;
+                stream.server_seq = 1;
+                write_segment(&mut writer, time, stream, Direction::ToServer, TCP_ACK, &[])?;
+                stream
+            }
+        };
+        for chunk in data.chunks(MAX_SEGMENT) {
+            write_segment(
+                &mut writer,
+                time,
+                stream,
+                packet.direction,
+                TCP_PSH | TCP_ACK,
+                chunk,
+            )?;
+            let seq = match packet.direction {
+                Direction::ToServer => &mut stream.client_seq,
+                Direction::ToClient => &mut stream.server_seq,
+            };
+            *seq = seq.wrapping_add(chunk.len() as u32);
+        }
+        packets += 1;
+    }
+    Ok((writer.into_inner(), packets))
+}
+
+fn write_segment<W: Write>(
+    writer: &mut PcapNgWriter<W>,
+    time: Duration,
+    stream: &ExportStream,
+    direction: Direction,
+    flags: u8,
+    data: &[u8],
+) -> Result<(), PcapError> {
+    let (src, dst, seq, ack) = match direction {
+        Direction::ToServer => (
+            stream.client,
+            stream.server,
+            stream.client_seq,
+            stream.server_seq,
+        ),
+        Direction::ToClient => (
+            stream.server,
+            stream.client,
+            stream.server_seq,
+            stream.client_seq,
+        ),
+    };
+    let frame = build_frame(src, dst, seq, ack, flags, data);
+    writer.write_pcapng_block(EnhancedPacketBlock {
+        interface_id: 0,
+        // raw value in nanoseconds (see the interface resolution)
+        timestamp: time,
+        original_len: frame.len() as u32,
+        data: frame.into(),
+        options: vec![],
+    })?;
+    Ok(())
+}
+
+/// Builds an IPv4 TCP segment.
+fn build_frame(
+    src: SocketAddrV4,
+    dst: SocketAddrV4,
+    seq: u32,
+    ack: u32,
+    flags: u8,
+    data: &[u8],
+) -> Vec<u8> {
+    let total_len = 40 + data.len();
+    let mut frame = Vec::with_capacity(total_len);
+    frame.extend_from_slice(&[0x45, 0x00]);
+    frame.extend_from_slice(&(total_len as u16).to_be_bytes());
+    // id, don't fragment, ttl, protocol, checksum
+    frame.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+    frame.extend_from_slice(&src.ip().octets());
+    frame.extend_from_slice(&dst.ip().octets());
+    let ip_checksum = checksum(&frame, 0);
+    frame[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+    frame.extend_from_slice(&src.port().to_be_bytes());
+    frame.extend_from_slice(&dst.port().to_be_bytes());
+    frame.extend_from_slice(&seq.to_be_bytes());
+    let ack = if flags & TCP_ACK != 0 { ack } else { 0 };
+    frame.extend_from_slice(&ack.to_be_bytes());
+    // data offset, flags, window, checksum, urgent pointer
+    frame.extend_from_slice(&[0x50, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+    frame.extend_from_slice(data);
+    // pseudo header
+    let mut sum = 0;
+    for ip in [src.ip(), dst.ip()] {
+        let octets = ip.octets();
+        sum += u16::from_be_bytes([octets[0], octets[1]]) as u32;
+        sum += u16::from_be_bytes([octets[2], octets[3]]) as u32;
+    }
+    sum += 6 + (total_len - 20) as u32;
+    let tcp_checksum = checksum(&frame[20..], sum);
+    frame[36..38].copy_from_slice(&tcp_checksum.to_be_bytes());
+    frame
+}
+
+/// Internet checksum.
+fn checksum(data: &[u8], initial: u32) -> u16 {
+    let mut sum = data.chunks(2).fold(initial, |sum, chunk| {
+        let word = match *chunk {
+            [a, b] => u16::from_be_bytes([a, b]),
+            [a] => u16::from_be_bytes([a, 0]),
+            _ => 0,
+        };
+        sum + word as u32
+    });
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -0,0 +1,151 @@
+//! Background PPAC writer.
+
+use super::{Direction, PPACError, PPACWriter};
+use crate::protocol::{PacketType, ProtocolRW};
+use std::{
+    io::{Seek, Write},
+    sync::{mpsc, Arc, Mutex},
+    time::Duration,
+};
+
+enum Command {
+    Data(Duration, Direction, Vec<u8>),
+    Packet(Duration, Direction, Vec<u8>),
+    PacketType(PacketType),
+    StreamId(u32),
+    Flush,
+}
+
+/// [`PPACWriter`] that writes packets on a separate thread.
+///
+/// Writes only queue the packet, so this writer can be used from async code without blocking the
+/// runtime on the file IO. Errors that occur on the writing thread are returned by the next call.
+#[derive(Debug)]
+pub struct BackgroundPPACWriter<W> {
+    sender: Option<mpsc::Sender<Command>>,
+    result: Option<tokio::sync::oneshot::Receiver<Option<W>>>,
+    error: Arc<Mutex<Option<PPACError>>>,
+    packet_type: PacketType,
+}
+
+impl<W: Write + Seek + Send + 'static> BackgroundPPACWriter<W> {
+    /// Moves the writer to a new thread.
+    pub fn new(mut writer: PPACWriter<W>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let (result_send, result_recv) = tokio::sync::oneshot::channel();
+        let error = Arc::new(Mutex::new(None));
+        let packet_type = writer.packet_type;
+        let thread_error = error.clone();
+        std::thread::spawn(move || {
+            for command in receiver {
+                let result = match command {
+                    Command::Data(time, direction, data) => {
+                        writer.write_data(time, direction, &data)
+                    }
+                    Command::Packet(time, direction, data) => {
+                        writer.write_data_unchecked(time, direction, &data)
+                    }
+                    Command::PacketType(packet_type) => writer.change_packet_type(packet_type),
+                    Command::StreamId(stream_id) => {
+                        writer.set_stream_id(stream_id);
+                        Ok(())
+                    }
+                    Command::Flush => writer.flush_frame(),
+                };
+                if let Err(e) = result {
+                    *thread_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e);
+                    return;
+                }
+            }
+            let inner = match writer.into_inner() {
+                Ok(inner) => Some(inner),
+                Err(e) => {
+                    *thread_error.lock().unwrap_or_else(|e| e.into_inner()) = Some(e.into());
+                    None
+                }
+            };
+            let _ = result_send.send(inner);
+        });
+        Self {
+            sender: Some(sender),
+            result: Some(result_recv),
+            error,
+            packet_type,
+        }
+    }
+}
+
+impl<W> BackgroundPPACWriter<W> {
+    /// Queues data (must be valid packet data).
+    pub fn write_data(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        input: &[u8],
+    ) -> Result<(), PPACError> {
+        self.send(Command::Data(time, direction, input.to_vec()))
+    }
+
+    /// Queues a parsed packet.
+    pub fn write_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        input: &impl ProtocolRW,
+    ) -> Result<(), PPACError> {
+        self.send(Command::Packet(
+            time,
+            direction,
+            input.write(self.packet_type),
+        ))
+    }
+
+    /// Changes stored client type.
+    pub fn change_packet_type(&mut self, packet_type: PacketType) -> Result<(), PPACError> {
+        if matches!(packet_type, PacketType::Raw) {
+            return Err(PPACError::InvalidPacketType(5));
+        }
+        self.packet_type = packet_type;
+        self.send(Command::PacketType(packet_type))
+    }
+
+    /// Sets the connection/stream id of the following packets.
+    pub fn set_stream_id(&mut self, stream_id: u32) -> Result<(), PPACError> {
+        self.send(Command::StreamId(stream_id))
+    }
+
+    /// Queues [`PPACWriter::flush_frame`].
+    pub fn flush_frame(&mut self) -> Result<(), PPACError> {
+        self.send(Command::Flush)
+    }
+
+    /// Writes all queued packets, finishes the file and returns the underlying writer.
+    pub async fn finish(mut self) -> Result<W, PPACError> {
+        self.sender = None;
+        let inner = self.result.take().expect("result should be present").await;
+        match (inner, self.take_error()) {
+            (_, Some(e)) => Err(e),
+            (Ok(Some(inner)), None) => Ok(inner),
+            _ => Err(stopped()),
+        }
+    }
+
+    fn send(&mut self, command: Command) -> Result<(), PPACError> {
+        if let Some(e) = self.take_error() {
+            return Err(e);
+        }
+        self.sender
+            .as_ref()
+            .expect("sender should be present")
+            .send(command)
+            .map_err(|_| stopped())
+    }
+
+    fn take_error(&self) -> Option<PPACError> {
+        self.error.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+}
+
+fn stopped() -> PPACError {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "writer thread has stopped").into()
+}
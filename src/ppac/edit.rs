@@ -0,0 +1,164 @@
+//! Merging, splitting and trimming of PPAC files.
+
+use super::{Header, Metadata, PPACError, PPACReader, PPACWriter};
+use crate::protocol::{Packet, PacketType};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    ops::RangeBounds,
+    time::Duration,
+};
+
+/// Uncompressed size of the indexed frames in the produced files.
+const FRAME_SIZE: u64 = 0x100000;
+
+/// How [`split`] divides the packet records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitBy {
+    /// Each part contains the records stored in a time window of this length (starting from the
+    /// first record). Empty windows don't produce a part.
+    Time(Duration),
+    /// Each part contains the records of a single connection/stream.
+    Stream,
+    /// Each part contains this many records (the last part might contain less).
+    Records(u64),
+}
+
+/// Merges multiple files into one, ordering packet records by their timestamp.
+///
+/// All files must have the same packet type. Stream ids are renumbered, so connections from
+/// different files don't share an id. Metadata is taken from the first file, with the earliest
+/// start time of all files. The output is packed and indexed.
+pub fn merge<R: Read, W: Write>(inputs: Vec<R>, output: W) -> Result<W, PPACError> {
+    let mut readers = inputs
+        .into_iter()
+        .map(PPACReader::<_, Packet>::open)
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(first) = readers.first() else {
+        return Err(PPACError::InvalidFile);
+    };
+    let packet_type = first.get_protocol_type();
+    let mut metadata = first.get_metadata().clone();
+    for reader in &readers[1..] {
+        if reader.get_protocol_type() != packet_type {
+            return Err(PPACError::PacketTypeMismatch(
+                packet_type,
+                reader.get_protocol_type(),
+            ));
+        }
+        metadata.start_time = match (metadata.start_time, reader.get_metadata().start_time) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+    let mut writer = new_writer(output, packet_type, &metadata)?;
+    let mut heads = readers
+        .iter_mut()
+        .map(|r| r.read_record())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut stream_ids = HashMap::new();
+    loop {
+        let next = heads
+            .iter()
+            .enumerate()
+            .filter_map(|(i, h)| h.as_ref().map(|(header, _)| (i, header.time)))
+            .min_by_key(|&(_, time)| time);
+        let Some((i, _)) = next else {
+            break;
+        };
+        let (header, data) = heads[i].take().expect("record should be present");
+        let next_id = stream_ids.len() as u32;
+        let stream_id = *stream_ids.entry((i, header.stream_id)).or_insert(next_id);
+        write_record(&mut writer, header, stream_id, &data)?;
+        heads[i] = readers[i].read_record()?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+/// Splits a file into parts. `create` is called with the part number (for [`SplitBy::Stream`]
+/// this is the stream id) to create the writer of a new part.
+///
+/// Parts keep the packet type and metadata of the input. The parts are packed and indexed and
+/// are returned in the order they were created.
+pub fn split<R: Read, W: Write>(
+    input: R,
+    by: SplitBy,
+    mut create: impl FnMut(u64) -> std::io::Result<W>,
+) -> Result<Vec<W>, PPACError> {
+    let mut reader = PPACReader::<_, Packet>::open(input)?;
+    let packet_type = reader.get_protocol_type();
+    let metadata = reader.get_metadata().clone();
+    // part key -> index of the writer
+    let mut parts = HashMap::new();
+    let mut writers = vec![];
+    let mut first_time = None;
+    let mut records = 0;
+    while let Some((header, data)) = reader.read_record()? {
+        let key = match by {
+            SplitBy::Time(window) => {
+                let start = *first_time.get_or_insert(header.time);
+                let since_start = header.time.saturating_sub(start).as_nanos();
+                (since_start / window.as_nanos().max(1)) as u64
+            }
+            SplitBy::Stream => header.stream_id as u64,
+            SplitBy::Records(count) => records / count.max(1),
+        };
+        records += 1;
+        let index = match parts.get(&key) {
+            Some(&index) => index,
+            None => {
+                let part = match by {
+                    SplitBy::Stream => key,
+                    _ => writers.len() as u64,
+                };
+                let writer = new_writer(create(part)?, packet_type, &metadata)?;
+                writers.push(writer);
+                parts.insert(key, writers.len() - 1);
+                writers.len() - 1
+            }
+        };
+        write_record(&mut writers[index], header, header.stream_id, &data)?;
+    }
+    writers
+        .into_iter()
+        .map(|w| w.into_inner().map_err(PPACError::from))
+        .collect()
+}
+
+/// Copies the packet records stored in the provided time range.
+///
+/// The output keeps the packet type and metadata of the input and is packed and indexed.
+pub fn trim<R: Read, W: Write>(
+    input: R,
+    output: W,
+    range: impl RangeBounds<Duration>,
+) -> Result<W, PPACError> {
+    let mut reader = PPACReader::<_, Packet>::open(input)?;
+    let mut writer = new_writer(output, reader.get_protocol_type(), reader.get_metadata())?;
+    while let Some((header, data)) = reader.read_record()? {
+        if range.contains(&header.time) {
+            write_record(&mut writer, header, header.stream_id, &data)?;
+        }
+    }
+    Ok(writer.into_inner()?)
+}
+
+fn new_writer<W: Write>(
+    output: W,
+    packet_type: PacketType,
+    metadata: &Metadata,
+) -> Result<PPACWriter<W>, PPACError> {
+    let mut writer = PPACWriter::with_metadata(output, packet_type, true, metadata)?;
+    writer.enable_index(FRAME_SIZE);
+    Ok(writer)
+}
+
+fn write_record<W: Write>(
+    writer: &mut PPACWriter<W>,
+    header: Header,
+    stream_id: u32,
+    data: &[u8],
+) -> Result<(), PPACError> {
+    writer.set_stream_id(stream_id);
+    writer.write_data_unchecked(header.time, header.direction, data)
+}
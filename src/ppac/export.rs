@@ -0,0 +1,184 @@
+//! Conversion of PPAC files to and from JSON Lines and MessagePack.
+
+use super::{Direction, OutputType, PPACError, PPACReader, PPACWriter};
+use crate::{
+    hex::{from_hex, to_hex},
+    protocol::{PacketType, ProtocolRW},
+};
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+/// Single exported packet.
+///
+/// If the packet was parsed and writing it produces the stored data, only the parsed packet is
+/// exported. Otherwise `data` contains the hex encoded stored data, so the conversion is
+/// lossless.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct PacketRecord<P> {
+    /// When was the packet stored (nanoseconds since the Unix epoch).
+    pub time: u64,
+    /// Where the packet was heading.
+    pub direction: Direction,
+    /// Which client version produced this packet.
+    pub protocol_type: PacketType,
+    /// Connection/stream that the packet belongs to.
+    pub stream_id: u32,
+    /// Parsed packet.
+    pub packet: Option<P>,
+    /// Hex encoded packet data.
+    pub data: Option<String>,
+    /// Parsing error.
+    pub parse_error: Option<String>,
+}
+
+impl<P: ProtocolRW> PacketRecord<P> {
+    /// Returns the stored packet data.
+    pub fn to_data(&self) -> Result<Vec<u8>, PPACError> {
+        match (&self.data, &self.packet) {
+            (Some(data), _) => from_hex(data).ok_or_else(|| invalid_data("invalid hex data")),
+            (None, Some(packet)) => Ok(packet.write(self.protocol_type)),
+            (None, None) => Err(invalid_data("record has no packet data")),
+        }
+    }
+}
+
+/// Iterator over the records of the file. Packets are split, parsed and converted to
+/// [`PacketRecord`]s.
+pub fn records<R: Read, P: ProtocolRW>(
+    mut reader: PPACReader<R, P>,
+) -> impl Iterator<Item = Result<PacketRecord<P>, PPACError>> {
+    reader.set_out_type(OutputType::Both);
+    reader.map(|packet| {
+        let packet = packet?;
+        let data = packet.data.unwrap_or_default();
+        let lossless = packet
+            .packet
+            .as_ref()
+            .is_some_and(|p| p.write(packet.protocol_type) == data);
+        Ok(PacketRecord {
+            time: packet.time.as_nanos() as u64,
+            direction: packet.direction,
+            protocol_type: packet.protocol_type,
+            stream_id: packet.stream_id,
+            packet: packet.packet,
+            data: (!lossless).then(|| to_hex(&data)),
+            parse_error: packet.parse_error.map(|e| e.to_string()),
+        })
+    })
+}
+
+/// Writes records back to a PPAC file. The file uses the protocol type of the first record.
+///
+/// Every packet is written as a separate packet record.
+pub fn import<P: ProtocolRW, W: Write>(
+    records: impl IntoIterator<Item = Result<PacketRecord<P>, PPACError>>,
+    output: W,
+    is_packed: bool,
+) -> Result<W, PPACError> {
+    let mut output = Some(output);
+    let mut writer = None;
+    for record in records {
+        let record = record?;
+        let writer = match &mut writer {
+            Some(writer) => writer,
+            None => writer.insert(PPACWriter::new(
+                output.take().expect("output should be present"),
+                record.protocol_type,
+                is_packed,
+            )?),
+        };
+        if writer.packet_type != record.protocol_type {
+            return Err(PPACError::PacketTypeMismatch(
+                writer.packet_type,
+                record.protocol_type,
+            ));
+        }
+        writer.set_stream_id(record.stream_id);
+        writer.write_data_unchecked(
+            Duration::from_nanos(record.time),
+            record.direction,
+            &record.to_data()?,
+        )?;
+    }
+    match (writer, output) {
+        (Some(writer), _) => Ok(writer.into_inner()?),
+        (None, Some(output)) => {
+            Ok(PPACWriter::new(output, PacketType::default(), is_packed)?.into_inner()?)
+        }
+        (None, None) => unreachable!("output should be used by the writer"),
+    }
+}
+
+/// Converts the file to JSON Lines (one [`PacketRecord`] per line).
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn to_json_lines<R: Read, P: ProtocolRW + serde::Serialize, W: Write>(
+    reader: PPACReader<R, P>,
+    mut output: W,
+) -> Result<W, PPACError> {
+    for record in records(reader) {
+        serde_json::to_writer(&mut output, &record?).map_err(std::io::Error::from)?;
+        output.write_all(b"\n")?;
+    }
+    Ok(output)
+}
+
+/// Converts JSON Lines produced by [`to_json_lines`] back to a PPAC file.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn from_json_lines<P: ProtocolRW + serde::de::DeserializeOwned, W: Write>(
+    input: impl std::io::BufRead,
+    output: W,
+    is_packed: bool,
+) -> Result<W, PPACError> {
+    let records = input
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| -> Result<PacketRecord<P>, PPACError> {
+            Ok(serde_json::from_str(&line?).map_err(std::io::Error::from)?)
+        });
+    import(records, output, is_packed)
+}
+
+/// Converts the file to MessagePack (a sequence of [`PacketRecord`] maps).
+#[cfg(feature = "msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+pub fn to_msgpack<R: Read, P: ProtocolRW + serde::Serialize, W: Write>(
+    reader: PPACReader<R, P>,
+    mut output: W,
+) -> Result<W, PPACError> {
+    for record in records(reader) {
+        rmp_serde::encode::write_named(&mut output, &record?).map_err(std::io::Error::other)?;
+    }
+    Ok(output)
+}
+
+/// Converts MessagePack produced by [`to_msgpack`] back to a PPAC file.
+#[cfg(feature = "msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+pub fn from_msgpack<P: ProtocolRW + serde::de::DeserializeOwned, W: Write>(
+    input: impl Read,
+    output: W,
+    is_packed: bool,
+) -> Result<W, PPACError> {
+    let mut deserializer = rmp_serde::Deserializer::new(input);
+    let records = std::iter::from_fn(move || {
+        match serde::Deserialize::deserialize(&mut deserializer) {
+            Ok(record) => Some(Ok::<PacketRecord<P>, _>(record)),
+            // end of the input
+            Err(rmp_serde::decode::Error::InvalidMarkerRead(e))
+                if e.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                None
+            }
+            Err(e) => Some(Err(std::io::Error::other(e).into())),
+        }
+    });
+    import(records, output, is_packed)
+}
+
+fn invalid_data(error: &str) -> PPACError {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, error).into()
+}
@@ -0,0 +1,117 @@
+//! Packet filtering.
+
+use super::Direction;
+use crate::protocol::{PacketCategory, PacketHeader, ProtocolRW};
+use std::{
+    ops::{Bound, RangeBounds},
+    time::Duration,
+};
+
+/// Filter for packets read by [`super::PPACReader`].
+///
+/// All set conditions must match, but added categories and ids are alternatives (i.e. the packet
+/// must match one of them). Direction and time are checked before the record is split into
+/// packets and categories and ids are checked using the packet header, so only the matching
+/// packets are parsed.
+///
+/// # Example
+///
+/// ```no_run
+/// # use pso2packetlib::ppac::{Direction, PacketFilter, PPACReader};
+/// # use pso2packetlib::protocol::{Packet, PacketCategory};
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), pso2packetlib::ppac::PPACError> {
+/// let mut reader = PPACReader::<_, Packet>::open(std::fs::File::open("capture.pak")?)?;
+/// let filter = PacketFilter::new()
+///     .direction(Direction::ToClient)
+///     .category(PacketCategory::Object)
+///     .time_range(Duration::from_secs(1_700_000_000)..Duration::from_secs(1_700_000_060));
+/// reader.set_filter(Some(filter));
+/// for packet in reader {
+///     println!("{:?}", packet?.packet);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PacketFilter {
+    direction: Option<Direction>,
+    categories: Vec<PacketCategory>,
+    ids: Vec<(u8, u16)>,
+    time: (Bound<Duration>, Bound<Duration>),
+    parse_failed: Option<bool>,
+}
+
+impl PacketFilter {
+    /// Creates a filter that matches all packets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match packets heading in the provided direction.
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = Some(direction);
+        self
+    }
+
+    /// Match packets of the provided category. Category is determined by the packet id (see
+    /// [`PacketCategory::from_id`]).
+    pub fn category(mut self, category: PacketCategory) -> Self {
+        self.categories.push(category);
+        self
+    }
+
+    /// Match packets with the provided id and subid.
+    pub fn id(mut self, id: u8, subid: u16) -> Self {
+        self.ids.push((id, subid));
+        self
+    }
+
+    /// Only match packets stored in the provided time range.
+    pub fn time_range(mut self, range: impl RangeBounds<Duration>) -> Self {
+        self.time = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Only match packets that failed to parse (if `true`) or that were parsed successfully (if
+    /// `false`). Setting this condition requires all otherwise matching packets to be parsed.
+    pub fn parse_failed(mut self, failed: bool) -> Self {
+        self.parse_failed = Some(failed);
+        self
+    }
+
+    pub(super) fn parse_status(&self) -> Option<bool> {
+        self.parse_failed
+    }
+
+    pub(super) fn matches_record(&self, time: Duration, direction: Direction) -> bool {
+        if matches!(self.direction, Some(d) if d != direction) {
+            return false;
+        }
+        self.time.contains(&time)
+    }
+
+    pub(super) fn matches_header<P: ProtocolRW>(&self, header: Option<&PacketHeader>) -> bool {
+        if self.categories.is_empty() && self.ids.is_empty() {
+            return true;
+        }
+        let Some(header) = header else {
+            return false;
+        };
+        self.categories
+            .contains(&PacketCategory::from_id::<P>(header.id, header.subid))
+            || self.ids.contains(&(header.id, header.subid))
+    }
+}
+
+impl Default for PacketFilter {
+    fn default() -> Self {
+        Self {
+            direction: None,
+            categories: vec![],
+            ids: vec![],
+            time: (Bound::Unbounded, Bound::Unbounded),
+            parse_failed: None,
+        }
+    }
+}
@@ -0,0 +1,1327 @@
+//! Packet storage file format.
+//!
+//! # Random access
+//!
+//! If the index is enabled using [`PPACWriter::enable_index`], packed data is split into
+//! independently decodable zstd frames and the frame index is stored at the end of the file in a
+//! zstd skippable frame (so older readers ignore it). [`PPACReader::seek_to_packet`] and
+//! [`PPACReader::seek_to_time`] use this index to jump directly to the closest frame. Files
+//! without the index are still seekable, but packed files are then decoded from the start.
+//!
+//! # Filtering
+//!
+//! [`PPACReader`] is also an [`Iterator`] over the stored packets. Setting a [`PacketFilter`]
+//! using [`PPACReader::set_filter`] skips packets without parsing them.
+//!
+//! # Crash recovery
+//!
+//! [`PPACWriter::flush_frame`] ends the current zstd frame and flushes the file, so everything
+//! written before it can be read even if the writer never finishes (e.g. the process crashes).
+//! [`PPACWriter::set_flush_interval`] does this periodically and connections enable it for their
+//! packet storage files. Files that are truncated or damaged can be read using
+//! [`PPACReader::set_salvage`], which recovers all complete packet records and reports where the
+//! damage begins.
+//!
+//! # Editing
+//!
+//! The [`edit`] module can merge, split and trim files.
+//!
+//! # Export
+//!
+//! If `serde` feature is enabled, the [`export`] module converts files to a stream of serializable
+//! packet records and back. `json` and `msgpack` features add converters to JSON Lines and
+//! MessagePack.
+//!
+//! # Async
+//!
+//! If `tokio` feature is enabled, `BackgroundPPACWriter` can be used to write packets on a separate
+//! thread. Connections use it for the storage files created by `Connection::create_ppac` and it
+//! can be inserted into split connections using `set_background_ppac`.
+
+#[cfg(feature = "tokio")]
+mod background;
+pub mod edit;
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub mod export;
+mod filter;
+mod recovery;
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub use background::BackgroundPPACWriter;
+pub use filter::PacketFilter;
+pub use recovery::Damage;
+
+pub use crate::protocol::Direction;
+use crate::protocol::{Packet, PacketError, PacketHeader, PacketType, ProtocolRW};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use recovery::{FrameDecoder, PosReader};
+use std::{
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    net::SocketAddr,
+    time::Duration,
+};
+use zstd::stream::Encoder;
+
+/// Error type returned by [`PPACReader`] and [`PPACWriter`].
+#[derive(Debug, thiserror::Error)]
+pub enum PPACError {
+    /// Error occurred while parsing a packet.
+    #[error(transparent)]
+    PacketError(#[from] PacketError),
+    /// File with unsupported version was opened.
+    #[error("unsupported version: {0}")]
+    UnsupportedVersion(u8),
+    /// File is not a PPAC file.
+    #[error("opened file is not a PPAC file")]
+    InvalidFile,
+    /// File with unsupported protocol type ([`crate::protocol::PacketType`]) was opened.
+    #[error("invalid packet type: {0}")]
+    InvalidPacketType(u8),
+    /// Files with different protocol types were merged.
+    #[error("packet type mismatch: {0:?} and {1:?}")]
+    PacketTypeMismatch(PacketType, PacketType),
+    /// Packet with invalid length was being written.
+    #[error("attempted to write a corrupted packet")]
+    CorruptedPacket,
+    /// IO error occured (i.e. [`std::io::Error`]).
+    #[error("IO error: {0}")]
+    IOError(#[from] std::io::Error),
+}
+
+/// Possible types of packet data output.
+pub enum OutputType {
+    /// Output only parsed packet data.
+    Packet,
+    /// Output only raw packet data.
+    Raw,
+    /// Output both types.
+    Both,
+}
+
+/// Capture metadata (stored in version 5 and later).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metadata {
+    /// Client build string.
+    pub client_build: Option<String>,
+    /// When the capture was started.
+    pub start_time: Option<Duration>,
+    /// Server endpoint.
+    pub server: Option<SocketAddr>,
+    /// Client endpoint.
+    pub client: Option<SocketAddr>,
+    /// Name of the tool that produced the capture.
+    pub tool: Option<String>,
+    /// Free-form notes.
+    pub notes: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    time: Duration,
+    direction: Direction,
+    stream_id: u32,
+}
+
+/// Start of a block of packets that can be read independently.
+#[derive(Debug, Clone, Copy)]
+struct IndexEntry {
+    /// Number of the first packet record in the block.
+    record: u64,
+    /// Timestamp of the first packet record in the block.
+    time: Duration,
+    /// File offset of the block.
+    offset: u64,
+}
+
+enum ReaderWrapper<R: Read> {
+    NoEnc(PosReader<R>),
+    Zstd(FrameDecoder<R>),
+}
+
+enum WriterWrapper<W: Write> {
+    NoEnc(W),
+    Zstd(Encoder<'static, W>),
+}
+
+/// Writer that keeps track of the current position.
+#[derive(Debug)]
+struct PosWriter<W: Write> {
+    writer: W,
+    pos: u64,
+}
+
+/// Reader for the `ppac` packet files.
+pub struct PPACReader<R: Read, P: ProtocolRW> {
+    reader: Option<ReaderWrapper<R>>,
+    version: u8,
+    is_packed: bool,
+    data_start: u64,
+    index: Option<Vec<IndexEntry>>,
+    record: u64,
+    pending_record: Option<(Header, Vec<u8>)>,
+    packet_buffer: Vec<P>,
+    data_buffer: Vec<Vec<u8>>,
+    protocol_type: PacketType,
+    metadata: Metadata,
+    last_header: Header,
+    out_type: OutputType,
+    filter: Option<PacketFilter>,
+    io_failed: bool,
+    salvage: bool,
+    damage: Vec<Damage>,
+}
+
+/// Writer of the `ppac` packet files.
+#[derive(Debug)]
+pub struct PPACWriter<W: Write> {
+    writer: Option<WriterWrapper<PosWriter<W>>>,
+    packet_type: PacketType,
+    stream_id: u32,
+    is_packed: bool,
+    records: u64,
+    index: Vec<IndexEntry>,
+    frame_start: u64,
+    frame_bytes: u64,
+    frame_size: Option<u64>,
+    frame_time: Duration,
+    flush_interval: Option<Duration>,
+}
+
+/// Packet data.
+pub struct PacketData<P: ProtocolRW> {
+    /// When was the packet stored.
+    pub time: Duration,
+    /// Where the packet was heading.
+    pub direction: Direction,
+    /// Which client version produced this packet.
+    pub protocol_type: PacketType,
+    /// Connection/stream that the packet belongs to (0 for files before version 5).
+    pub stream_id: u32,
+    /// Parsed packet (if requested).
+    pub packet: Option<P>,
+    /// Unparsed packet (if requested).
+    pub data: Option<Vec<u8>>,
+    /// Parsing error (if any and if both types are requested).
+    pub parse_error: Option<PacketError>,
+}
+
+//--------------------------------------
+// Reader/Writer wrapper implementation
+//--------------------------------------
+
+impl<R: Read> ReaderWrapper<R> {
+    fn new(reader: R, is_packed: bool, pos: u64) -> std::io::Result<Self> {
+        Ok(match is_packed {
+            true => ReaderWrapper::Zstd(FrameDecoder::new(reader, pos)?),
+            false => ReaderWrapper::NoEnc(PosReader::new(reader, pos)),
+        })
+    }
+
+    fn into_inner(self) -> R {
+        match self {
+            ReaderWrapper::NoEnc(r) => r.into_inner(),
+            ReaderWrapper::Zstd(d) => d.into_inner(),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut R {
+        match self {
+            ReaderWrapper::NoEnc(r) => r.get_mut(),
+            ReaderWrapper::Zstd(d) => d.get_mut(),
+        }
+    }
+
+    /// Offset of the next record (or for packed files of the current frame).
+    fn offset(&self) -> u64 {
+        match self {
+            ReaderWrapper::NoEnc(r) => r.position(),
+            ReaderWrapper::Zstd(d) => d.frame_start(),
+        }
+    }
+
+    fn next_frame(&mut self) -> std::io::Result<bool> {
+        match self {
+            ReaderWrapper::NoEnc(_) => Ok(false),
+            ReaderWrapper::Zstd(d) => d.next_frame(),
+        }
+    }
+
+    /// Moves past the damaged data. Unpacked files can't be resynchronized, so reading stops.
+    fn resync(&mut self) -> std::io::Result<bool> {
+        match self {
+            ReaderWrapper::NoEnc(r) => {
+                r.stop();
+                Ok(false)
+            }
+            ReaderWrapper::Zstd(d) => d.resync(),
+        }
+    }
+}
+
+impl<R: Read> Read for ReaderWrapper<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            ReaderWrapper::NoEnc(r) => r.read(buf),
+            ReaderWrapper::Zstd(d) => d.read(buf),
+        }
+    }
+}
+
+impl<W: Write> WriterWrapper<W> {
+    fn into_inner(self) -> std::io::Result<W> {
+        match self {
+            WriterWrapper::NoEnc(w) => Ok(w),
+            WriterWrapper::Zstd(e) => e.finish(),
+        }
+    }
+    fn write_u8_raw(&mut self, byte: u8) -> std::io::Result<()> {
+        match self {
+            WriterWrapper::NoEnc(w) => w.write_u8(byte),
+            WriterWrapper::Zstd(e) => e.get_mut().write_u8(byte),
+        }
+    }
+}
+
+impl<W: Write> Write for WriterWrapper<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            WriterWrapper::NoEnc(w) => w.write(buf),
+            WriterWrapper::Zstd(e) => e.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            WriterWrapper::NoEnc(w) => w.flush(),
+            WriterWrapper::Zstd(e) => e.flush(),
+        }
+    }
+}
+
+impl<W: Write> Write for PosWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.writer.write(buf)?;
+        self.pos += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write + Seek> Seek for PosWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = self.writer.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl<W: Write + Seek> Seek for WriterWrapper<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            WriterWrapper::NoEnc(w) => w.seek(pos),
+            WriterWrapper::Zstd(e) => e.get_mut().seek(pos),
+        }
+    }
+}
+
+impl<W: Write> std::fmt::Debug for WriterWrapper<W> {
+    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Ok(())
+    }
+}
+
+//--------------------------------------
+// Metadata implementation
+//--------------------------------------
+const TAG_CLIENT_BUILD: u8 = 1;
+const TAG_START_TIME: u8 = 2;
+const TAG_SERVER: u8 = 3;
+const TAG_CLIENT: u8 = 4;
+const TAG_TOOL: u8 = 5;
+const TAG_NOTES: u8 = 6;
+
+impl Metadata {
+    /// Metadata for captures started by this library.
+    #[cfg(feature = "connection")]
+    pub(crate) fn live_capture() -> Self {
+        Self {
+            start_time: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .ok(),
+            tool: Some(concat!("pso2packetlib ", env!("CARGO_PKG_VERSION")).to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn read(mut data: &[u8]) -> Result<Self, PPACError> {
+        let mut metadata = Self::default();
+        while !data.is_empty() {
+            let tag = data.read_u8()?;
+            let len = data.read_u32::<LittleEndian>()? as usize;
+            if len > data.len() {
+                return Err(PPACError::InvalidFile);
+            }
+            let (value, rest) = data.split_at(len);
+            data = rest;
+            let string = || String::from_utf8_lossy(value).into_owned();
+            match tag {
+                TAG_CLIENT_BUILD => metadata.client_build = Some(string()),
+                TAG_START_TIME => {
+                    let nanos = value
+                        .try_into()
+                        .map(u128::from_le_bytes)
+                        .map_err(|_| PPACError::InvalidFile)?;
+                    metadata.start_time = Some(Duration::new(
+                        (nanos / 1_000_000_000) as u64,
+                        (nanos % 1_000_000_000) as u32,
+                    ));
+                }
+                TAG_SERVER => metadata.server = string().parse().ok(),
+                TAG_CLIENT => metadata.client = string().parse().ok(),
+                TAG_TOOL => metadata.tool = Some(string()),
+                TAG_NOTES => metadata.notes = Some(string()),
+                // unknown fields from newer writers
+                _ => {}
+            }
+        }
+        Ok(metadata)
+    }
+
+    fn write(&self) -> Vec<u8> {
+        let mut out = vec![];
+        let mut write_field = |tag: u8, value: &[u8]| {
+            out.push(tag);
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(value);
+        };
+        if let Some(build) = &self.client_build {
+            write_field(TAG_CLIENT_BUILD, build.as_bytes());
+        }
+        if let Some(time) = self.start_time {
+            write_field(TAG_START_TIME, &time.as_nanos().to_le_bytes());
+        }
+        if let Some(server) = self.server {
+            write_field(TAG_SERVER, server.to_string().as_bytes());
+        }
+        if let Some(client) = self.client {
+            write_field(TAG_CLIENT, client.to_string().as_bytes());
+        }
+        if let Some(tool) = &self.tool {
+            write_field(TAG_TOOL, tool.as_bytes());
+        }
+        if let Some(notes) = &self.notes {
+            write_field(TAG_NOTES, notes.as_bytes());
+        }
+        out
+    }
+}
+
+//--------------------------------------
+// PPAC reader wrapper implementation
+//--------------------------------------
+const MAX_VERSION: u8 = 5;
+
+impl<R: Read, P: ProtocolRW> PPACReader<R, P> {
+    /// Opens a PPAC file.
+    pub fn open(mut reader: R) -> Result<Self, PPACError> {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header)?;
+        if &header != b"PPAC" {
+            return Err(PPACError::InvalidFile);
+        }
+        let version = reader.read_u8()?;
+        if version > MAX_VERSION {
+            return Err(PPACError::UnsupportedVersion(version));
+        }
+        let protocol_type = if version >= 3 {
+            let tmp_proto = reader.read_u8()?;
+            match tmp_proto {
+                0 => PacketType::Classic,
+                1 => PacketType::NGS,
+                2 => PacketType::NA,
+                3 => PacketType::JP,
+                4 => PacketType::Vita,
+                x => return Err(PPACError::InvalidPacketType(x)),
+            }
+        } else {
+            PacketType::NGS
+        };
+        let is_packed = if version >= 4 {
+            reader.read_u8()? != 0
+        } else {
+            false
+        };
+        let (metadata, data_start) = if version >= 5 {
+            let len = reader.read_u32::<LittleEndian>()?;
+            let mut data = vec![];
+            reader.by_ref().take(len as u64).read_to_end(&mut data)?;
+            if data.len() != len as usize {
+                return Err(PPACError::InvalidFile);
+            }
+            (Metadata::read(&data)?, 11 + len as u64)
+        } else {
+            let data_start = match version {
+                4 => 7,
+                3 => 6,
+                _ => 5,
+            };
+            (Metadata::default(), data_start)
+        };
+        let reader = ReaderWrapper::new(reader, is_packed, data_start)?;
+        Ok(Self {
+            reader: Some(reader),
+            version,
+            is_packed,
+            data_start,
+            index: None,
+            record: 0,
+            pending_record: None,
+            packet_buffer: vec![],
+            data_buffer: vec![],
+            protocol_type,
+            metadata,
+            last_header: Header {
+                time: Duration::new(0, 0),
+                direction: Direction::ToServer,
+                stream_id: 0,
+            },
+            out_type: OutputType::Packet,
+            filter: None,
+            io_failed: false,
+            salvage: false,
+            damage: vec![],
+        })
+    }
+
+    /// Sets the client type.
+    pub fn set_out_type(&mut self, out_type: OutputType) {
+        self.out_type = out_type;
+    }
+
+    /// Sets the packet filter. Packets that don't match the filter are skipped by [`Self::read`].
+    ///
+    /// Remaining packets of the partially read record are discarded, so the filter should be set
+    /// before reading or right after seeking.
+    pub fn set_filter(&mut self, filter: Option<PacketFilter>) {
+        self.filter = filter;
+        self.packet_buffer.clear();
+        self.data_buffer.clear();
+    }
+
+    /// Enables the salvage mode.
+    ///
+    /// In this mode damaged or truncated data doesn't stop reading with an error. Instead, the
+    /// damage is recorded (see [`Self::get_damage`]) and reading of packed files continues from the
+    /// next intact zstd frame, so every complete packet record is recovered. Unpacked files are
+    /// read up to the damage.
+    pub fn set_salvage(&mut self, salvage: bool) {
+        self.salvage = salvage;
+    }
+
+    /// Returns the damage found in the salvage mode.
+    pub fn get_damage(&self) -> &[Damage] {
+        &self.damage
+    }
+
+    /// Returns the readers protocol type..
+    pub fn get_protocol_type(&self) -> PacketType {
+        self.protocol_type
+    }
+
+    /// Returns the capture metadata. Files before version 5 have empty metadata.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Reads a packet from the PPAC.
+    pub fn read(&mut self) -> Result<Option<PacketData<P>>, PPACError> {
+        if let Some(filter) = self.filter.take() {
+            let result = self.read_filtered(&filter);
+            self.filter = Some(filter);
+            return result;
+        }
+        let packet = if !self.packet_buffer.is_empty() {
+            self.packet_buffer.drain(0..1).next()
+        } else {
+            None
+        };
+        let data = if !self.data_buffer.is_empty() {
+            self.data_buffer.drain(0..1).next()
+        } else {
+            None
+        };
+        if packet.is_some() || data.is_some() {
+            return Ok(Some(PacketData {
+                time: self.last_header.time,
+                direction: self.last_header.direction,
+                protocol_type: self.protocol_type,
+                stream_id: self.last_header.stream_id,
+                packet,
+                data,
+                parse_error: None,
+            }));
+        }
+        let Some((header, data)) = self.read_record()? else {
+            return Ok(None);
+        };
+        let Header {
+            time,
+            direction,
+            stream_id,
+        } = header;
+        self.last_header = header;
+        let mut parse_error = None;
+        let (packet, data) = match self.out_type {
+            OutputType::Packet => {
+                self.read_packet(&data)?;
+                (self.packet_buffer.drain(0..1).next(), None)
+            }
+            OutputType::Raw => {
+                self.read_data(&data)?;
+                (None, self.data_buffer.drain(0..1).next())
+            }
+            OutputType::Both => {
+                let output = self.read_packet(&data);
+                let packet_data = match output {
+                    Ok(_) => self.packet_buffer.drain(0..1).next(),
+                    Err(e) => {
+                        parse_error = Some(e);
+                        None
+                    }
+                };
+                self.read_data(&data)?;
+                (packet_data, self.data_buffer.drain(0..1).next())
+            }
+        };
+        Ok(Some(PacketData {
+            time,
+            direction,
+            protocol_type: self.protocol_type,
+            stream_id,
+            packet,
+            data,
+            parse_error,
+        }))
+    }
+
+    // Returns the underlying reader.
+    pub fn into_inner(mut self) -> R {
+        self.take_reader().into_inner()
+    }
+
+    fn reader(&mut self) -> &mut ReaderWrapper<R> {
+        self.reader
+            .as_mut()
+            .expect("reader should always be present")
+    }
+
+    fn take_reader(&mut self) -> ReaderWrapper<R> {
+        self.reader.take().expect("reader should always be present")
+    }
+
+    fn read_record(&mut self) -> Result<Option<(Header, Vec<u8>)>, PPACError> {
+        if let Some(record) = self.pending_record.take() {
+            return Ok(Some(record));
+        }
+        loop {
+            let offset = self.reader().offset();
+            match self.read_next_record() {
+                Err(error) if self.salvage => {
+                    let offset = match self.is_packed {
+                        true => self.reader().offset(),
+                        false => offset,
+                    };
+                    self.damage.push(Damage {
+                        record: self.record,
+                        offset,
+                        error,
+                    });
+                    if !self.reader().resync()? {
+                        return Ok(None);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn read_next_record(&mut self) -> Result<Option<(Header, Vec<u8>)>, PPACError> {
+        let time = loop {
+            if let Some(time) = self.read_time()? {
+                break time;
+            }
+            if !self.reader().next_frame()? {
+                return Ok(None);
+            }
+        };
+        let direction = match self.reader().read_u8()? {
+            0 => Direction::ToServer,
+            1 => Direction::ToClient,
+            _ if self.salvage => return Err(PPACError::InvalidFile),
+            _ => Direction::ToClient,
+        };
+        let stream_id = match self.version {
+            5.. => self.reader().read_u32::<LittleEndian>()?,
+            _ => 0,
+        };
+        let len = self.reader().read_u64::<LittleEndian>()?;
+        if self.salvage && len > u32::MAX as u64 {
+            return Err(PPACError::InvalidFile);
+        }
+        let mut data = vec![];
+        self.reader().take(len).read_to_end(&mut data)?;
+        if (data.len() as u64) < len {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        self.record += 1;
+        Ok(Some((
+            Header {
+                time,
+                direction,
+                stream_id,
+            },
+            data,
+        )))
+    }
+
+    fn read_filtered(&mut self, filter: &PacketFilter) -> Result<Option<PacketData<P>>, PPACError> {
+        loop {
+            while !self.data_buffer.is_empty() {
+                let data = self.data_buffer.remove(0);
+                if let Some(packet) = self.filter_packet(filter, data)? {
+                    return Ok(Some(packet));
+                }
+            }
+            let Some((header, data)) = self.read_record()? else {
+                return Ok(None);
+            };
+            if filter.matches_record(header.time, header.direction) {
+                self.last_header = header;
+                self.read_data(&data)?;
+            }
+        }
+    }
+
+    fn filter_packet(
+        &mut self,
+        filter: &PacketFilter,
+        data: Vec<u8>,
+    ) -> Result<Option<PacketData<P>>, PPACError> {
+        let header = data.get(4..).and_then(|header| {
+            PacketHeader::read(&mut std::io::Cursor::new(header), self.protocol_type).ok()
+        });
+        if !filter.matches_header::<P>(header.as_ref()) {
+            return Ok(None);
+        }
+        let parse_status = filter.parse_status();
+        let (packet, parse_error) =
+            if !matches!(self.out_type, OutputType::Raw) || parse_status.is_some() {
+                match P::read(&data, self.protocol_type) {
+                    Ok(packets) => (packets.into_iter().next(), None),
+                    Err(e) => (None, Some(e)),
+                }
+            } else {
+                (None, None)
+            };
+        if matches!(parse_status, Some(failed) if failed != parse_error.is_some()) {
+            return Ok(None);
+        }
+        let (packet, data) = match self.out_type {
+            OutputType::Packet => match parse_error {
+                // failed packets weren't explicitly requested
+                Some(e) if parse_status.is_none() => return Err(e.into()),
+                _ => (packet, None),
+            },
+            OutputType::Raw => (None, Some(data)),
+            OutputType::Both => (packet, Some(data)),
+        };
+        Ok(Some(PacketData {
+            time: self.last_header.time,
+            direction: self.last_header.direction,
+            protocol_type: self.protocol_type,
+            stream_id: self.last_header.stream_id,
+            packet,
+            data,
+            parse_error,
+        }))
+    }
+
+    fn read_packet(&mut self, buf: &[u8]) -> Result<(), PacketError> {
+        self.packet_buffer
+            .append(&mut P::read(buf, self.protocol_type)?);
+        Ok(())
+    }
+
+    fn read_data(&mut self, buf: &[u8]) -> Result<(), PacketError> {
+        let packets = Packet::read(buf, PacketType::Raw)?;
+        for packet in packets {
+            let Packet::Raw(raw_data) = packet else {
+                unreachable!()
+            };
+            self.data_buffer.push(raw_data);
+        }
+        Ok(())
+    }
+
+    /// Reads the record timestamp. Returns `None` at the end of the data (or the current frame).
+    fn read_time(&mut self) -> std::io::Result<Option<Duration>> {
+        let mut buf = [0u8; 16];
+        let size = match self.version {
+            2.. => 16,
+            _ => 8,
+        };
+        if !recovery::read_full(self.reader(), &mut buf[..size])? {
+            return Ok(None);
+        }
+        if size == 16 {
+            Ok(Some(Duration::from_nanos(u128::from_le_bytes(buf) as u64)))
+        } else {
+            Ok(Some(Duration::from_secs(
+                (&buf[..8]).read_u64::<LittleEndian>()?,
+            )))
+        }
+    }
+}
+
+impl<R: Read, P: ProtocolRW> Iterator for PPACReader<R, P> {
+    type Item = Result<PacketData<P>, PPACError>;
+
+    /// Reads the next packet. Iteration stops after an IO error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.io_failed {
+            return None;
+        }
+        let result = self.read();
+        if let Err(PPACError::IOError(_)) = result {
+            self.io_failed = true;
+        }
+        result.transpose()
+    }
+}
+
+/// Number of records between index entries of unpacked files.
+const SCAN_INTERVAL: u64 = 1024;
+/// Magic of the zstd skippable frame that stores the index.
+const SKIPPABLE_MAGIC: u32 = 0x184D2A5E;
+/// Magic at the end of the index.
+const INDEX_MAGIC: &[u8; 4] = b"PIDX";
+/// Size of a single index entry.
+const INDEX_ENTRY_SIZE: u64 = 32;
+
+impl<R: Read + Seek, P: ProtocolRW> PPACReader<R, P> {
+    /// Moves the reader to the `n`-th stored packet record (zero based), so that the next call to
+    /// [`PPACReader::read`] returns it. If `n` is past the end of the file then the reader is moved
+    /// to the end.
+    ///
+    /// # Note
+    ///
+    /// The PPAC file must start at the beginning of the reader.
+    pub fn seek_to_packet(&mut self, n: u64) -> Result<(), PPACError> {
+        let entry = self
+            .get_index()?
+            .iter()
+            .take_while(|e| e.record <= n)
+            .last()
+            .copied();
+        self.seek_to_entry(entry)?;
+        while self.record < n {
+            if self.read_record()?.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the reader to the first packet record that was stored at or after `time`, so that
+    /// the next call to [`PPACReader::read`] returns it. Packet records are expected to be stored
+    /// in the chronological order.
+    ///
+    /// # Note
+    ///
+    /// The PPAC file must start at the beginning of the reader.
+    pub fn seek_to_time(&mut self, time: Duration) -> Result<(), PPACError> {
+        let entry = self
+            .get_index()?
+            .iter()
+            .take_while(|e| e.time <= time)
+            .last()
+            .copied();
+        self.seek_to_entry(entry)?;
+        while let Some((header, data)) = self.read_record()? {
+            if header.time >= time {
+                self.pending_record = Some((header, data));
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn seek_to_entry(&mut self, entry: Option<IndexEntry>) -> Result<(), PPACError> {
+        let (record, offset) = match entry {
+            Some(e) => (e.record, e.offset),
+            None => (0, self.data_start),
+        };
+        let mut reader = self.take_reader().into_inner();
+        let result = reader.seek(SeekFrom::Start(offset));
+        self.reader = Some(ReaderWrapper::new(reader, self.is_packed, offset)?);
+        result?;
+        self.record = record;
+        self.pending_record = None;
+        self.packet_buffer.clear();
+        self.data_buffer.clear();
+        self.io_failed = false;
+        Ok(())
+    }
+
+    fn get_index(&mut self) -> Result<&[IndexEntry], PPACError> {
+        if self.index.is_none() {
+            let reader = self
+                .reader
+                .as_mut()
+                .expect("reader should always be present")
+                .get_mut();
+            let pos = reader.stream_position()?;
+            let index = match self.is_packed {
+                true => read_index(reader),
+                false => scan_index(reader, self.data_start, self.version),
+            };
+            // keep the current reader usable if the index can't be read
+            reader.seek(SeekFrom::Start(pos))?;
+            self.index = Some(index?);
+        }
+        Ok(self.index.as_deref().unwrap_or_default())
+    }
+}
+
+/// Reads the index stored at the end of a packed file.
+fn read_index(reader: &mut (impl Read + Seek)) -> std::io::Result<Vec<IndexEntry>> {
+    let len = reader.seek(SeekFrom::End(0))?;
+    if len < 20 {
+        return Ok(vec![]);
+    }
+    reader.seek(SeekFrom::End(-12))?;
+    let count = reader.read_u64::<LittleEndian>()?;
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != INDEX_MAGIC {
+        return Ok(vec![]);
+    }
+    let Some(index_size) = count
+        .checked_mul(INDEX_ENTRY_SIZE)
+        .and_then(|s| s.checked_add(12))
+        .filter(|s| s + 8 <= len)
+    else {
+        return Ok(vec![]);
+    };
+    reader.seek(SeekFrom::Start(len - index_size - 8))?;
+    if reader.read_u32::<LittleEndian>()? != SKIPPABLE_MAGIC
+        || reader.read_u32::<LittleEndian>()? as u64 != index_size
+    {
+        return Ok(vec![]);
+    }
+    let mut index = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        index.push(IndexEntry {
+            record: reader.read_u64::<LittleEndian>()?,
+            time: Duration::from_nanos(reader.read_u128::<LittleEndian>()? as u64),
+            offset: reader.read_u64::<LittleEndian>()?,
+        });
+    }
+    Ok(index)
+}
+
+/// Builds a sparse index of an unpacked file.
+fn scan_index(
+    reader: &mut (impl Read + Seek),
+    data_start: u64,
+    version: u8,
+) -> std::io::Result<Vec<IndexEntry>> {
+    let mut reader = BufReader::new(reader);
+    reader.seek(SeekFrom::Start(data_start))?;
+    let mut index = vec![];
+    let mut offset = data_start;
+    for record in 0.. {
+        let time = match version {
+            2.. => reader
+                .read_u128::<LittleEndian>()
+                .map(|t| Duration::from_nanos(t as u64)),
+            _ => reader.read_u64::<LittleEndian>().map(Duration::from_secs),
+        };
+        let time = match time {
+            Ok(time) => time,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+        if record % SCAN_INTERVAL == 0 {
+            index.push(IndexEntry {
+                record,
+                time,
+                offset,
+            });
+        }
+        reader.read_u8()?;
+        let header_size = match version {
+            5.. => 29,
+            2.. => 25,
+            _ => 17,
+        };
+        if version >= 5 {
+            reader.read_u32::<LittleEndian>()?;
+        }
+        let len = reader.read_u64::<LittleEndian>()?;
+        reader.seek_relative(len as i64)?;
+        offset += header_size + len;
+    }
+    Ok(index)
+}
+
+//--------------------------------------
+// PPAC writer wrapper implementation
+//--------------------------------------
+
+impl<W: Write> PPACWriter<W> {
+    /// Creates a new PPAC file.
+    pub fn new(
+        writer: W,
+        packet_type: PacketType,
+        is_enc: bool,
+    ) -> Result<PPACWriter<W>, PPACError> {
+        Self::with_metadata(writer, packet_type, is_enc, &Metadata::default())
+    }
+
+    /// Creates a new PPAC file with the provided capture metadata.
+    pub fn with_metadata(
+        mut writer: W,
+        packet_type: PacketType,
+        is_enc: bool,
+        metadata: &Metadata,
+    ) -> Result<PPACWriter<W>, PPACError> {
+        writer.write_all(b"PPAC")?;
+        writer.write_u8(MAX_VERSION)?;
+        writer.write_u8(match packet_type {
+            PacketType::Classic => 0,
+            PacketType::NGS => 1,
+            PacketType::NA => 2,
+            PacketType::JP => 3,
+            PacketType::Vita => 4,
+            PacketType::Raw => return Err(PPACError::InvalidPacketType(5)),
+        })?;
+        writer.write_u8(is_enc as u8)?;
+        let metadata = metadata.write();
+        writer.write_u32::<LittleEndian>(metadata.len() as u32)?;
+        writer.write_all(&metadata)?;
+        let data_start = 11 + metadata.len() as u64;
+        let writer = PosWriter {
+            writer,
+            pos: data_start,
+        };
+        let writer = Some(match is_enc {
+            true => WriterWrapper::Zstd(new_encoder(writer)?),
+            false => WriterWrapper::NoEnc(writer),
+        });
+        Ok(Self {
+            writer,
+            packet_type,
+            stream_id: 0,
+            is_packed: is_enc,
+            records: 0,
+            index: vec![],
+            frame_start: data_start,
+            frame_bytes: 0,
+            frame_size: None,
+            frame_time: Duration::ZERO,
+            flush_interval: None,
+        })
+    }
+
+    /// Enables the random access index. Packed data is split into independent zstd frames of
+    /// roughly `frame_size` uncompressed bytes and the frame index is written when the writer is
+    /// finished. Does nothing for unpacked files, as they can be indexed by the reader.
+    ///
+    /// # Note
+    ///
+    /// The file offsets are stored relative to the position of the writer when it was passed to
+    /// [`PPACWriter::new`].
+    pub fn enable_index(&mut self, frame_size: u64) {
+        self.frame_size = Some(frame_size.max(1));
+    }
+
+    /// Enables periodic flushing. After a packet record is written, the current zstd frame is
+    /// ended and the underlying writer is flushed if the record was stored at least `interval`
+    /// after the first record of the frame (see [`Self::flush_frame`]).
+    ///
+    /// The check is only done when packets are written, so [`Self::flush_frame`] should be called
+    /// if no packets are written for a while.
+    pub fn set_flush_interval(&mut self, interval: Option<Duration>) {
+        self.flush_interval = interval;
+    }
+
+    /// Ends the current zstd frame and flushes the underlying writer, so all written packets can
+    /// be read (e.g. by a reader in the salvage mode) even if this writer is never finished. For
+    /// unpacked files only the underlying writer is flushed.
+    pub fn flush_frame(&mut self) -> Result<(), PPACError> {
+        if self.is_packed && self.frame_bytes > 0 {
+            self.end_frame()?;
+        }
+        match self.writer.as_mut().unwrap() {
+            WriterWrapper::NoEnc(w) => w.flush()?,
+            WriterWrapper::Zstd(e) => e.get_mut().flush()?,
+        }
+        Ok(())
+    }
+
+    /// Sets the connection/stream id of the following packets.
+    pub fn set_stream_id(&mut self, stream_id: u32) {
+        self.stream_id = stream_id;
+    }
+
+    fn end_frame(&mut self) -> std::io::Result<()> {
+        let WriterWrapper::Zstd(encoder) = self.writer.take().unwrap() else {
+            unreachable!("packed writer should use zstd")
+        };
+        let writer = encoder.finish()?;
+        self.frame_start = writer.pos;
+        self.frame_bytes = 0;
+        self.writer = Some(WriterWrapper::Zstd(new_encoder(writer)?));
+        Ok(())
+    }
+
+    fn start_record(&mut self, time: Duration) -> Result<(), PPACError> {
+        if let Some(frame_size) = self.frame_size {
+            if self.is_packed && self.frame_bytes >= frame_size {
+                self.end_frame()?;
+            }
+        }
+        if self.frame_bytes == 0 {
+            self.frame_time = time;
+            self.index.push(IndexEntry {
+                record: self.records,
+                time,
+                offset: self.frame_start,
+            });
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    fn end_record(&mut self, time: Duration) -> Result<(), PPACError> {
+        match self.flush_interval {
+            Some(interval) if time.saturating_sub(self.frame_time) >= interval => {
+                self.flush_frame()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(&mut self) -> std::io::Result<Option<W>> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(None);
+        };
+        let mut writer = writer.into_inner()?;
+        if self.is_packed && self.frame_size.is_some() {
+            let index_size = self.index.len() as u64 * INDEX_ENTRY_SIZE + 12;
+            writer.write_u32::<LittleEndian>(SKIPPABLE_MAGIC)?;
+            writer.write_u32::<LittleEndian>(index_size as u32)?;
+            for entry in &self.index {
+                writer.write_u64::<LittleEndian>(entry.record)?;
+                writer.write_u128::<LittleEndian>(entry.time.as_nanos())?;
+                writer.write_u64::<LittleEndian>(entry.offset)?;
+            }
+            writer.write_u64::<LittleEndian>(self.index.len() as u64)?;
+            writer.write_all(INDEX_MAGIC)?;
+        }
+        Ok(Some(writer.writer))
+    }
+    fn write_header(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        len: u64,
+    ) -> Result<(), PPACError> {
+        let writer = self.writer.as_mut().unwrap();
+        writer.write_u128::<LittleEndian>(time.as_nanos())?;
+        writer.write_u8(match direction {
+            Direction::ToServer => 0,
+            Direction::ToClient => 1,
+        })?;
+        writer.write_u32::<LittleEndian>(self.stream_id)?;
+        writer.write_u64::<LittleEndian>(len)?;
+        Ok(())
+    }
+    /// Writes data without checking its length.
+    pub fn write_data_unchecked(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        input: &[u8],
+    ) -> Result<(), PPACError> {
+        self.start_record(time)?;
+        self.write_header(time, direction, input.len() as u64)?;
+        self.writer.as_mut().unwrap().write_all(input)?;
+        self.frame_bytes += 29 + input.len() as u64;
+        self.end_record(time)
+    }
+    /// Writes data (must be valid packet data).
+    pub fn write_data(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        input: &[u8],
+    ) -> Result<(), PPACError> {
+        let buffer_length = input.len();
+        let mut pointer = 0;
+        loop {
+            if pointer >= buffer_length {
+                break;
+            }
+            if input[pointer..].len() <= 4 {
+                break;
+            }
+            let len = (&input[pointer..pointer + 4]).read_u32::<LittleEndian>()? as usize;
+            if input[pointer..].len() < len {
+                return Err(PPACError::CorruptedPacket);
+            }
+            let data = &input[pointer..pointer + len];
+            self.write_data_unchecked(time, direction, data)?;
+            pointer += len;
+        }
+        Ok(())
+    }
+    /// Writes a parsed packet.
+    pub fn write_packet(
+        &mut self,
+        time: Duration,
+        direction: Direction,
+        input: &impl ProtocolRW,
+    ) -> Result<(), PPACError> {
+        let data = input.write(self.packet_type);
+        self.write_data_unchecked(time, direction, &data)?;
+        Ok(())
+    }
+
+    // Returns the underlying writer.
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        Ok(self.finish()?.unwrap())
+    }
+}
+
+impl<W: Write + Seek> PPACWriter<W> {
+    /// Changes stored client type.
+    pub fn change_packet_type(&mut self, packet_type: PacketType) -> Result<(), PPACError> {
+        if matches!(packet_type, PacketType::Raw) {
+            return Err(PPACError::InvalidPacketType(5));
+        }
+        let writer = self.writer.as_mut().unwrap();
+        let curr_pos = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(5))?;
+        writer.write_u8_raw(match packet_type {
+            PacketType::Classic => 0,
+            PacketType::NGS => 1,
+            PacketType::NA => 2,
+            PacketType::JP => 3,
+            PacketType::Vita => 4,
+            PacketType::Raw => unreachable!(),
+        })?;
+        writer.seek(SeekFrom::Start(curr_pos))?;
+        self.packet_type = packet_type;
+        Ok(())
+    }
+}
+
+/// Creates a zstd encoder for a new frame. Frames include checksums, so damaged frames can be
+/// detected while salvaging.
+fn new_encoder<W: Write>(writer: W) -> std::io::Result<Encoder<'static, W>> {
+    let mut encoder = Encoder::new(writer, 3)?;
+    encoder.include_checksum(true)?;
+    Ok(encoder)
+}
+
+impl<W: Write> Drop for PPACWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Packet storage file of a connection. Shared between the split halves of the connection.
+#[cfg(feature = "connection")]
+#[derive(Debug, Clone)]
+pub(crate) enum FileWriter {
+    Blocking(std::sync::Arc<std::sync::Mutex<PPACWriter<std::fs::File>>>),
+    #[cfg(feature = "tokio")]
+    Background(std::sync::Arc<std::sync::Mutex<BackgroundPPACWriter<std::fs::File>>>),
+}
+
+#[cfg(feature = "connection")]
+impl FileWriter {
+    pub(crate) fn write_data(
+        &self,
+        time: Duration,
+        direction: Direction,
+        input: &[u8],
+    ) -> Result<(), PPACError> {
+        match self {
+            Self::Blocking(writer) => writer.lock().unwrap().write_data(time, direction, input),
+            #[cfg(feature = "tokio")]
+            Self::Background(writer) => writer.lock().unwrap().write_data(time, direction, input),
+        }
+    }
+
+    #[cfg(feature = "proxy")]
+    pub(crate) fn write_packet(
+        &self,
+        time: Duration,
+        direction: Direction,
+        input: &impl ProtocolRW,
+    ) -> Result<(), PPACError> {
+        match self {
+            Self::Blocking(writer) => writer.lock().unwrap().write_packet(time, direction, input),
+            #[cfg(feature = "tokio")]
+            Self::Background(writer) => writer.lock().unwrap().write_packet(time, direction, input),
+        }
+    }
+
+    pub(crate) fn change_packet_type(&self, packet_type: PacketType) -> Result<(), PPACError> {
+        match self {
+            Self::Blocking(writer) => writer.lock().unwrap().change_packet_type(packet_type),
+            #[cfg(feature = "tokio")]
+            Self::Background(writer) => writer.lock().unwrap().change_packet_type(packet_type),
+        }
+    }
+}
+
+/// Flush interval of the live capture files.
+#[cfg(feature = "connection")]
+const LIVE_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Creates a packet storage file for a live capture. If `tokio` feature is enabled the file is
+/// written on a separate thread.
+#[cfg(feature = "connection")]
+pub(crate) fn create_file<PT: AsRef<std::path::Path>>(
+    path: PT,
+    packet_type: PacketType,
+) -> Result<FileWriter, PPACError> {
+    let mut writer = PPACWriter::with_metadata(
+        std::fs::File::create(path)?,
+        packet_type,
+        true,
+        &Metadata::live_capture(),
+    )?;
+    writer.set_flush_interval(Some(LIVE_FLUSH_INTERVAL));
+    #[cfg(feature = "tokio")]
+    let writer = FileWriter::Background(std::sync::Arc::new(std::sync::Mutex::new(
+        BackgroundPPACWriter::new(writer),
+    )));
+    #[cfg(not(feature = "tokio"))]
+    let writer = FileWriter::Blocking(std::sync::Arc::new(std::sync::Mutex::new(writer)));
+    Ok(writer)
+}
+
+#[cfg(feature = "connection")]
+pub(crate) fn get_now() -> Duration {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+}
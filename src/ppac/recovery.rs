@@ -0,0 +1,230 @@
+//! Frame-by-frame reading and damage recovery.
+
+use super::PPACError;
+use std::io::{BufRead, BufReader, Read};
+use zstd::stream::Decoder;
+
+/// Magic of the zstd frame (little endian).
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+/// Size of the chunks read while looking for the next frame.
+const SCAN_CHUNK: usize = 0x10000;
+
+/// Damaged part of a file found by a salvaging [`super::PPACReader`].
+#[derive(Debug)]
+pub struct Damage {
+    /// Number of packet records that were read before the damage.
+    pub record: u64,
+    /// Offset where the damage begins. For packed files this is the offset of the damaged zstd
+    /// frame.
+    pub offset: u64,
+    /// Error that revealed the damage.
+    pub error: PPACError,
+}
+
+/// Reader that keeps track of the current position.
+pub(super) struct PosReader<R> {
+    reader: R,
+    pos: u64,
+    /// Data that was returned to the reader.
+    unread: Vec<u8>,
+    stopped: bool,
+}
+
+/// Zstd decoder that stops at the end of every frame.
+pub(super) struct FrameDecoder<R: Read> {
+    decoder: Option<Decoder<'static, BufReader<PosReader<R>>>>,
+    frame_start: u64,
+    finished: bool,
+}
+
+impl<R: Read> PosReader<R> {
+    pub(super) fn new(reader: R, pos: u64) -> Self {
+        Self {
+            reader,
+            pos,
+            unread: vec![],
+            stopped: false,
+        }
+    }
+
+    pub(super) fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub(super) fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Makes all following reads return no data.
+    pub(super) fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    fn unread(&mut self, data: &[u8]) {
+        self.unread.splice(0..0, data.iter().copied());
+        self.pos -= data.len() as u64;
+    }
+}
+
+impl<R: Read> Read for PosReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.stopped {
+            return Ok(0);
+        }
+        let read = if !self.unread.is_empty() {
+            let len = buf.len().min(self.unread.len());
+            buf[..len].copy_from_slice(&self.unread[..len]);
+            self.unread.drain(..len);
+            len
+        } else {
+            self.reader.read(buf)?
+        };
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read> FrameDecoder<R> {
+    pub(super) fn new(reader: R, pos: u64) -> std::io::Result<Self> {
+        Ok(Self {
+            decoder: Some(new_decoder(BufReader::new(PosReader::new(reader, pos)))?),
+            frame_start: pos,
+            finished: false,
+        })
+    }
+
+    /// Offset of the current frame.
+    pub(super) fn frame_start(&self) -> u64 {
+        self.frame_start
+    }
+
+    pub(super) fn into_inner(mut self) -> R {
+        self.take_reader().into_inner().into_inner()
+    }
+
+    /// Returns the underlying reader. Moving it without restoring the position breaks decoding.
+    pub(super) fn get_mut(&mut self) -> &mut R {
+        self.decoder
+            .as_mut()
+            .expect("decoder should always be present")
+            .get_mut()
+            .get_mut()
+            .get_mut()
+    }
+
+    /// Moves to the next frame after the current one was read. Returns `false` if there are no
+    /// more frames.
+    pub(super) fn next_frame(&mut self) -> std::io::Result<bool> {
+        if self.finished {
+            return Ok(false);
+        }
+        let mut reader = self.take_reader();
+        let frame_start = reader.get_ref().position() - reader.buffer().len() as u64;
+        let has_data = reader.fill_buf().map(|b| !b.is_empty());
+        self.decoder = Some(new_decoder(reader)?);
+        self.frame_start = frame_start;
+        self.finished = !has_data?;
+        Ok(!self.finished)
+    }
+
+    /// Skips the rest of the damaged frame and moves to the next one. Returns `false` if no
+    /// frame was found.
+    pub(super) fn resync(&mut self) -> std::io::Result<bool> {
+        if self.finished {
+            return Ok(false);
+        }
+        let reader = self.take_reader();
+        let window = reader.buffer().to_vec();
+        let mut reader = reader.into_inner();
+        let found = find_frame(&mut reader, window, self.frame_start + 1);
+        self.decoder = Some(new_decoder(BufReader::new(reader))?);
+        match found {
+            Ok(Some(frame_start)) => {
+                self.frame_start = frame_start;
+                Ok(true)
+            }
+            Ok(None) => {
+                self.finished = true;
+                Ok(false)
+            }
+            Err(e) => {
+                self.finished = true;
+                Err(e)
+            }
+        }
+    }
+
+    fn take_reader(&mut self) -> BufReader<PosReader<R>> {
+        self.decoder
+            .take()
+            .expect("decoder should always be present")
+            .finish()
+    }
+}
+
+impl<R: Read> Read for FrameDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.finished {
+            return Ok(0);
+        }
+        self.decoder
+            .as_mut()
+            .expect("decoder should always be present")
+            .read(buf)
+    }
+}
+
+fn new_decoder<R: Read>(
+    reader: BufReader<PosReader<R>>,
+) -> std::io::Result<Decoder<'static, BufReader<PosReader<R>>>> {
+    Ok(Decoder::with_buffer(reader)?.single_frame())
+}
+
+/// Looks for the start of a zstd frame at or after `min_offset`. `window` contains data that
+/// was already read from the reader. If the frame is found, the reader is positioned at its start.
+fn find_frame<R: Read>(
+    reader: &mut PosReader<R>,
+    mut window: Vec<u8>,
+    min_offset: u64,
+) -> std::io::Result<Option<u64>> {
+    let mut chunk = vec![0; SCAN_CHUNK];
+    loop {
+        let window_start = reader.position() - window.len() as u64;
+        let skip = min_offset.saturating_sub(window_start) as usize;
+        let found = window
+            .windows(ZSTD_MAGIC.len())
+            .enumerate()
+            .skip(skip)
+            .find(|(_, w)| *w == ZSTD_MAGIC);
+        if let Some((i, _)) = found {
+            reader.unread(&window[i..]);
+            return Ok(Some(window_start + i as u64));
+        }
+        // keep the bytes that might be the start of the magic
+        window.drain(..window.len().saturating_sub(ZSTD_MAGIC.len() - 1));
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        window.extend_from_slice(&chunk[..read]);
+    }
+}
+
+/// Fills the buffer. Returns `false` if the reader had no more data.
+pub(super) fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
@@ -1,17 +1,32 @@
 use crate::{asciistring::StringRW, AsciiString};
 
-use super::{read_magic, write_magic, HelperReadWrite, PacketError};
+use super::{
+    limits::DecodeContext,
+    read_magic,
+    reflect::{Value, ValueMut},
+    schema::TypeSchema,
+    spans::{read_prefixed, SpanKind, SpanRecorder},
+    write_magic, HelperReadWrite, PacketError,
+};
 use half::f16;
-use std::{net::Ipv4Addr, time::Duration};
+use std::{
+    io::{Read, Seek, SeekFrom},
+    net::Ipv4Addr,
+    time::Duration,
+};
 
 macro_rules! helper_int {
-    ($name:ty; $read:ident, $write:ident) => {
+    ($name:ty => $schema:ident; $read:ident, $write:ident) => {
         impl HelperReadWrite for $name {
-            fn read(
+            const SCHEMA: TypeSchema = TypeSchema::$schema;
+
+            fn read_spanned(
                 reader: &mut (impl std::io::Read + std::io::Seek),
                 _: super::PacketType,
                 _: u32,
                 _: u32,
+                _: &mut SpanRecorder,
+                _: &mut DecodeContext,
             ) -> Result<Self, super::PacketError> {
                 let mut buf = [0; std::mem::size_of::<$name>()];
                 reader
@@ -38,25 +53,52 @@ macro_rules! helper_int {
                     error: e,
                 })
             }
+
+            fn as_value(&self) -> Value<'_> {
+                Value::$schema(*self)
+            }
+
+            fn as_value_mut(&mut self) -> ValueMut<'_> {
+                ValueMut::$schema(self)
+            }
         }
     };
-    ($name:ty, $($name_r:ty),+;$read:ident, $write:ident) => {
-        helper_int!($name; $read, $write);
-        helper_int!($($name_r),+; $read, $write);
+    ($name:ty => $schema:ident, $($name_r:ty => $schema_r:ident),+;$read:ident, $write:ident) => {
+        helper_int!($name => $schema; $read, $write);
+        helper_int!($($name_r => $schema_r),+; $read, $write);
     };
 }
 
-helper_int!(u8, i8, u16, i16, u32, i32, u64, i64, u128, i128, f16, f32, f64; from_le_bytes, to_le_bytes);
-helper_int!(Ipv4Addr; from, octets);
+helper_int!(
+    u8 => U8,
+    i8 => I8,
+    u16 => U16,
+    i16 => I16,
+    u32 => U32,
+    i32 => I32,
+    u64 => U64,
+    i64 => I64,
+    u128 => U128,
+    i128 => I128,
+    f16 => F16,
+    f32 => F32,
+    f64 => F64;
+    from_le_bytes, to_le_bytes
+);
+helper_int!(Ipv4Addr => Ipv4Addr; from, octets);
 
 impl<T: HelperReadWrite> HelperReadWrite for Box<T> {
-    fn read(
+    const SCHEMA: TypeSchema = T::SCHEMA;
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: super::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        T::read(reader, packet_type, xor, sub).map(Box::new)
+        T::read_spanned(reader, packet_type, xor, sub, spans, ctx).map(Box::new)
     }
 
     fn write(
@@ -68,26 +110,51 @@ impl<T: HelperReadWrite> HelperReadWrite for Box<T> {
     ) -> Result<(), PacketError> {
         self.as_ref().write(writer, packet_type, xor, sub)
     }
+    fn as_value(&self) -> Value<'_> {
+        self.as_ref().as_value()
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        self.as_mut().as_value_mut()
+    }
 }
 
 impl<T: HelperReadWrite, const N: usize> HelperReadWrite for [T; N] {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::Array {
+        len: N,
+        value: &T::SCHEMA,
+    };
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: super::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut arr = vec![];
         arr.reserve_exact(N);
 
-        for _ in 0..N {
-            arr.push(T::read(reader, packet_type, xor, sub).map_err(|e| {
-                PacketError::CompositeFieldError {
-                    packet_name: "array",
-                    field_name: "value",
-                    error: e.into(),
-                }
-            })?);
+        let map_err = |e| PacketError::FieldError {
+            packet_name: "array",
+            field_name: "value",
+            error: e,
+        };
+        for i in 0..N {
+            spans
+                .begin_index(reader, i, SpanKind::Value)
+                .map_err(map_err)?;
+            arr.push(
+                T::read_spanned(reader, packet_type, xor, sub, spans, ctx).map_err(|e| {
+                    PacketError::CompositeFieldError {
+                        packet_name: "array",
+                        field_name: "value",
+                        error: e.into(),
+                    }
+                })?,
+            );
+            spans.end(reader).map_err(map_err)?;
         }
 
         if let Ok(arr) = arr.try_into() {
@@ -115,14 +182,25 @@ impl<T: HelperReadWrite, const N: usize> HelperReadWrite for [T; N] {
         }
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::List(self.iter().map(|v| v.as_value()).collect())
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::List(self.iter_mut().map(|v| v.as_value_mut()).collect())
+    }
 }
 
 impl HelperReadWrite for Duration {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::Duration;
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: crate::protocol::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
         Ok(Duration::from_secs(
             u32::read(reader, packet_type, 0, 0).map_err(|e| PacketError::CompositeFieldError {
@@ -148,19 +226,35 @@ impl HelperReadWrite for Duration {
                 error: e.into(),
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Duration(*self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Duration(self)
+    }
 }
 
 impl HelperReadWrite for String {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::String;
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
-        <String as StringRW>::read_variable(reader, sub, xor).map_err(|e| PacketError::FieldError {
-            packet_name: "String",
-            field_name: "str",
-            error: e,
+        check_string_len(reader, ctx, "String", xor, sub, 2)?;
+        read_prefixed(reader, spans, "String", |reader| {
+            <String as StringRW>::read_variable(reader, sub, xor).map_err(|e| {
+                PacketError::FieldError {
+                    packet_name: "String",
+                    field_name: "str",
+                    error: e,
+                }
+            })
         })
     }
 
@@ -179,21 +273,35 @@ impl HelperReadWrite for String {
                 error: e,
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::String(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::String(self)
+    }
 }
 
 impl HelperReadWrite for AsciiString {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::AsciiString;
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
-        <AsciiString as StringRW>::read_variable(reader, sub, xor).map_err(|e| {
-            PacketError::FieldError {
-                packet_name: "AsciiString",
-                field_name: "str",
-                error: e,
-            }
+        check_string_len(reader, ctx, "AsciiString", xor, sub, 1)?;
+        read_prefixed(reader, spans, "AsciiString", |reader| {
+            <AsciiString as StringRW>::read_variable(reader, sub, xor).map_err(|e| {
+                PacketError::FieldError {
+                    packet_name: "AsciiString",
+                    field_name: "str",
+                    error: e,
+                }
+            })
         })
     }
 
@@ -212,20 +320,39 @@ impl HelperReadWrite for AsciiString {
                 error: e,
             })
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::String(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::AsciiString(self)
+    }
 }
 
 impl<T: HelperReadWrite> HelperReadWrite for Vec<T> {
-    fn read(
+    const SCHEMA: TypeSchema = TypeSchema::Vec(&T::SCHEMA);
+
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: crate::protocol::PacketType,
         xor: u32,
         sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, crate::protocol::PacketError> {
-        let len = read_magic(reader, sub, xor).map_err(|e| PacketError::FieldError {
-            packet_name: "Vec",
-            field_name: "len",
-            error: e,
-        })?;
+        let map_err = |field_name| {
+            move |e| PacketError::FieldError {
+                packet_name: "Vec",
+                field_name,
+                error: e,
+            }
+        };
+        spans
+            .begin(reader, "len", SpanKind::Length)
+            .map_err(map_err("len"))?;
+        let len = read_magic(reader, sub, xor).map_err(map_err("len"))?;
+        spans.end(reader).map_err(map_err("len"))?;
+        ctx.check_elements("Vec", len as usize, std::mem::size_of::<T>())?;
         let mut data = vec![];
         data.reserve_exact(len as usize);
 
@@ -236,14 +363,20 @@ impl<T: HelperReadWrite> HelperReadWrite for Vec<T> {
                 field_name: "pre_read",
                 error: e,
             })?;
-        for _ in 0..len {
-            data.push(T::read(reader, packet_type, xor, sub).map_err(|e| {
-                PacketError::CompositeFieldError {
-                    packet_name: "Vec",
-                    field_name: "value",
-                    error: e.into(),
-                }
-            })?);
+        for i in 0..len {
+            spans
+                .begin_index(reader, i as usize, SpanKind::Value)
+                .map_err(map_err("value"))?;
+            data.push(
+                T::read_spanned(reader, packet_type, xor, sub, spans, ctx).map_err(|e| {
+                    PacketError::CompositeFieldError {
+                        packet_name: "Vec",
+                        field_name: "value",
+                        error: e.into(),
+                    }
+                })?,
+            );
+            spans.end(reader).map_err(map_err("value"))?;
         }
         let seek2 = reader
             .stream_position()
@@ -253,10 +386,12 @@ impl<T: HelperReadWrite> HelperReadWrite for Vec<T> {
                 error: e,
             })?;
         let len = (seek2 - seek1) as usize;
+        let padding = len.next_multiple_of(4) - len;
+        if padding != 0 {
+            spans.add(seek2, padding as u64, "padding", SpanKind::Padding);
+        }
         reader
-            .seek(std::io::SeekFrom::Current(
-                (len.next_multiple_of(4) - len) as i64,
-            ))
+            .seek(std::io::SeekFrom::Current(padding as i64))
             .map_err(|e| PacketError::PaddingError {
                 packet_name: "Vec",
                 field_name: "padding",
@@ -307,4 +442,33 @@ impl<T: HelperReadWrite> HelperReadWrite for Vec<T> {
 
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::List(self.iter().map(|v| v.as_value()).collect())
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::List(self.iter_mut().map(|v| v.as_value_mut()).collect())
+    }
+}
+
+/// Checks the length of a variable length string before reading it.
+fn check_string_len(
+    reader: &mut (impl Read + Seek),
+    ctx: &mut DecodeContext,
+    packet_name: &'static str,
+    xor: u32,
+    sub: u32,
+    char_size: usize,
+) -> Result<(), PacketError> {
+    if !ctx.is_limited() {
+        return Ok(());
+    }
+    let map_err = |e| PacketError::FieldLengthError {
+        packet_name,
+        field_name: "str",
+        error: e,
+    };
+    let len = read_magic(reader, sub, xor).map_err(map_err)?;
+    reader.seek(SeekFrom::Current(-4)).map_err(map_err)?;
+    ctx.check_string(packet_name, len as usize, char_size)
 }
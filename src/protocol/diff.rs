@@ -0,0 +1,203 @@
+//! Structural comparison of packets.
+//!
+//! Changes are reported by the path of the changed field (e.g. `settings.unk3` or
+//! `other_settings[2].zone_id`). Packets without fields (i.e. `Unknown` or `Raw` packets) and
+//! byte array fields are compared byte by byte. Fields that can't be inspected (i.e. manually
+//! implemented types) are reported as [`ChangeKind::Uncomparable`].
+//!
+//! # Example
+//! ```
+//! use pso2packetlib::protocol::{diff::diff, login::BlockBalancePacket, Packet};
+//!
+//! let a = Packet::BlockBalance(BlockBalancePacket {
+//!     port: 12000,
+//!     ..Default::default()
+//! });
+//! let b = Packet::BlockBalance(BlockBalancePacket {
+//!     port: 12100,
+//!     ..Default::default()
+//! });
+//! let changes = diff(&a, &b);
+//! assert_eq!(changes.len(), 1);
+//! assert_eq!(changes[0].to_string(), "port: 12000 -> 12100");
+//! ```
+
+use super::{
+    reflect::{Reflect, Value},
+    PacketType, ProtocolRW,
+};
+use std::fmt::Display;
+
+/// Change of a packet field.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldChange {
+    /// Path of the changed field. Empty if the whole packet has changed.
+    pub path: String,
+    /// Kind of the change.
+    pub kind: ChangeKind,
+}
+
+/// Kind of a field change.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// Field value has changed.
+    Changed { old: String, new: String },
+    /// List element is only present in the second packet.
+    Added(String),
+    /// List element is only present in the first packet.
+    Removed(String),
+    /// Byte range has changed. Bytes that are only present in one packet are also included.
+    Bytes {
+        offset: usize,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// Field can't be inspected, so it might have changed.
+    Uncomparable,
+}
+
+impl Display for FieldChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() {
+            "<packet>"
+        } else {
+            &self.path
+        };
+        match &self.kind {
+            ChangeKind::Changed { old, new } => write!(f, "{path}: {old} -> {new}"),
+            ChangeKind::Added(value) => write!(f, "{path}: added {value}"),
+            ChangeKind::Removed(value) => write!(f, "{path}: removed {value}"),
+            ChangeKind::Bytes { offset, old, new } => {
+                write!(f, "{path}[{offset:#X}..]: {old:02X?} -> {new:02X?}")
+            }
+            ChangeKind::Uncomparable => write!(f, "{path}: not comparable"),
+        }
+    }
+}
+
+/// Returns the changes between two packets.
+///
+/// Packets of different types are reported as a single change of the packet name. Packets
+/// without fields are compared using their encoded bytes (in the classic format). Fields that
+/// can't be inspected are reported only if the encoded packets differ.
+pub fn diff<P: ProtocolRW>(a: &P, b: &P) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    match (a.as_reflect(), b.as_reflect()) {
+        (Some(ra), Some(rb)) => {
+            diff_structs(&mut String::new(), ra, rb, &mut changes);
+            if changes.iter().any(|c| c.kind == ChangeKind::Uncomparable)
+                && a.write(PacketType::Classic) == b.write(PacketType::Classic)
+            {
+                changes.retain(|c| c.kind != ChangeKind::Uncomparable);
+            }
+        }
+        _ => diff_bytes(
+            "",
+            &a.write(PacketType::Classic),
+            &b.write(PacketType::Classic),
+            &mut changes,
+        ),
+    }
+    changes
+}
+
+/// Returns the changes between two structs. Fields that can't be inspected are always reported
+/// as [`ChangeKind::Uncomparable`].
+pub fn diff_reflect(a: &dyn Reflect, b: &dyn Reflect) -> Vec<FieldChange> {
+    let mut changes = vec![];
+    diff_structs(&mut String::new(), a, b, &mut changes);
+    changes
+}
+
+fn fields(value: &dyn Reflect) -> Vec<(&'static str, Value<'_>)> {
+    let mut fields = vec![];
+    value.visit_fields(&mut |name, value| fields.push((name, value)));
+    fields
+}
+
+fn diff_structs(path: &mut String, a: &dyn Reflect, b: &dyn Reflect, out: &mut Vec<FieldChange>) {
+    if a.type_name() != b.type_name() {
+        out.push(FieldChange {
+            path: path.clone(),
+            kind: ChangeKind::Changed {
+                old: a.type_name().to_string(),
+                new: b.type_name().to_string(),
+            },
+        });
+        return;
+    }
+    let path_len = path.len();
+    for ((name, a), (_, b)) in fields(a).into_iter().zip(fields(b)) {
+        if !path.is_empty() {
+            path.push('.');
+        }
+        path.push_str(name);
+        diff_values(path, &a, &b, out);
+        path.truncate(path_len);
+    }
+}
+
+fn diff_values(path: &mut String, a: &Value, b: &Value, out: &mut Vec<FieldChange>) {
+    match (a, b) {
+        (Value::Struct(a), Value::Struct(b)) => diff_structs(path, *a, *b, out),
+        (Value::Bytes(a), Value::Bytes(b)) => diff_bytes(path, a, b, out),
+        (Value::List(a), Value::List(b)) => {
+            let path_len = path.len();
+            for i in 0..a.len().max(b.len()) {
+                path.push_str(&format!("[{i}]"));
+                match (a.get(i), b.get(i)) {
+                    (Some(a), Some(b)) => diff_values(path, a, b, out),
+                    (Some(a), None) => out.push(FieldChange {
+                        path: path.clone(),
+                        kind: ChangeKind::Removed(a.to_string()),
+                    }),
+                    (None, Some(b)) => out.push(FieldChange {
+                        path: path.clone(),
+                        kind: ChangeKind::Added(b.to_string()),
+                    }),
+                    (None, None) => unreachable!(),
+                }
+                path.truncate(path_len);
+            }
+        }
+        (Value::Opaque, Value::Opaque) => out.push(FieldChange {
+            path: path.clone(),
+            kind: ChangeKind::Uncomparable,
+        }),
+        (a, b) => {
+            let (old, new) = (a.to_string(), b.to_string());
+            if old != new {
+                out.push(FieldChange {
+                    path: path.clone(),
+                    kind: ChangeKind::Changed { old, new },
+                });
+            }
+        }
+    }
+}
+
+fn diff_bytes(path: &str, a: &[u8], b: &[u8], out: &mut Vec<FieldChange>) {
+    let len = a.len().max(b.len());
+    let mut i = 0;
+    while i < len {
+        if a.get(i) == b.get(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && a.get(i) != b.get(i) {
+            i += 1;
+        }
+        let range = |data: &[u8]| data[start.min(data.len())..i.min(data.len())].to_vec();
+        out.push(FieldChange {
+            path: path.to_string(),
+            kind: ChangeKind::Bytes {
+                offset: start,
+                old: range(a),
+                new: range(b),
+            },
+        });
+    }
+}
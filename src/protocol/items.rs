@@ -2,7 +2,13 @@
 use crate::fixed_types::{Bytes, FixedString, FixedVec};
 
 use super::{
+    limits::DecodeContext,
     models::{character::HSVColor, Position},
+    reflect::{
+        impl_reflect, struct_list, struct_list_mut, FieldVisitor, FieldVisitorMut, Reflect, Value,
+        ValueMut,
+    },
+    spans::SpanRecorder,
     HelperReadWrite, ObjectHeader, PacketError, PacketReadWrite, PacketType,
 };
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
@@ -1393,22 +1399,45 @@ pub enum MesetaDirection {
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl Reflect for LoadItemPacket {
+    fn type_name(&self) -> &'static str {
+        "LoadItemPacket"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("items", struct_list(&self.items));
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("items", struct_list_mut(&mut self.items));
+    }
+}
+
+impl_reflect!(NamedId { name, id });
+
 impl PacketReadWrite for LoadItemPacket {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         flags: &super::Flags,
         packet_type: PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        let packet = LoadItemInternal::read(reader, flags, packet_type).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "LoadItemPacket",
-                field_name: "internal",
-                error: Box::new(e),
-            }
+        let packet = LoadItemInternal::read_spanned(
+            reader,
+            flags,
+            packet_type,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "LoadItemPacket",
+            field_name: "internal",
+            error: Box::new(e),
         })?;
         let mut names = packet.names.chars();
         let mut items = vec![];
-        for (id, name_length) in packet.ids.into_iter().zip(packet.name_length.into_iter()) {
+        for (id, name_length) in packet.ids.into_iter().zip(packet.name_length) {
             let name = names.by_ref().take(name_length as usize).collect();
             items.push(NamedId { name, id });
         }
@@ -1438,12 +1467,36 @@ impl PacketReadWrite for LoadItemPacket {
     }
 }
 
+impl Reflect for Item {
+    fn type_name(&self) -> &'static str {
+        "Item"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("uuid", self.uuid.as_value());
+        visitor.visit("id", self.id.as_value());
+        visitor.visit("data", self.data.as_value());
+        #[cfg(feature = "ngs_packets")]
+        visitor.visit("unk", self.unk.as_value());
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("uuid", self.uuid.as_value_mut());
+        visitor.visit("id", self.id.as_value_mut());
+        visitor.visit("data", self.data.as_value_mut());
+        #[cfg(feature = "ngs_packets")]
+        visitor.visit("unk", self.unk.as_value_mut());
+    }
+}
+
 impl HelperReadWrite for Item {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: PacketType,
         xor: u32,
         sub: u32,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let uuid = reader
             .read_u64::<LittleEndian>()
@@ -1452,14 +1505,20 @@ impl HelperReadWrite for Item {
                 field_name: "uuid",
                 error: e,
             })?;
-        let id = ItemId::read(reader, packet_type, xor, sub).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "Item",
-                field_name: "id",
-                error: Box::new(e),
-            }
+        let id = ItemId::read_spanned(
+            reader,
+            packet_type,
+            xor,
+            sub,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "Item",
+            field_name: "id",
+            error: Box::new(e),
         })?;
-        let data = ItemType::read(reader, &id, packet_type).map_err(|e| {
+        let data = ItemType::read(reader, &id, packet_type, ctx).map_err(|e| {
             PacketError::CompositeFieldError {
                 packet_name: "Item",
                 field_name: "data",
@@ -1534,23 +1593,58 @@ impl HelperReadWrite for Item {
         }
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
+}
+
+impl Reflect for ItemData {
+    fn type_name(&self) -> &'static str {
+        "ItemData"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("id", self.id.as_value());
+        visitor.visit("data", self.data.as_value());
+        #[cfg(feature = "ngs_packets")]
+        visitor.visit("unk", self.unk.as_value());
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("id", self.id.as_value_mut());
+        visitor.visit("data", self.data.as_value_mut());
+        #[cfg(feature = "ngs_packets")]
+        visitor.visit("unk", self.unk.as_value_mut());
+    }
 }
 
 impl HelperReadWrite for ItemData {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: PacketType,
         xor: u32,
         sub: u32,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        let id = ItemId::read(reader, packet_type, xor, sub).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "ItemData",
-                field_name: "id",
-                error: Box::new(e),
-            }
+        let id = ItemId::read_spanned(
+            reader,
+            packet_type,
+            xor,
+            sub,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "ItemData",
+            field_name: "id",
+            error: Box::new(e),
         })?;
-        let data = ItemType::read(reader, &id, packet_type).map_err(|e| {
+        let data = ItemType::read(reader, &id, packet_type, ctx).map_err(|e| {
             PacketError::CompositeFieldError {
                 packet_name: "ItemData",
                 field_name: "data",
@@ -1617,14 +1711,75 @@ impl HelperReadWrite for ItemData {
         }
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
 impl ItemType {
+    fn as_value(&self) -> Value<'_> {
+        match self {
+            Self::NoItem => Value::None,
+            Self::Weapon(item) => item.as_value(),
+            Self::Clothing(item) => item.as_value(),
+            Self::Consumable(item) => item.as_value(),
+            Self::Camo(item) => item.as_value(),
+            Self::Unit(item) => item.as_value(),
+            Self::Unknown(data) => data.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::NoItemNGS => Value::None,
+            #[cfg(feature = "ngs_packets")]
+            Self::WeaponNGS(item) => item.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::ClothingNGS(item) => item.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::ConsumableNGS(item) => item.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::CamoNGS(item) => item.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::UnitNGS(item) => item.as_value(),
+            #[cfg(feature = "ngs_packets")]
+            Self::UnknownNGS(data) => data.as_value(),
+        }
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        match self {
+            Self::NoItem => ValueMut::None,
+            Self::Weapon(item) => item.as_value_mut(),
+            Self::Clothing(item) => item.as_value_mut(),
+            Self::Consumable(item) => item.as_value_mut(),
+            Self::Camo(item) => item.as_value_mut(),
+            Self::Unit(item) => item.as_value_mut(),
+            Self::Unknown(data) => data.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::NoItemNGS => ValueMut::None,
+            #[cfg(feature = "ngs_packets")]
+            Self::WeaponNGS(item) => item.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::ClothingNGS(item) => item.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::ConsumableNGS(item) => item.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::CamoNGS(item) => item.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::UnitNGS(item) => item.as_value_mut(),
+            #[cfg(feature = "ngs_packets")]
+            Self::UnknownNGS(data) => data.as_value_mut(),
+        }
+    }
+
     pub(crate) fn read(
         reader: &mut (impl std::io::Read + std::io::Seek),
         item: &ItemId,
         packet_type: PacketType,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
+        let spans = &mut SpanRecorder::disabled();
         Ok(match (item.item_type, packet_type) {
             #[cfg(feature = "ngs_packets")]
             (0, PacketType::NGS) => {
@@ -1638,21 +1793,50 @@ impl ItemType {
                 Self::NoItemNGS
             }
             #[cfg(feature = "ngs_packets")]
-            (1, PacketType::NGS) => {
-                Self::WeaponNGS(WeaponItemNGS::read(reader, packet_type, 0, 0)?)
-            }
+            (1, PacketType::NGS) => Self::WeaponNGS(WeaponItemNGS::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             #[cfg(feature = "ngs_packets")]
-            (2, PacketType::NGS) => {
-                Self::ClothingNGS(ClothingNGSItem::read(reader, packet_type, 0, 0)?)
-            }
+            (2, PacketType::NGS) => Self::ClothingNGS(ClothingNGSItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             #[cfg(feature = "ngs_packets")]
-            (3, PacketType::NGS) => {
-                Self::ConsumableNGS(ConsumableNGSItem::read(reader, packet_type, 0, 0)?)
-            }
+            (3, PacketType::NGS) => Self::ConsumableNGS(ConsumableNGSItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             #[cfg(feature = "ngs_packets")]
-            (5, PacketType::NGS) => Self::UnitNGS(UnitItemNGS::read(reader, packet_type, 0, 0)?),
+            (5, PacketType::NGS) => Self::UnitNGS(UnitItemNGS::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             #[cfg(feature = "ngs_packets")]
-            (10, PacketType::NGS) => Self::CamoNGS(CamoNGSItem::read(reader, packet_type, 0, 0)?),
+            (10, PacketType::NGS) => Self::CamoNGS(CamoNGSItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             #[cfg(feature = "ngs_packets")]
             (_, PacketType::NGS) => Self::UnknownNGS({
                 let mut tmp = [0u8; 0x38];
@@ -1675,11 +1859,46 @@ impl ItemType {
                     })?;
                 Self::NoItem
             }
-            (1, _) => Self::Weapon(WeaponItem::read(reader, packet_type, 0, 0)?),
-            (2, _) => Self::Clothing(ClothingItem::read(reader, packet_type, 0, 0)?),
-            (3, _) => Self::Consumable(ConsumableItem::read(reader, packet_type, 0, 0)?),
-            (5, _) => Self::Unit(UnitItem::read(reader, packet_type, 0, 0)?),
-            (10, _) => Self::Camo(CamoItem::read(reader, packet_type, 0, 0)?),
+            (1, _) => Self::Weapon(WeaponItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
+            (2, _) => Self::Clothing(ClothingItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
+            (3, _) => Self::Consumable(ConsumableItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
+            (5, _) => Self::Unit(UnitItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
+            (10, _) => Self::Camo(CamoItem::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                spans,
+                ctx,
+            )?),
             _ => Self::Unknown({
                 let mut tmp = [0u8; 0x28];
                 reader
@@ -1767,6 +1986,8 @@ fn read_packed_affixes(
     _: PacketType,
     _: u32,
     _: u32,
+    _: &mut SpanRecorder,
+    _: &mut DecodeContext,
 ) -> Result<[u16; 8], PacketError> {
     let mut packed = [0u8; 12];
     let mut affixes = vec![];
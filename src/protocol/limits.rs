@@ -0,0 +1,217 @@
+//! Limits of the data allocated while decoding packets.
+//!
+//! Lengths of variable sized fields (i.e. [`Vec`], [`String`] or [`crate::fixed_types::Bytes`])
+//! are read from the input, so a malicious peer can make the reader allocate large buffers.
+//! Packets read using [`super::ProtocolRW::read_limited`] (or the `read_spanned` functions with a
+//! limited [`DecodeContext`]) check these lengths before allocating and return
+//! [`PacketError::LimitError`] if a limit is exceeded. Manually implemented read functions must
+//! pass the context to the types they read.
+//!
+//! # Example
+//! ```
+//! use pso2packetlib::protocol::{
+//!     limits::{DecodeLimits, LimitKind},
+//!     unk19::SystemMessagePacket,
+//!     Packet, PacketError, PacketType, ProtocolRW,
+//! };
+//!
+//! let packet = Packet::SystemMessage(SystemMessagePacket {
+//!     message: "a".repeat(100),
+//!     ..Default::default()
+//! });
+//! let data = packet.write(PacketType::NGS);
+//! let limits = DecodeLimits {
+//!     max_string_len: 64,
+//!     ..Default::default()
+//! };
+//! let error = Packet::read_limited(&data, PacketType::NGS, &limits).unwrap_err();
+//! assert!(matches!(
+//!     error,
+//!     PacketError::LimitError {
+//!         kind: LimitKind::StringLength,
+//!         ..
+//!     }
+//! ));
+//! ```
+
+use super::PacketError;
+use std::fmt::Display;
+
+/// Limits of the decoded data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum number of elements in a list or a byte array.
+    pub max_elements: usize,
+    /// Maximum length of a string in characters.
+    pub max_string_len: usize,
+    /// Maximum number of bytes allocated for one packet.
+    pub max_alloc: usize,
+}
+
+/// Kind of an exceeded limit.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// Number of elements. (see [`DecodeLimits::max_elements`])
+    Elements,
+    /// String length. (see [`DecodeLimits::max_string_len`])
+    StringLength,
+    /// Allocated bytes. (see [`DecodeLimits::max_alloc`])
+    Allocation,
+}
+
+/// Decode limits applied while reading packets. Passed through the `read_spanned` functions of
+/// the packet traits.
+#[derive(Debug, Default, Clone)]
+pub struct DecodeContext {
+    limits: Option<DecodeLimits>,
+    allocated: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_elements: 0x10000,
+            max_string_len: 0x1000,
+            max_alloc: 0x100_0000,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Creates limits that don't restrict anything.
+    pub const fn unlimited() -> Self {
+        Self {
+            max_elements: usize::MAX,
+            max_string_len: usize::MAX,
+            max_alloc: usize::MAX,
+        }
+    }
+}
+
+impl Display for LimitKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Elements => "element count",
+            Self::StringLength => "string length",
+            Self::Allocation => "allocation",
+        })
+    }
+}
+
+impl DecodeContext {
+    /// Creates a context that applies the provided limits.
+    pub fn new(limits: DecodeLimits) -> Self {
+        Self {
+            limits: Some(limits),
+            allocated: 0,
+        }
+    }
+
+    /// Creates a context that doesn't check anything.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Returns the applied limits.
+    pub fn limits(&self) -> Option<&DecodeLimits> {
+        self.limits.as_ref()
+    }
+
+    /// Returns `true` if the limits are applied.
+    pub fn is_limited(&self) -> bool {
+        self.limits.is_some()
+    }
+
+    /// Resets the allocation counter (i.e. before reading the next packet).
+    pub fn reset_allocated(&mut self) {
+        self.allocated = 0;
+    }
+
+    /// Checks the number of elements of `size` bytes before allocating them.
+    pub fn check_elements(
+        &mut self,
+        packet_name: &'static str,
+        len: usize,
+        size: usize,
+    ) -> Result<(), PacketError> {
+        self.check(
+            packet_name,
+            LimitKind::Elements,
+            len,
+            len.saturating_mul(size),
+        )
+    }
+
+    /// Checks the length of a string of `size` byte characters before allocating it.
+    pub fn check_string(
+        &mut self,
+        packet_name: &'static str,
+        len: usize,
+        size: usize,
+    ) -> Result<(), PacketError> {
+        self.check(
+            packet_name,
+            LimitKind::StringLength,
+            len,
+            len.saturating_mul(size),
+        )
+    }
+
+    fn check(
+        &mut self,
+        packet_name: &'static str,
+        kind: LimitKind,
+        len: usize,
+        bytes: usize,
+    ) -> Result<(), PacketError> {
+        let Some(limits) = &self.limits else {
+            return Ok(());
+        };
+        let max = match kind {
+            LimitKind::Elements => limits.max_elements,
+            LimitKind::StringLength => limits.max_string_len,
+            LimitKind::Allocation => limits.max_alloc,
+        };
+        if len > max {
+            return Err(PacketError::LimitError {
+                packet_name,
+                kind,
+                len,
+                max,
+            });
+        }
+        self.allocated = self.allocated.saturating_add(bytes);
+        if self.allocated > limits.max_alloc {
+            return Err(PacketError::LimitError {
+                packet_name,
+                kind: LimitKind::Allocation,
+                len: self.allocated,
+                max: limits.max_alloc,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Returns the limit error that caused `error` (if any).
+pub(crate) fn root_limit_error(error: PacketError) -> PacketError {
+    let mut inner = &error;
+    while let PacketError::CompositeFieldError { error, .. } = inner {
+        inner = error;
+    }
+    match *inner {
+        PacketError::LimitError {
+            packet_name,
+            kind,
+            len,
+            max,
+        } => PacketError::LimitError {
+            packet_name,
+            kind,
+            len,
+            max,
+        },
+        _ => error,
+    }
+}
@@ -4,7 +4,10 @@ use super::models::FunValue;
 use super::{
     items::Item,
     items::ItemId,
+    limits::DecodeContext,
     models::{character::Character, SGValue},
+    reflect::{impl_reflect, FieldVisitor, FieldVisitorMut, Reflect, Value, ValueMut},
+    spans::SpanRecorder,
     Flags, HelperReadWrite, ObjectHeader, ObjectType, PacketError, PacketHeader, PacketReadWrite,
     PacketType,
 };
@@ -1385,11 +1388,59 @@ pub enum CharacterCreationStatus {
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl Reflect for CharacterListPacket {
+    fn type_name(&self) -> &'static str {
+        "CharacterListPacket"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("characters", self.characters.as_value());
+        visitor.visit("equiped_items", self.equiped_items.as_value());
+        visitor.visit("play_times", self.play_times.as_value());
+        visitor.visit("deletion_flags", flag_list(&self.deletion_flags));
+        visitor.visit("transfer_flags", flag_list(&self.transfer_flags));
+        visitor.visit("account_accessory", self.account_accessory.as_value());
+        visitor.visit("login_survey", self.login_survey.as_value());
+        visitor.visit("ad", self.ad.as_value());
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("characters", self.characters.as_value_mut());
+        visitor.visit("equiped_items", self.equiped_items.as_value_mut());
+        visitor.visit("play_times", self.play_times.as_value_mut());
+        visitor.visit("deletion_flags", flag_list_mut(&mut self.deletion_flags));
+        visitor.visit("transfer_flags", flag_list_mut(&mut self.transfer_flags));
+        visitor.visit("account_accessory", self.account_accessory.as_value_mut());
+        visitor.visit("login_survey", self.login_survey.as_value_mut());
+        visitor.visit("ad", self.ad.as_value_mut());
+    }
+}
+
+fn flag_list(flags: &[(u32, u32)]) -> Value<'_> {
+    Value::List(
+        flags
+            .iter()
+            .map(|&(flag, time)| Value::List(vec![Value::U32(flag), Value::U32(time)]))
+            .collect(),
+    )
+}
+
+fn flag_list_mut(flags: &mut [(u32, u32)]) -> ValueMut<'_> {
+    ValueMut::List(
+        flags
+            .iter_mut()
+            .map(|(flag, time)| ValueMut::List(vec![ValueMut::U32(flag), ValueMut::U32(time)]))
+            .collect(),
+    )
+}
+
 impl PacketReadWrite for CharacterListPacket {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl Read + Seek),
         _: &Flags,
         packet_type: PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let char_amount = reader
             .read_u32::<LittleEndian>()
@@ -1415,12 +1466,18 @@ impl PacketReadWrite for CharacterListPacket {
                     field_name: "vec_characters_value",
                     error: e,
                 })?;
-            let character = Character::read(reader, packet_type, 0, 0).map_err(|e| {
-                PacketError::CompositeFieldError {
-                    packet_name: "CharacterListPacket",
-                    field_name: "vec_characters_value",
-                    error: Box::new(e),
-                }
+            let character = Character::read_spanned(
+                reader,
+                packet_type,
+                0,
+                0,
+                &mut SpanRecorder::disabled(),
+                ctx,
+            )
+            .map_err(|e| PacketError::CompositeFieldError {
+                packet_name: "CharacterListPacket",
+                field_name: "vec_characters_value",
+                error: Box::new(e),
             })?;
             if i < char_amount {
                 characters.push(character);
@@ -1698,16 +1755,17 @@ impl PacketReadWrite for CharacterListPacket {
     }
 }
 
+impl_reflect!(EncryptionRequestPacket { rsa_data });
+
 impl PacketReadWrite for EncryptionRequestPacket {
-    fn read(reader: &mut impl Read, _: &Flags, _: PacketType) -> Result<Self, PacketError> {
-        let mut rsa_data = vec![];
-        reader
-            .read_to_end(&mut rsa_data)
-            .map_err(|e| PacketError::FieldError {
-                packet_name: "EncryptionRequestPacket",
-                field_name: "rsa_data",
-                error: e,
-            })?;
+    fn read_spanned(
+        reader: &mut (impl Read + Seek),
+        _: &Flags,
+        _: PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, PacketError> {
+        let rsa_data = read_remaining(reader, ctx, "EncryptionRequestPacket", "rsa_data")?;
         let mut tmp_data = vec![];
         let mut iter = rsa_data.into_iter().rev().skip(4);
         if let Some(x) = iter.find(|x| *x != 0x00) {
@@ -1728,16 +1786,17 @@ impl PacketReadWrite for EncryptionRequestPacket {
     }
 }
 
+impl_reflect!(EncryptionResponsePacket { data });
+
 impl PacketReadWrite for EncryptionResponsePacket {
-    fn read(reader: &mut impl Read, _: &Flags, _: PacketType) -> Result<Self, PacketError> {
-        let mut data = vec![];
-        reader
-            .read_to_end(&mut data)
-            .map_err(|e| PacketError::FieldError {
-                packet_name: "EncryptionResponsePacket",
-                field_name: "data",
-                error: e,
-            })?;
+    fn read_spanned(
+        reader: &mut (impl Read + Seek),
+        _: &Flags,
+        _: PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
+    ) -> Result<Self, PacketError> {
+        let data = read_remaining(reader, ctx, "EncryptionResponsePacket", "data")?;
 
         Ok(Self { data: data.into() })
     }
@@ -1748,6 +1807,29 @@ impl PacketReadWrite for EncryptionResponsePacket {
     }
 }
 
+/// Reads the rest of the packet body, checking its length against the decode limits.
+fn read_remaining(
+    reader: &mut (impl Read + Seek),
+    ctx: &mut DecodeContext,
+    packet_name: &'static str,
+    field_name: &'static str,
+) -> Result<Vec<u8>, PacketError> {
+    let map_err = |error| PacketError::FieldError {
+        packet_name,
+        field_name,
+        error,
+    };
+    let pos = reader.stream_position().map_err(map_err)?;
+    let end = reader.seek(std::io::SeekFrom::End(0)).map_err(map_err)?;
+    reader
+        .seek(std::io::SeekFrom::Start(pos))
+        .map_err(map_err)?;
+    ctx.check_elements(packet_name, end.saturating_sub(pos) as usize, 1)?;
+    let mut data = vec![];
+    reader.read_to_end(&mut data).map_err(map_err)?;
+    Ok(data)
+}
+
 // ----------------------------------------------------------------
 // Default implementations
 // ----------------------------------------------------------------
@@ -8,6 +8,21 @@ use std::io::{Read, Seek};
 mod traits;
 pub use traits::*;
 
+// Packet layouts
+pub mod schema;
+
+// Field locations
+pub mod spans;
+
+// Field visitors
+pub mod reflect;
+
+// Packet comparison
+pub mod diff;
+
+// Decoding limits
+pub mod limits;
+
 // Packet definitions modules
 pub mod chat;
 pub mod colfolder;
@@ -128,10 +143,33 @@ pub enum PacketError {
         #[source]
         error: std::io::Error,
     },
+    /// Packet body was not fully read.
+    #[error("{len} bytes left unread after reading {packet_name}")]
+    TrailingDataError {
+        packet_name: &'static str,
+        len: usize,
+    },
+    /// Length of a field exceeds a decoding limit.
+    #[error("{kind} limit exceeded in {packet_name}: {len} > {max}")]
+    LimitError {
+        packet_name: &'static str,
+        kind: limits::LimitKind,
+        len: usize,
+        max: usize,
+    },
     // #[error(transparent)]
     // Io(#[from] std::io::Error),
 }
 
+/// Direction of the packet.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    ToServer,
+    ToClient,
+}
+
 /// Type of the packet.
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
@@ -1539,6 +1577,7 @@ pub enum Packet {
 }
 
 /// Known packet categories
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub enum PacketCategory {
     #[default]
@@ -1606,6 +1645,19 @@ pub enum PacketCategory {
     MissionPass,
 }
 
+impl PacketCategory {
+    /// Returns the category of the packet with the provided id and subid as declared in
+    /// [`ProtocolRW::PACKETS`]. Unknown subids get the category of the other packets with the same
+    /// id.
+    pub fn from_id<P: ProtocolRW>(id: u8, subid: u16) -> Self {
+        P::PACKETS
+            .iter()
+            .find(|p| p.id == id && p.subid == subid)
+            .or_else(|| P::PACKETS.iter().find(|p| p.id == id))
+            .map_or(Self::Unknown, |p| p.category)
+    }
+}
+
 // ----------------------------------------------------------------
 // PacketEncryption impls
 // ----------------------------------------------------------------
@@ -1627,6 +1679,13 @@ impl PacketEncryption for Packet {
             None
         }
     }
+    fn as_enc_response(&self) -> Option<&[u8]> {
+        if let Self::EncryptionResponse(data) = self {
+            Some(&data.data)
+        } else {
+            None
+        }
+    }
 }
 
 // ----------------------------------------------------------------
@@ -1772,7 +1831,7 @@ pub struct ObjectHeader {
 #[doc(hidden)]
 #[inline(always)]
 pub fn read_magic(reader: &mut impl Read, sub: u32, xor: u32) -> std::io::Result<u32> {
-    Ok((reader.read_u32::<LittleEndian>()? ^ xor) - sub)
+    Ok((reader.read_u32::<LittleEndian>()? ^ xor).wrapping_sub(sub))
 }
 #[doc(hidden)]
 #[inline(always)]
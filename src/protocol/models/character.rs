@@ -1,7 +1,12 @@
 //! Character related structures.
 use crate::{
     asciistring::StringRW,
-    protocol::{HelperReadWrite, PacketError, PacketType},
+    protocol::{
+        limits::DecodeContext,
+        reflect::{impl_reflect, Value, ValueMut},
+        spans::SpanRecorder,
+        HelperReadWrite, PacketError, PacketType,
+    },
 };
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::io::{Read, Seek, Write};
@@ -357,12 +362,28 @@ pub struct ClassInfo {
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl_reflect!(Character {
+    character_id,
+    player_id,
+    unk1,
+    voice_type,
+    unk2,
+    voice_pitch,
+    name,
+    look,
+    unk3,
+    classes,
+    unk4,
+});
+
 impl HelperReadWrite for Character {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl Read + Seek),
         packet_type: PacketType,
         xor: u32,
         sub: u32,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let character_id =
             reader
@@ -425,12 +446,18 @@ impl HelperReadWrite for Character {
                 })?;
         }
 
-        let look = Look::read(reader, packet_type, xor, sub).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "Character",
-                field_name: "look",
-                error: Box::new(e),
-            }
+        let look = Look::read_spanned(
+            reader,
+            packet_type,
+            xor,
+            sub,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "Character",
+            field_name: "look",
+            error: Box::new(e),
         })?;
         let unk3 = reader
             .read_u32::<LittleEndian>()
@@ -439,12 +466,18 @@ impl HelperReadWrite for Character {
                 field_name: "unk3",
                 error: e,
             })?;
-        let classes = ClassInfo::read(reader, packet_type, xor, sub).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "Character",
-                field_name: "classes",
-                error: Box::new(e),
-            }
+        let classes = ClassInfo::read_spanned(
+            reader,
+            packet_type,
+            xor,
+            sub,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "Character",
+            field_name: "classes",
+            error: Box::new(e),
         })?;
 
         let unk4 = String::read_fixed(reader, 32).map_err(|e| PacketError::FieldError {
@@ -598,6 +631,13 @@ impl HelperReadWrite for Character {
 
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
 // ----------------------------------------------------------------
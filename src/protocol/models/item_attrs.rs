@@ -2,7 +2,12 @@
 use super::character::ClassFlags;
 use crate::{
     fixed_types::{FixedBytes, FixedVec, VecUSize},
-    protocol::{HelperReadWrite, PacketError, PacketType},
+    protocol::{
+        limits::DecodeContext,
+        reflect::{impl_reflect, Value, ValueMut},
+        spans::SpanRecorder,
+        HelperReadWrite, PacketError, PacketType,
+    },
 };
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 
@@ -546,12 +551,16 @@ impl ItemAttributesVita {
     }
 }
 
+impl_reflect!(GenderDmg { force_dmg, gender });
+
 impl HelperReadWrite for GenderDmg {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         pt: PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let bits = reader
             .read_u16::<LittleEndian>()
@@ -564,12 +573,18 @@ impl HelperReadWrite for GenderDmg {
         // hacky solution but it works
         let gender_bits = (bits >> 14) as u8;
         let mut gender_slice = std::io::Cursor::new(std::slice::from_ref(&gender_bits));
-        let gender = GenderFlags::read(&mut gender_slice, pt, 0, 0).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "GenderDmg",
-                field_name: "gender",
-                error: Box::new(e),
-            }
+        let gender = GenderFlags::read_spanned(
+            &mut gender_slice,
+            pt,
+            0,
+            0,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "GenderDmg",
+            field_name: "gender",
+            error: Box::new(e),
         })?;
         Ok(Self { force_dmg, gender })
     }
@@ -601,14 +616,40 @@ impl HelperReadWrite for GenderDmg {
             })?;
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
+impl_reflect!(UnitRes {
+    tec_res,
+    tec_def,
+    rng_def,
+    mel_def,
+    hp,
+    pp,
+    dark_res,
+    light_res,
+    wind_res,
+    lightning_res,
+    ice_res,
+    fire_res,
+    rng_res,
+    mel_res,
+});
+
 impl HelperReadWrite for UnitRes {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut bytes = [0u8; 16];
         reader
@@ -712,14 +753,31 @@ impl HelperReadWrite for UnitRes {
             })?;
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
+impl_reflect!(UnitAtk {
+    mel_atk,
+    rng_atk,
+    tec_atk,
+    dex,
+    unk_atk,
+});
+
 impl HelperReadWrite for UnitAtk {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut bytes = [0u8; 8];
         reader
@@ -774,6 +832,13 @@ impl HelperReadWrite for UnitAtk {
             })?;
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
 impl Default for ShortData {
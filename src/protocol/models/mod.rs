@@ -4,7 +4,12 @@ pub mod character;
 #[cfg_attr(docsrs, doc(cfg(feature = "item_attrs")))]
 pub mod item_attrs;
 
-use super::{PacketError, PacketType};
+use super::{
+    limits::DecodeContext,
+    reflect::{impl_reflect, Value, ValueMut},
+    spans::SpanRecorder,
+    PacketError, PacketType,
+};
 use crate::protocol::HelperReadWrite;
 use half::f16;
 
@@ -68,14 +73,32 @@ pub struct FunValue(pub u32);
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl_reflect!(EulerPosition {
+    roll,
+    pitch,
+    yaw,
+    x,
+    y,
+    z,
+});
+
 impl HelperReadWrite for EulerPosition {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: PacketType,
         xor: u32,
         sub: u32,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        let pos = Position::read(reader, packet_type, xor, sub)?;
+        let pos = Position::read_spanned(
+            reader,
+            packet_type,
+            xor,
+            sub,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )?;
         Ok(pos.into())
     }
 
@@ -89,14 +112,23 @@ impl HelperReadWrite for EulerPosition {
         let pos: Position = (*self).into();
         pos.write(writer, packet_type, xor, sub)
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::Struct(self)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Struct(self)
+    }
 }
 
 impl HelperReadWrite for SGValue {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut buf = [0u8; 4];
         reader
@@ -139,14 +171,23 @@ impl HelperReadWrite for SGValue {
             })?;
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::F32(self.0)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::F32(&mut self.0)
+    }
 }
 
 impl HelperReadWrite for FunValue {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         _: PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut buf = [0u8; 4];
         reader
@@ -187,6 +228,13 @@ impl HelperReadWrite for FunValue {
             })?;
         Ok(())
     }
+    fn as_value(&self) -> Value<'_> {
+        Value::U32(self.0)
+    }
+
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::U32(&mut self.0)
+    }
 }
 
 // ----------------------------------------------------------------
@@ -1,9 +1,12 @@
 //! Object related packets. \[0x04\]
 use super::{
+    limits::DecodeContext,
     models::{
         character::{Class, ClassInfo},
         Position,
     },
+    reflect::impl_reflect,
+    spans::SpanRecorder,
     Flags, ObjectHeader, PacketError, PacketHeader, PacketReadWrite, PacketType,
 };
 use crate::{fixed_types::FixedBytes, AsciiString};
@@ -681,12 +684,39 @@ pub struct Unk04EAPacket {
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl_reflect!(MovementPacket {
+    unk,
+    ent1_id,
+    ent1_type,
+    ent1_unk,
+    ent2_id,
+    ent2_type,
+    ent2_unk,
+    timestamp,
+    rot_x,
+    rot_y,
+    rot_z,
+    rot_w,
+    cur_x,
+    cur_y,
+    cur_z,
+    unk1,
+    unk_x,
+    unk_y,
+    unk_z,
+    unk2,
+    unk3,
+    unk4,
+});
+
 //yikes
 impl PacketReadWrite for MovementPacket {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl Read + Seek),
         flags: &Flags,
         _: PacketType,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let mut packet = Self::default();
         reader
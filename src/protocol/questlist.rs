@@ -1,7 +1,7 @@
 //! Quest list related packets. \[0x0B\]
 use super::{
-    CollectionFolderItem, CollectionFolderProgress, HelperReadWrite, Item, ObjectHeader,
-    PacketReadWrite,
+    limits::DecodeContext, spans::SpanRecorder, CollectionFolderItem, CollectionFolderProgress,
+    HelperReadWrite, Item, ObjectHeader, PacketReadWrite,
 };
 use crate::{
     fixed_types::{FixedAsciiString, FixedBytes, FixedVec},
@@ -989,11 +989,13 @@ impl Default for UnlockedQuest {
 // ----------------------------------------------------------------
 
 impl HelperReadWrite for RevealedRegions {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         packet_type: super::PacketType,
         _: u32,
         _: u32,
+        _: &mut SpanRecorder,
+        _: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
         let data = <[u8; 10]>::read(reader, packet_type, 0, 0).map_err(|e| {
             PacketError::CompositeFieldError {
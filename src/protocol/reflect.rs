@@ -0,0 +1,285 @@
+//! Runtime access to packet fields, generated by the derive macros.
+//!
+//! Every derived struct implements [`Reflect`], which passes the names and values of its fields to
+//! a [`FieldVisitor`] (or a [`FieldVisitorMut`] for in-place modification). Values of field types
+//! are returned by [`super::HelperReadWrite::as_value`] and
+//! [`super::HelperReadWrite::as_value_mut`].
+//!
+//! # Example
+//! ```
+//! use pso2packetlib::protocol::{
+//!     login::BlockBalancePacket,
+//!     reflect::{Reflect, Value},
+//! };
+//!
+//! let packet = BlockBalancePacket {
+//!     port: 12000,
+//!     ..Default::default()
+//! };
+//! let mut port = None;
+//! packet.visit_fields(&mut |name, value: Value| {
+//!     if name == "port" {
+//!         port = value.as_u128();
+//!     }
+//! });
+//! assert_eq!(port, Some(12000));
+//! ```
+
+use super::HelperReadWrite;
+use crate::AsciiString;
+use half::f16;
+use std::{
+    fmt::{Debug, Display},
+    net::Ipv4Addr,
+    time::Duration,
+};
+
+/// Struct with visitable fields.
+pub trait Reflect {
+    /// Returns the name of the struct.
+    fn type_name(&self) -> &'static str;
+    /// Passes every field to the visitor in the order they are read.
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>);
+    /// Passes every field to the visitor in the order they are read, allowing modification.
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>);
+}
+
+/// Visitor of struct fields.
+pub trait FieldVisitor<'a> {
+    /// Visits a field. Tuple struct fields are named by their index.
+    fn visit(&mut self, name: &'static str, value: Value<'a>);
+}
+
+/// Visitor of mutable struct fields.
+pub trait FieldVisitorMut<'a> {
+    /// Visits a field. Tuple struct fields are named by their index.
+    fn visit(&mut self, name: &'static str, value: ValueMut<'a>);
+}
+
+/// Value of a field.
+#[derive(Debug, Clone)]
+pub enum Value<'a> {
+    Bool(bool),
+    U8(u8),
+    I8(i8),
+    U16(u16),
+    I16(i16),
+    U32(u32),
+    I32(i32),
+    U64(u64),
+    I64(i64),
+    U128(u128),
+    I128(i128),
+    F16(f16),
+    F32(f32),
+    F64(f64),
+    Ipv4Addr(Ipv4Addr),
+    Duration(Duration),
+    /// UTF-16 or ASCII string.
+    String(&'a str),
+    /// Byte array.
+    Bytes(&'a [u8]),
+    /// Name of the enum variant.
+    Enum(&'static str),
+    /// Nested struct.
+    Struct(&'a dyn Reflect),
+    /// Array of values.
+    List(Vec<Value<'a>>),
+    /// Missing optional value.
+    None,
+    /// Value of an unknown type (i.e. manually implemented).
+    Opaque,
+}
+
+/// Mutable value of a field.
+#[derive(Debug)]
+pub enum ValueMut<'a> {
+    Bool(&'a mut bool),
+    U8(&'a mut u8),
+    I8(&'a mut i8),
+    U16(&'a mut u16),
+    I16(&'a mut i16),
+    U32(&'a mut u32),
+    I32(&'a mut i32),
+    U64(&'a mut u64),
+    I64(&'a mut i64),
+    U128(&'a mut u128),
+    I128(&'a mut i128),
+    F16(&'a mut f16),
+    F32(&'a mut f32),
+    F64(&'a mut f64),
+    Ipv4Addr(&'a mut Ipv4Addr),
+    Duration(&'a mut Duration),
+    /// UTF-16 string.
+    String(&'a mut String),
+    /// ASCII string.
+    AsciiString(&'a mut AsciiString),
+    /// Byte array.
+    Bytes(&'a mut Vec<u8>),
+    /// Nested struct.
+    Struct(&'a mut dyn Reflect),
+    /// Array of values.
+    List(Vec<ValueMut<'a>>),
+    /// Missing optional value.
+    None,
+    /// Value that can't be modified in place (i.e. enums or manually implemented types).
+    Opaque,
+}
+
+impl<'a, F: FnMut(&'static str, Value<'a>)> FieldVisitor<'a> for F {
+    fn visit(&mut self, name: &'static str, value: Value<'a>) {
+        self(name, value)
+    }
+}
+
+impl<'a, F: FnMut(&'static str, ValueMut<'a>)> FieldVisitorMut<'a> for F {
+    fn visit(&mut self, name: &'static str, value: ValueMut<'a>) {
+        self(name, value)
+    }
+}
+
+impl Debug for dyn Reflect + '_ {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = f.debug_struct(self.type_name());
+        self.visit_fields(&mut |name, value: Value| {
+            s.field(name, &value);
+        });
+        s.finish()
+    }
+}
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(x) => write!(f, "{x}"),
+            Self::U8(x) => write!(f, "{x}"),
+            Self::I8(x) => write!(f, "{x}"),
+            Self::U16(x) => write!(f, "{x}"),
+            Self::I16(x) => write!(f, "{x}"),
+            Self::U32(x) => write!(f, "{x}"),
+            Self::I32(x) => write!(f, "{x}"),
+            Self::U64(x) => write!(f, "{x}"),
+            Self::I64(x) => write!(f, "{x}"),
+            Self::U128(x) => write!(f, "{x}"),
+            Self::I128(x) => write!(f, "{x}"),
+            Self::F16(x) => write!(f, "{x:?}"),
+            Self::F32(x) => write!(f, "{x:?}"),
+            Self::F64(x) => write!(f, "{x:?}"),
+            Self::Ipv4Addr(x) => write!(f, "{x}"),
+            Self::Duration(x) => write!(f, "{x:?}"),
+            Self::String(x) => write!(f, "{x:?}"),
+            Self::Bytes(x) => write!(f, "{x:02X?}"),
+            Self::Enum(x) => write!(f, "{x}"),
+            Self::Struct(x) => write!(f, "{x:?}"),
+            Self::List(x) => {
+                f.write_str("[")?;
+                for (i, value) in x.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                f.write_str("]")
+            }
+            Self::None => f.write_str("None"),
+            Self::Opaque => f.write_str(".."),
+        }
+    }
+}
+
+impl Value<'_> {
+    /// Returns the value as an unsigned integer if it is one.
+    pub fn as_u128(&self) -> Option<u128> {
+        match *self {
+            Self::U8(x) => Some(x as _),
+            Self::U16(x) => Some(x as _),
+            Self::U32(x) => Some(x as _),
+            Self::U64(x) => Some(x as _),
+            Self::U128(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a signed integer if it is one.
+    pub fn as_i128(&self) -> Option<i128> {
+        match *self {
+            Self::I8(x) => Some(x as _),
+            Self::I16(x) => Some(x as _),
+            Self::I32(x) => Some(x as _),
+            Self::I64(x) => Some(x as _),
+            Self::I128(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a float if it is one.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Self::F16(x) => Some(x.to_f64()),
+            Self::F32(x) => Some(x as _),
+            Self::F64(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+/// Value conversion of the fields of manually implemented structs.
+pub(crate) trait AsValue {
+    fn field_value(&self) -> Value<'_>;
+    fn field_value_mut(&mut self) -> ValueMut<'_>;
+}
+
+impl<T: HelperReadWrite> AsValue for T {
+    fn field_value(&self) -> Value<'_> {
+        self.as_value()
+    }
+
+    fn field_value_mut(&mut self) -> ValueMut<'_> {
+        self.as_value_mut()
+    }
+}
+
+impl<T: HelperReadWrite> AsValue for Option<T> {
+    fn field_value(&self) -> Value<'_> {
+        self.as_ref().map_or(Value::None, T::as_value)
+    }
+
+    fn field_value_mut(&mut self) -> ValueMut<'_> {
+        self.as_mut().map_or(ValueMut::None, T::as_value_mut)
+    }
+}
+
+/// Implements [`Reflect`] for a manually implemented struct.
+macro_rules! impl_reflect {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl $crate::protocol::reflect::Reflect for $name {
+            fn type_name(&self) -> &'static str {
+                stringify!($name)
+            }
+
+            fn visit_fields<'a>(&'a self, visitor: &mut dyn $crate::protocol::reflect::FieldVisitor<'a>) {
+                use $crate::protocol::reflect::AsValue;
+                $(visitor.visit(stringify!($field), self.$field.field_value());)*
+            }
+
+            fn visit_fields_mut<'a>(
+                &'a mut self,
+                visitor: &mut dyn $crate::protocol::reflect::FieldVisitorMut<'a>,
+            ) {
+                use $crate::protocol::reflect::AsValue;
+                $(visitor.visit(stringify!($field), self.$field.field_value_mut());)*
+            }
+        }
+    };
+}
+pub(crate) use impl_reflect;
+
+/// Returns the value of a list of structs.
+pub(crate) fn struct_list<T: Reflect>(list: &[T]) -> Value<'_> {
+    Value::List(list.iter().map(|v| Value::Struct(v)).collect())
+}
+
+/// Returns the mutable value of a list of structs.
+pub(crate) fn struct_list_mut<T: Reflect>(list: &mut [T]) -> ValueMut<'_> {
+    ValueMut::List(list.iter_mut().map(|v| ValueMut::Struct(v)).collect())
+}
@@ -0,0 +1,292 @@
+//! Layout descriptions of packets, generated by the derive macros.
+//!
+//! Packet layouts of a protocol enum are available in [`super::ProtocolRW::PACKETS`]. With the
+//! `json` feature they can be exported using [`to_json`].
+
+use super::{Flags, PacketCategory, PacketType};
+
+/// Layout of a type.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TypeSchema {
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    U128,
+    I128,
+    F16,
+    F32,
+    F64,
+    /// IPv4 address (4 bytes).
+    Ipv4Addr,
+    /// Duration in seconds (u32).
+    Duration,
+    /// Windows file time (u64).
+    WinTime,
+    /// Variable length UTF-16 string.
+    String,
+    /// Variable length ASCII string.
+    AsciiString,
+    /// Fixed length UTF-16 string.
+    FixedString(usize),
+    /// Fixed length ASCII string.
+    FixedAsciiString(usize),
+    /// Variable length byte array.
+    Bytes {
+        /// Is the data padded to 4 bytes.
+        padding: bool,
+    },
+    /// Fixed length byte array.
+    FixedBytes {
+        /// Length of the array.
+        len: usize,
+        /// Is the data padded to 4 bytes.
+        padding: bool,
+    },
+    /// Variable length array. The data is padded to 4 bytes.
+    Vec(&'static TypeSchema),
+    /// Array with an integer (non-magic) length.
+    VecUSize {
+        /// Type of the length.
+        len: &'static TypeSchema,
+        /// Type of the elements.
+        value: &'static TypeSchema,
+    },
+    /// Fixed length array.
+    Array {
+        /// Length of the array.
+        len: usize,
+        /// Type of the elements.
+        value: &'static TypeSchema,
+    },
+    /// Struct with fields.
+    Struct(&'static StructSchema),
+    /// Integer enum.
+    Enum(&'static EnumSchema),
+    /// Integer with named boolean fields.
+    Flags(&'static FlagsSchema),
+    /// Integer with bitflags.
+    BitFlags(&'static TypeSchema),
+    /// Type with an unknown layout (i.e. manually implemented).
+    Opaque,
+}
+
+/// Layout of a struct.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StructSchema {
+    /// Name of the struct.
+    pub name: &'static str,
+    /// Fields in the order they are read.
+    pub fields: &'static [FieldSchema],
+}
+
+/// Layout of a struct field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldSchema {
+    /// Name of the field. Tuple struct fields are named by their index.
+    pub name: &'static str,
+    /// Name of the field type as written in the struct definition.
+    pub type_name: &'static str,
+    /// Type of the field.
+    pub ty: &'static TypeSchema,
+    /// Data before the field.
+    pub padding_before: &'static [Padding],
+    /// Number of padding bytes after the field.
+    pub padding_after: i64,
+    /// If set, the field is only present on these packet types.
+    pub only_on: Option<&'static [PacketType]>,
+    /// If set, the field is not present on these packet types.
+    pub not_on: Option<&'static [PacketType]>,
+}
+
+/// Data before a field.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Padding {
+    /// Number of skipped bytes.
+    Seek(i64),
+    /// Constant u16.
+    ConstU16(u16),
+}
+
+/// Layout of an integer enum.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnumSchema {
+    /// Name of the enum.
+    pub name: &'static str,
+    /// Underlying integer type.
+    pub repr: &'static TypeSchema,
+    /// Variant names and their values.
+    pub variants: &'static [(&'static str, u64)],
+    /// Variant that is read for unknown values.
+    pub default: Option<&'static str>,
+}
+
+/// Layout of an integer with named boolean fields.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlagsSchema {
+    /// Name of the struct.
+    pub name: &'static str,
+    /// Underlying integer type.
+    pub repr: &'static TypeSchema,
+    /// Field names and their bits.
+    pub flags: &'static [(&'static str, u64)],
+}
+
+/// Layout of a packet.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketSchema {
+    /// Name of the packet struct.
+    pub name: &'static str,
+    /// Id of the packet.
+    pub id: u8,
+    /// Subid of the packet.
+    pub subid: u16,
+    /// Flags of the packet header.
+    pub flags: Flags,
+    /// Xor and sub for variable length types.
+    pub magic: Option<(u32, u32)>,
+    /// Fields in the order they are read.
+    pub fields: &'static [FieldSchema],
+}
+
+/// Packet variant of a protocol enum.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacketInfo {
+    /// Name of the variant.
+    pub name: &'static str,
+    /// Id of the packet.
+    pub id: u8,
+    /// Subid of the packet.
+    pub subid: u16,
+    /// Category of the packet.
+    pub category: PacketCategory,
+    /// Packet types that the variant is read on.
+    pub packet_types: &'static [PacketType],
+    /// Layout of the packet data. `None` if the layout is unknown.
+    pub schema: Option<&'static PacketSchema>,
+}
+
+impl TypeSchema {
+    /// Returns the size of the type if it doesn't depend on the data or the packet type.
+    pub const fn fixed_size(&self) -> Option<usize> {
+        match self {
+            Self::U8 | Self::I8 => Some(1),
+            Self::U16 | Self::I16 | Self::F16 => Some(2),
+            Self::U32 | Self::I32 | Self::F32 | Self::Ipv4Addr | Self::Duration => Some(4),
+            Self::U64 | Self::I64 | Self::F64 | Self::WinTime => Some(8),
+            Self::U128 | Self::I128 => Some(16),
+            Self::FixedString(len) => Some(*len * 2),
+            Self::FixedAsciiString(len) => Some(*len),
+            Self::FixedBytes { len, padding } => {
+                if *padding {
+                    Some(len.next_multiple_of(4))
+                } else {
+                    Some(*len)
+                }
+            }
+            Self::Array { len, value } => match value.fixed_size() {
+                Some(size) => Some(size * *len),
+                None => None,
+            },
+            Self::Struct(s) => s.fixed_size(),
+            Self::Enum(e) => e.repr.fixed_size(),
+            Self::Flags(f) => f.repr.fixed_size(),
+            Self::BitFlags(repr) => repr.fixed_size(),
+            Self::String
+            | Self::AsciiString
+            | Self::Bytes { .. }
+            | Self::Vec(_)
+            | Self::VecUSize { .. }
+            | Self::Opaque => None,
+        }
+    }
+}
+
+impl StructSchema {
+    /// Returns the size of the struct (including padding) if it doesn't depend on the data or the
+    /// packet type.
+    pub const fn fixed_size(&self) -> Option<usize> {
+        fields_size(self.fields)
+    }
+}
+
+impl FieldSchema {
+    /// Returns the size of the field data (excluding padding) if it doesn't depend on the data.
+    pub const fn fixed_size(&self) -> Option<usize> {
+        self.ty.fixed_size()
+    }
+
+    /// Returns the number of bytes before the field data.
+    pub const fn padding_size(&self) -> i64 {
+        let mut size = 0;
+        let mut i = 0;
+        while i < self.padding_before.len() {
+            size += match self.padding_before[i] {
+                Padding::Seek(amount) => amount,
+                Padding::ConstU16(_) => 2,
+            };
+            i += 1;
+        }
+        size
+    }
+}
+
+impl PacketSchema {
+    /// Returns the size of the packet data (excluding the header) if it doesn't depend on the data
+    /// or the packet type.
+    pub const fn fixed_size(&self) -> Option<usize> {
+        fields_size(self.fields)
+    }
+}
+
+const fn fields_size(fields: &[FieldSchema]) -> Option<usize> {
+    let mut size = 0;
+    let mut i = 0;
+    while i < fields.len() {
+        let field = &fields[i];
+        if field.only_on.is_some() || field.not_on.is_some() {
+            return None;
+        }
+        let Some(field_size) = field.fixed_size() else {
+            return None;
+        };
+        size += field_size as i64 + field.padding_size() + field.padding_after;
+        i += 1;
+    }
+    Some(size as usize)
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for FieldSchema {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("FieldSchema", 8)?;
+        s.serialize_field("name", self.name)?;
+        s.serialize_field("type_name", self.type_name)?;
+        s.serialize_field("ty", self.ty)?;
+        s.serialize_field("fixed_size", &self.fixed_size())?;
+        s.serialize_field("padding_before", self.padding_before)?;
+        s.serialize_field("padding_after", &self.padding_after)?;
+        s.serialize_field("only_on", &self.only_on)?;
+        s.serialize_field("not_on", &self.not_on)?;
+        s.end()
+    }
+}
+
+/// Exports packet layouts of the protocol enum as JSON.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn to_json<P: super::ProtocolRW>() -> String {
+    serde_json::to_string(P::PACKETS).expect("Serializing the schema shouldn't fail")
+}
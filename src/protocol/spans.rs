@@ -0,0 +1,225 @@
+//! Byte spans of decoded packet fields.
+//!
+//! Packets read using [`super::ProtocolRW::read_spanned`] (or the `read_spanned` functions of
+//! other packet traits) record the location of every field in a [`SpanRecorder`]. Each read
+//! packet produces one [`FieldSpan`] tree containing the length prefix, the header and the
+//! packet fields.
+//!
+//! Types with manually implemented read functions are recorded as a single span without
+//! children.
+
+use std::{
+    borrow::Cow,
+    io::{Read, Seek},
+    ops::Range,
+};
+
+use super::PacketError;
+
+/// Kind of the data in a span.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    /// Field value.
+    #[default]
+    Value,
+    /// Length of the following data.
+    Length,
+    /// Skipped bytes.
+    Padding,
+    /// Constant value.
+    Constant,
+}
+
+/// Location of a field in the input buffer.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FieldSpan {
+    /// Name of the field. List elements are named by their index.
+    pub name: Cow<'static, str>,
+    /// Kind of the data.
+    pub kind: SpanKind,
+    /// Offset of the field from the start of the buffer.
+    pub offset: usize,
+    /// Length of the field.
+    pub len: usize,
+    /// Spans of the inner fields.
+    pub children: Vec<FieldSpan>,
+}
+
+/// Collector of field spans.
+#[derive(Debug, Default, Clone)]
+pub struct SpanRecorder {
+    enabled: bool,
+    base: u64,
+    stack: Vec<FieldSpan>,
+    spans: Vec<FieldSpan>,
+}
+
+impl FieldSpan {
+    /// Returns the byte range of the field.
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+
+    /// Returns the span of an inner field by its path (e.g. `settings.unk3` or
+    /// `other_settings.2.zone_id`).
+    pub fn find(&self, path: &str) -> Option<&FieldSpan> {
+        path.split('.').try_fold(self, |span, name| {
+            span.children.iter().find(|c| c.name == name)
+        })
+    }
+}
+
+impl SpanRecorder {
+    /// Creates a new recorder.
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a recorder that doesn't record anything.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Returns `true` if the spans are recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the recorded spans.
+    pub fn spans(&self) -> &[FieldSpan] {
+        &self.spans
+    }
+
+    /// Consumes the recorder, returning the recorded spans.
+    pub fn into_spans(self) -> Vec<FieldSpan> {
+        self.spans
+    }
+
+    /// Sets the offset of the reader start in the input buffer.
+    pub fn set_base(&mut self, base: u64) {
+        self.base = base;
+    }
+
+    /// Starts a new span at the reader position.
+    pub fn begin(
+        &mut self,
+        reader: &mut impl Seek,
+        name: impl Into<Cow<'static, str>>,
+        kind: SpanKind,
+    ) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let pos = reader.stream_position()?;
+        self.begin_at(pos, name, kind);
+        Ok(())
+    }
+
+    /// Starts a new span named after the element index at the reader position. The name is only
+    /// formatted if the recorder is enabled.
+    pub fn begin_index(
+        &mut self,
+        reader: &mut impl Seek,
+        index: usize,
+        kind: SpanKind,
+    ) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        self.begin(reader, index.to_string(), kind)
+    }
+
+    /// Starts a new span at the specified reader position.
+    pub fn begin_at(&mut self, pos: u64, name: impl Into<Cow<'static, str>>, kind: SpanKind) {
+        if !self.enabled {
+            return;
+        }
+        self.stack.push(FieldSpan {
+            name: name.into(),
+            kind,
+            offset: (self.base + pos) as usize,
+            len: 0,
+            children: vec![],
+        });
+    }
+
+    /// Ends the last started span at the reader position.
+    pub fn end(&mut self, reader: &mut impl Seek) -> std::io::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        let pos = reader.stream_position()?;
+        self.end_at(pos);
+        Ok(())
+    }
+
+    /// Ends the last started span at the specified reader position.
+    pub fn end_at(&mut self, pos: u64) {
+        let Some(mut span) = self.stack.pop() else {
+            return;
+        };
+        span.len = ((self.base + pos) as usize).saturating_sub(span.offset);
+        self.push(span);
+    }
+
+    /// Adds a complete span at the specified reader position.
+    pub fn add(&mut self, pos: u64, len: u64, name: impl Into<Cow<'static, str>>, kind: SpanKind) {
+        if !self.enabled {
+            return;
+        }
+        self.push(FieldSpan {
+            name: name.into(),
+            kind,
+            offset: (self.base + pos) as usize,
+            len: len as usize,
+            children: vec![],
+        });
+    }
+
+    /// Renames the last started span.
+    pub fn set_name(&mut self, name: impl Into<Cow<'static, str>>) {
+        if let Some(span) = self.stack.last_mut() {
+            span.name = name.into();
+        }
+    }
+
+    fn push(&mut self, span: FieldSpan) {
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(span),
+            None => self.spans.push(span),
+        }
+    }
+}
+
+/// Reads a value prefixed by a magic encoded length, recording spans of the length and the data.
+pub(crate) fn read_prefixed<R: Read + Seek, T>(
+    reader: &mut R,
+    spans: &mut SpanRecorder,
+    packet_name: &'static str,
+    read: impl FnOnce(&mut R) -> Result<T, PacketError>,
+) -> Result<T, PacketError> {
+    if !spans.is_enabled() {
+        return read(reader);
+    }
+    let map_err = |e| PacketError::FieldError {
+        packet_name,
+        field_name: "span",
+        error: e,
+    };
+    let start = reader.stream_position().map_err(map_err)?;
+    let value = read(reader)?;
+    let end = reader.stream_position().map_err(map_err)?;
+    spans.add(start, 4, "len", SpanKind::Length);
+    spans.add(
+        start + 4,
+        end.saturating_sub(start + 4),
+        "data",
+        SpanKind::Value,
+    );
+    Ok(value)
+}
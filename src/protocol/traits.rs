@@ -1,5 +1,14 @@
-use super::{Flags, PacketCategory, PacketError, PacketType};
-use std::io::{Read, Seek, Write};
+use super::{
+    limits::{self, DecodeContext, DecodeLimits},
+    reflect::{Reflect, Value, ValueMut},
+    schema::{PacketInfo, PacketSchema, TypeSchema},
+    spans::SpanRecorder,
+    Flags, PacketCategory, PacketError, PacketType,
+};
+use std::{
+    any::Any,
+    io::{Read, Seek, Write},
+};
 
 /// Trait for manipulating encryption data.
 pub trait PacketEncryption {
@@ -9,25 +18,139 @@ pub trait PacketEncryption {
     fn as_enc_data(&self) -> Option<&[u8]>;
     /// Returns a mutable refrence to the RSA encrypted data.
     fn mut_enc_data(&mut self) -> Option<&mut Vec<u8>>;
+    /// Returns a refrence to the encryption secret echoed by the server (i.e data of
+    /// [`crate::protocol::Packet::EncryptionResponse`]).
+    fn as_enc_response(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
+/// Handler of the data left unread in a packet body. Receives the packet name and the data.
+pub type TrailingDataHandler<'a> = dyn FnMut(&'static str, &[u8]) -> Result<(), PacketError> + 'a;
+
 /// Read/Write trait for packet enums.
-pub trait ProtocolRW: PacketEncryption + Sized {
+pub trait ProtocolRW: PacketEncryption + PacketAny + Sized {
+    /// Known packet variants.
+    const PACKETS: &'static [PacketInfo] = &[];
+
     /// Reads packets from an input slice.
     fn read(input: &[u8], packet_type: PacketType) -> Result<Vec<Self>, PacketError>;
+    /// Reads packets from an input slice, recording byte spans of every packet in `spans`.
+    fn read_spanned(
+        input: &[u8],
+        packet_type: PacketType,
+        _: &mut SpanRecorder,
+    ) -> Result<Vec<Self>, PacketError> {
+        Self::read(input, packet_type)
+    }
+    /// Reads packets from an input slice, checking the decode limits of `ctx` and passing the name
+    /// and the unread body data of every packet to `trailing`.
+    fn read_trailing(
+        input: &[u8],
+        packet_type: PacketType,
+        spans: &mut SpanRecorder,
+        _: &mut DecodeContext,
+        _: &mut TrailingDataHandler,
+    ) -> Result<Vec<Self>, PacketError> {
+        Self::read_spanned(input, packet_type, spans)
+    }
+    /// Reads packets from an input slice, returning [`PacketError::TrailingDataError`] if a
+    /// packet body is not fully read.
+    fn read_strict(input: &[u8], packet_type: PacketType) -> Result<Vec<Self>, PacketError> {
+        let mut spans = SpanRecorder::disabled();
+        let mut ctx = DecodeContext::unlimited();
+        Self::read_trailing(
+            input,
+            packet_type,
+            &mut spans,
+            &mut ctx,
+            &mut |packet_name, data| match trailing_data(data).len() {
+                0 => Ok(()),
+                len => Err(PacketError::TrailingDataError { packet_name, len }),
+            },
+        )
+    }
+    /// Reads packets from an input slice, returning every packet with the data left unread in
+    /// its body.
+    fn read_lenient(
+        input: &[u8],
+        packet_type: PacketType,
+    ) -> Result<Vec<(Self, Vec<u8>)>, PacketError> {
+        let mut spans = SpanRecorder::disabled();
+        let mut ctx = DecodeContext::unlimited();
+        let mut unread = vec![];
+        let packets =
+            Self::read_trailing(input, packet_type, &mut spans, &mut ctx, &mut |_, data| {
+                unread.push(trailing_data(data).to_vec());
+                Ok(())
+            })?;
+        // packets read without `read_trailing` (i.e. raw packets) have no unread data
+        unread.resize(packets.len(), vec![]);
+        Ok(packets.into_iter().zip(unread).collect())
+    }
+    /// Reads packets from an input slice, returning [`PacketError::LimitError`] if the lengths
+    /// of the packet fields exceed `limits`. The allocation limit applies to each packet.
+    fn read_limited(
+        input: &[u8],
+        packet_type: PacketType,
+        limits: &DecodeLimits,
+    ) -> Result<Vec<Self>, PacketError> {
+        let mut spans = SpanRecorder::disabled();
+        let mut ctx = DecodeContext::new(*limits);
+        Self::read_trailing(input, packet_type, &mut spans, &mut ctx, &mut |_, _| Ok(()))
+            .map_err(limits::root_limit_error)
+    }
     /// Writes a packet to a byte vector.
     fn write(&self, packet_type: PacketType) -> Vec<u8>;
     /// Returns category of the packet.
     fn get_category(&self) -> PacketCategory;
+    /// Returns the packet struct if the variant contains one.
+    fn as_reflect(&self) -> Option<&dyn Reflect> {
+        None
+    }
+    /// Returns the mutable packet struct if the variant contains one.
+    fn as_reflect_mut(&mut self) -> Option<&mut dyn Reflect> {
+        None
+    }
+}
+
+/// Conversion of packet enums to [`Any`] (used to pass written packets to packet sinks without
+/// parsing them again). Implemented by the [`ProtocolRW`] derive.
+#[doc(hidden)]
+pub trait PacketAny {
+    /// Returns the packet as [`Any`].
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
+    /// Returns the packet if `value` is a packet of this type.
+    fn from_any(_: &dyn Any) -> Option<&Self> {
+        None
+    }
 }
 
 /// Read/Write trait for packet data containing structs.
 pub trait PacketReadWrite: Sized {
+    /// Layout of the packet. `None` if the layout is unknown.
+    const SCHEMA: Option<&'static PacketSchema> = None;
+
     /// Reads a packet from a stream.
     fn read(
         reader: &mut (impl Read + Seek),
         flags: &Flags,
         packet_type: PacketType,
+    ) -> Result<Self, PacketError> {
+        let mut spans = SpanRecorder::disabled();
+        let mut ctx = DecodeContext::unlimited();
+        Self::read_spanned(reader, flags, packet_type, &mut spans, &mut ctx)
+    }
+    /// Reads a packet from a stream, recording byte spans of the fields in `spans` and checking
+    /// the decode limits of `ctx`.
+    fn read_spanned(
+        reader: &mut (impl Read + Seek),
+        flags: &Flags,
+        packet_type: PacketType,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError>;
     /// Writes a packet to a Vec.
     fn write(&self, packet_type: PacketType) -> Result<Vec<u8>, PacketError>;
@@ -35,12 +158,29 @@ pub trait PacketReadWrite: Sized {
 
 /// Read/Write trait for aditional data structs/enums.
 pub trait HelperReadWrite: Sized {
+    /// Layout of the type.
+    const SCHEMA: TypeSchema = TypeSchema::Opaque;
+
     /// Reads data from a stream.
     fn read(
         reader: &mut (impl Read + Seek),
         packet_type: PacketType,
         xor: u32,
         sub: u32,
+    ) -> Result<Self, PacketError> {
+        let mut spans = SpanRecorder::disabled();
+        let mut ctx = DecodeContext::unlimited();
+        Self::read_spanned(reader, packet_type, xor, sub, &mut spans, &mut ctx)
+    }
+    /// Reads data from a stream, recording byte spans of the fields in `spans` and checking the
+    /// decode limits of `ctx`.
+    fn read_spanned(
+        reader: &mut (impl Read + Seek),
+        packet_type: PacketType,
+        xor: u32,
+        sub: u32,
+        spans: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError>;
     /// Writes data to a stream.
     fn write(
@@ -50,4 +190,21 @@ pub trait HelperReadWrite: Sized {
         xor: u32,
         sub: u32,
     ) -> Result<(), PacketError>;
+    /// Returns the value of the data for field visitors.
+    fn as_value(&self) -> Value<'_> {
+        Value::Opaque
+    }
+    /// Returns the mutable value of the data for field visitors.
+    fn as_value_mut(&mut self) -> ValueMut<'_> {
+        ValueMut::Opaque
+    }
+}
+
+/// Returns the unread packet data, ignoring the alignment padding.
+fn trailing_data(data: &[u8]) -> &[u8] {
+    if data.len() < 4 && data.iter().all(|&b| b == 0) {
+        &[]
+    } else {
+        data
+    }
 }
@@ -1,5 +1,10 @@
 //! Unknown \[0x31\] packets.
-use super::{HelperReadWrite, Item, ItemId, PacketError, PacketReadWrite, PacketType};
+use super::{
+    limits::DecodeContext,
+    reflect::{impl_reflect, struct_list, struct_list_mut, FieldVisitor, FieldVisitorMut, Reflect},
+    spans::SpanRecorder,
+    HelperReadWrite, Item, ItemId, PacketError, PacketReadWrite, PacketType,
+};
 use crate::AsciiString;
 
 // ----------------------------------------------------------------
@@ -285,25 +290,48 @@ pub struct EnemyRecord {
 // Read/Write implementations
 // ----------------------------------------------------------------
 
+impl Reflect for LoadTitlesPacket {
+    fn type_name(&self) -> &'static str {
+        "LoadTitlesPacket"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("names", struct_list(&self.names));
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("names", struct_list_mut(&mut self.names));
+    }
+}
+
+impl_reflect!(NamedTitleId { title_id, name });
+
 impl PacketReadWrite for LoadTitlesPacket {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         flags: &super::Flags,
         packet_type: PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        let packet = LoadTitlesInternal::read(reader, flags, packet_type).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "LoadTitlesPacket",
-                field_name: "internal",
-                error: Box::new(e),
-            }
+        let packet = LoadTitlesInternal::read_spanned(
+            reader,
+            flags,
+            packet_type,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "LoadTitlesPacket",
+            field_name: "internal",
+            error: Box::new(e),
         })?;
         let mut names = packet.names.chars();
         let mut items = vec![];
         for (title_id, name_length) in packet
             .title_ids
             .into_iter()
-            .zip(packet.name_lens.into_iter())
+            .zip(packet.name_lens)
         {
             let name = names.by_ref().take(name_length as usize).collect();
             items.push(NamedTitleId { name, title_id });
@@ -334,18 +362,69 @@ impl PacketReadWrite for LoadTitlesPacket {
     }
 }
 
+impl Reflect for PlayAchievementsResponsePacket {
+    fn type_name(&self) -> &'static str {
+        "PlayAchievementsResponsePacket"
+    }
+
+    fn visit_fields<'a>(&'a self, visitor: &mut dyn FieldVisitor<'a>) {
+        visitor.visit("unk1", self.unk1.as_value());
+        visitor.visit("total_suppressed", self.total_suppressed.as_value());
+        visitor.visit("quest_completions", self.quest_completions.as_value());
+        visitor.visit("s_ranks", self.s_ranks.as_value());
+        visitor.visit("a_ranks", self.a_ranks.as_value());
+        visitor.visit("b_ranks", self.b_ranks.as_value());
+        visitor.visit("c_ranks", self.c_ranks.as_value());
+        visitor.visit("deaths", self.deaths.as_value());
+        visitor.visit("max_damage", self.max_damage.as_value());
+        visitor.visit("unk2", self.unk2.as_value());
+        visitor.visit("quest_records", self.quest_records.as_value());
+        visitor.visit("rare_items", self.rare_items.as_value());
+        visitor.visit("boss_enemies", struct_list(&self.boss_enemies));
+        visitor.visit("rare_enemies", struct_list(&self.rare_enemies));
+        visitor.visit("titles_acquired", self.titles_acquired.as_value());
+    }
+
+    fn visit_fields_mut<'a>(&'a mut self, visitor: &mut dyn FieldVisitorMut<'a>) {
+        visitor.visit("unk1", self.unk1.as_value_mut());
+        visitor.visit("total_suppressed", self.total_suppressed.as_value_mut());
+        visitor.visit("quest_completions", self.quest_completions.as_value_mut());
+        visitor.visit("s_ranks", self.s_ranks.as_value_mut());
+        visitor.visit("a_ranks", self.a_ranks.as_value_mut());
+        visitor.visit("b_ranks", self.b_ranks.as_value_mut());
+        visitor.visit("c_ranks", self.c_ranks.as_value_mut());
+        visitor.visit("deaths", self.deaths.as_value_mut());
+        visitor.visit("max_damage", self.max_damage.as_value_mut());
+        visitor.visit("unk2", self.unk2.as_value_mut());
+        visitor.visit("quest_records", self.quest_records.as_value_mut());
+        visitor.visit("rare_items", self.rare_items.as_value_mut());
+        visitor.visit("boss_enemies", struct_list_mut(&mut self.boss_enemies));
+        visitor.visit("rare_enemies", struct_list_mut(&mut self.rare_enemies));
+        visitor.visit("titles_acquired", self.titles_acquired.as_value_mut());
+    }
+}
+
+impl_reflect!(EnemyRecord { name, level });
+
 impl PacketReadWrite for PlayAchievementsResponsePacket {
-    fn read(
+    fn read_spanned(
         reader: &mut (impl std::io::Read + std::io::Seek),
         flags: &super::Flags,
         packet_type: super::PacketType,
+        _: &mut SpanRecorder,
+        ctx: &mut DecodeContext,
     ) -> Result<Self, PacketError> {
-        let packet = PlayAchievementsInternal::read(reader, flags, packet_type).map_err(|e| {
-            PacketError::CompositeFieldError {
-                packet_name: "PlayAchievementsResponsePacket",
-                field_name: "internal",
-                error: Box::new(e),
-            }
+        let packet = PlayAchievementsInternal::read_spanned(
+            reader,
+            flags,
+            packet_type,
+            &mut SpanRecorder::disabled(),
+            ctx,
+        )
+        .map_err(|e| PacketError::CompositeFieldError {
+            packet_name: "PlayAchievementsResponsePacket",
+            field_name: "internal",
+            error: Box::new(e),
         })?;
         let mut names = packet.enemy_ids.chars();
         let mut boss_enemies = vec![];
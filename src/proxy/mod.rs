@@ -0,0 +1,342 @@
+//! Man-in-the-middle proxy between a client and a server.
+//!
+//! [`Proxy`] holds two [`Connection`]s: one to the client and one to the upstream server. The
+//! client connection decrypts [`crate::protocol::Packet::EncryptionRequest`] using the proxy's
+//! private key and the server connection re-encrypts it using the official public key, so both
+//! sides see a regular encrypted connection while the proxy sees every packet in the clear.
+//!
+//! Every received packet is passed to a [`ProxyHandler`], which can inspect, modify or drop it
+//! and inject new packets in either direction.
+//!
+//! Server addresses sent to the client (ship and block lists, block switches) can be redirected
+//! to the proxy using [`AddressRewriter`].
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use pso2packetlib::proxy::{Action, Proxy, ProxyContext, ProxyHandler};
+//! # use pso2packetlib::ppac::Direction;
+//! # use pso2packetlib::protocol::{Packet, PacketType};
+//! # use pso2packetlib::{PrivateKey, PublicKey};
+//! struct Logger;
+//!
+//! impl ProxyHandler<Packet> for Logger {
+//!     fn on_packet(
+//!         &mut self,
+//!         _: &mut ProxyContext<Packet>,
+//!         direction: Direction,
+//!         packet: Packet,
+//!     ) -> Action<Packet> {
+//!         println!("{direction:?}: {packet:?}");
+//!         Action::Forward(packet)
+//!     }
+//! }
+//!
+//! # #[cfg(not(feature = "tokio"))]
+//! # fn main() -> Result<(), pso2packetlib::connection::ConnectionError> {
+//! let listener = std::net::TcpListener::bind("0.0.0.0:12000")?;
+//! let (client, _) = listener.accept()?;
+//! let mut proxy = Proxy::connect(
+//!     client,
+//!     "40.91.76.146:12000",
+//!     PacketType::NGS,
+//!     PrivateKey::Path("keypair.pem".into()),
+//!     PublicKey::Path("publicKey.pem".into()),
+//!     Logger,
+//! )?;
+//! proxy.create_ppac("session.pak")?;
+//! loop {
+//!     if !proxy.poll()? {
+//!         std::thread::sleep(std::time::Duration::from_millis(1));
+//!     }
+//! }
+//! # }
+//! # #[cfg(feature = "tokio")]
+//! # fn main() {}
+//! ```
+
+mod rewrite;
+pub use rewrite::{
+    packet_addresses, ship_port, AddressRewriter, ChannelListeners, ListenerFactory,
+};
+
+use crate::{
+    connection::{ConnReadAsync, ConnWriteAsync, Connection, ConnectionError, DefaultStream},
+    ppac::{Direction, FileWriter},
+    protocol::{PacketType, ProtocolRW},
+    PrivateKey, PublicKey,
+};
+
+/// What to do with the received packet.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action<P> {
+    /// Send the (possibly modified) packet to its destination.
+    Forward(P),
+    /// Do not send the packet.
+    Drop,
+}
+
+/// Packets injected by a [`ProxyHandler`].
+#[derive(Debug)]
+pub struct ProxyContext<P> {
+    packet_type: PacketType,
+    to_server: Vec<P>,
+    to_client: Vec<P>,
+}
+
+/// Hooks for packets passing through the [`Proxy`].
+pub trait ProxyHandler<P> {
+    /// Called for every packet received from either side. `direction` is where the packet is
+    /// heading. Injected packets are sent after the returned packet.
+    fn on_packet(
+        &mut self,
+        ctx: &mut ProxyContext<P>,
+        direction: Direction,
+        packet: P,
+    ) -> Action<P> {
+        let _ = (ctx, direction);
+        Action::Forward(packet)
+    }
+}
+
+/// Handler that forwards every packet unchanged.
+impl<P> ProxyHandler<P> for () {}
+
+/// Man-in-the-middle proxy. See [module level documentation](self) for more info.
+#[derive(Debug)]
+pub struct Proxy<P, H, C = DefaultStream, S = DefaultStream>
+where
+    P: ProtocolRW + Send,
+{
+    client: Connection<P, C>,
+    server: Connection<P, S>,
+    handler: H,
+    ppac: Option<FileWriter>,
+}
+
+impl<P> ProxyContext<P> {
+    /// Returns the current packet type.
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    /// Queues a packet to be sent in the provided direction.
+    pub fn inject(&mut self, direction: Direction, packet: P) {
+        match direction {
+            Direction::ToServer => self.to_server.push(packet),
+            Direction::ToClient => self.to_client.push(packet),
+        }
+    }
+}
+
+impl<P: ProtocolRW + Send, H: ProxyHandler<P>> Proxy<P, H> {
+    /// Accepts a client connection and connects to the upstream server.
+    /// `private_key` is used to decrypt the client's encryption request.
+    /// `public_key` is used to encrypt the encryption request for the upstream server.
+    ///
+    /// Both streams are set to a nonblocking mode.
+    #[cfg(not(feature = "tokio"))]
+    pub fn connect(
+        client: std::net::TcpStream,
+        upstream: impl std::net::ToSocketAddrs,
+        packet_type: PacketType,
+        private_key: PrivateKey,
+        public_key: PublicKey,
+        handler: H,
+    ) -> Result<Self, ConnectionError> {
+        let server = std::net::TcpStream::connect(upstream)?;
+        client.set_nonblocking(true)?;
+        server.set_nonblocking(true)?;
+        Ok(Self::new(
+            Connection::new(client, packet_type, private_key, PublicKey::None),
+            Connection::new(server, packet_type, PrivateKey::None, public_key),
+            handler,
+        ))
+    }
+
+    /// Accepts a client connection and connects to the upstream server.
+    /// `private_key` is used to decrypt the client's encryption request.
+    /// `public_key` is used to encrypt the encryption request for the upstream server.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn connect_async(
+        client: tokio::net::TcpStream,
+        upstream: impl tokio::net::ToSocketAddrs,
+        packet_type: PacketType,
+        private_key: PrivateKey,
+        public_key: PublicKey,
+        handler: H,
+    ) -> Result<Self, ConnectionError> {
+        let server = tokio::net::TcpStream::connect(upstream).await?;
+        Ok(Self::new(
+            Connection::new_async(client, packet_type, private_key, PublicKey::None),
+            Connection::new_async(server, packet_type, PrivateKey::None, public_key),
+            handler,
+        ))
+    }
+}
+
+impl<P, H, C, S> Proxy<P, H, C, S>
+where
+    P: ProtocolRW + Send,
+    H: ProxyHandler<P>,
+    C: ConnReadAsync + ConnWriteAsync + Send,
+    S: ConnReadAsync + ConnWriteAsync + Send,
+{
+    /// Creates a new proxy from existing connections.
+    ///
+    /// `client` must be created with the private key that the client uses (`in_keyfile`) and
+    /// `server` must be created with the public key of the upstream server (`out_keyfile`).
+    pub fn new(client: Connection<P, C>, server: Connection<P, S>, handler: H) -> Self {
+        Self {
+            client,
+            server,
+            handler,
+            ppac: None,
+        }
+    }
+
+    /// Creates a packet storage file. Packets are stored as they were received from each side
+    /// (i.e. before the handler is called).
+    pub fn create_ppac<PT: AsRef<std::path::Path>>(
+        &mut self,
+        path: PT,
+    ) -> Result<(), ConnectionError> {
+        self.ppac = Some(crate::ppac::create_file(path, self.server.packet_type())?);
+        Ok(())
+    }
+
+    /// Returns a reference to the handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Returns a mutable reference to the handler.
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Returns a reference to the client connection.
+    pub fn client(&self) -> &Connection<P, C> {
+        &self.client
+    }
+
+    /// Returns a reference to the server connection.
+    pub fn server(&self) -> &Connection<P, S> {
+        &self.server
+    }
+
+    /// Changes connection type of both sides.
+    pub fn change_packet_type(&mut self, packet_type: PacketType) {
+        if let Some(writer) = &self.ppac {
+            let _ = writer.change_packet_type(packet_type);
+        }
+        self.client.change_packet_type(packet_type);
+        self.server.change_packet_type(packet_type);
+    }
+
+    /// Sends a packet in the provided direction, bypassing the handler.
+    ///
+    /// # Note
+    ///
+    /// If `tokio` feature is enabled this function becomes nonblocking
+    pub fn send(
+        &mut self,
+        direction: Direction,
+        packet: &impl ProtocolRW,
+    ) -> Result<(), ConnectionError> {
+        match direction {
+            Direction::ToServer => self.server.write_packet(packet),
+            Direction::ToClient => self.client.write_packet(packet),
+        }
+    }
+
+    /// Sends a packet in the provided direction, bypassing the handler.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn send_async(
+        &mut self,
+        direction: Direction,
+        packet: &(impl ProtocolRW + Sync),
+    ) -> Result<(), ConnectionError> {
+        match direction {
+            Direction::ToServer => self.server.write_packet_async(packet).await,
+            Direction::ToClient => self.client.write_packet_async(packet).await,
+        }
+    }
+
+    /// Relays all currently available packets. Returns `true` if any packet was received.
+    ///
+    /// # Note
+    ///
+    /// Streams must be in a nonblocking mode, otherwise this function will block until the
+    /// client sends a packet.
+    pub fn poll(&mut self) -> Result<bool, ConnectionError> {
+        let mut received = false;
+        for direction in [Direction::ToServer, Direction::ToClient] {
+            loop {
+                let result = match direction {
+                    Direction::ToServer => self.client.read_packet(),
+                    Direction::ToClient => self.server.read_packet(),
+                };
+                match result {
+                    Ok(packet) => {
+                        received = true;
+                        self.handle_packet(direction, packet)?;
+                    }
+                    Err(ConnectionError::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        break
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        // unsent data stays in the buffer until the next poll
+        for result in [self.client.flush(), self.server.flush()] {
+            match result {
+                Err(e) if e.kind() != std::io::ErrorKind::WouldBlock => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(received)
+    }
+
+    /// Relays packets until either side disconnects or an error occurs.
+    #[cfg(feature = "tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+    pub async fn run_async(&mut self) -> Result<(), ConnectionError> {
+        loop {
+            let (direction, packet) = tokio::select! {
+                packet = self.client.read_packet_async() => (Direction::ToServer, packet?),
+                packet = self.server.read_packet_async() => (Direction::ToClient, packet?),
+            };
+            self.handle_packet(direction, packet)?;
+            self.client.flush_async().await?;
+            self.server.flush_async().await?;
+        }
+    }
+
+    fn handle_packet(&mut self, direction: Direction, packet: P) -> Result<(), ConnectionError> {
+        if let Some(writer) = &self.ppac {
+            writer.write_packet(crate::ppac::get_now(), direction, &packet)?;
+        }
+        let mut ctx = ProxyContext {
+            packet_type: self.server.packet_type(),
+            to_server: vec![],
+            to_client: vec![],
+        };
+        if let Action::Forward(packet) = self.handler.on_packet(&mut ctx, direction, packet) {
+            match direction {
+                Direction::ToServer => self.server.prepare_data(&packet)?,
+                Direction::ToClient => self.client.prepare_data(&packet)?,
+            }
+        }
+        for packet in ctx.to_server {
+            self.server.prepare_data(&packet)?;
+        }
+        for packet in ctx.to_client {
+            self.client.prepare_data(&packet)?;
+        }
+        Ok(())
+    }
+}
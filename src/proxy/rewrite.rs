@@ -0,0 +1,194 @@
+//! Server address rewriting.
+
+use super::{Action, ProxyContext, ProxyHandler};
+use crate::{ppac::Direction, protocol::Packet};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddrV4, TcpListener},
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+/// Creates listeners for upstream servers.
+pub trait ListenerFactory {
+    /// Starts listening for clients that should be proxied to `upstream`. Returns the address that
+    /// the clients should connect to.
+    fn listen(&mut self, upstream: SocketAddrV4) -> std::io::Result<SocketAddrV4>;
+}
+
+impl<F: FnMut(SocketAddrV4) -> std::io::Result<SocketAddrV4>> ListenerFactory for F {
+    fn listen(&mut self, upstream: SocketAddrV4) -> std::io::Result<SocketAddrV4> {
+        self(upstream)
+    }
+}
+
+/// [`ListenerFactory`] that binds a [`TcpListener`] for every upstream server and sends it
+/// through a channel.
+///
+/// The listener is bound to the same port as the upstream server if possible, otherwise a random
+/// port is used.
+#[derive(Debug)]
+pub struct ChannelListeners {
+    ip: Ipv4Addr,
+    sender: Sender<(TcpListener, SocketAddrV4)>,
+}
+
+/// Rewrites server addresses in packets to the addresses of the proxy listeners.
+///
+/// Every new upstream address gets a new listener from the [`ListenerFactory`] and the mapping
+/// is stored for later lookups (e.g. to find the upstream server for a newly accepted client).
+///
+/// Ship entries don't contain ports, so the port is derived from the ship ID (see [`ship_port`])
+/// in the same way as the client does. Because only the IP can be rewritten, the listener for a
+/// ship must be bound to the same port as the upstream ship.
+pub struct AddressRewriter {
+    listen_ip: Ipv4Addr,
+    factory: Box<dyn ListenerFactory + Send>,
+    mappings: HashMap<SocketAddrV4, SocketAddrV4>,
+}
+
+impl ChannelListeners {
+    /// Creates a new factory that binds listeners to the provided IP.
+    pub fn new(ip: Ipv4Addr) -> (Self, Receiver<(TcpListener, SocketAddrV4)>) {
+        let (sender, receiver) = channel();
+        (Self { ip, sender }, receiver)
+    }
+}
+
+impl ListenerFactory for ChannelListeners {
+    fn listen(&mut self, upstream: SocketAddrV4) -> std::io::Result<SocketAddrV4> {
+        let listener = TcpListener::bind((self.ip, upstream.port()))
+            .or_else(|_| TcpListener::bind((self.ip, 0)))?;
+        let addr = match listener.local_addr()? {
+            std::net::SocketAddr::V4(addr) => addr,
+            std::net::SocketAddr::V6(_) => unreachable!("listener is bound to an IPv4 address"),
+        };
+        self.sender
+            .send((listener, upstream))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        Ok(addr)
+    }
+}
+
+impl AddressRewriter {
+    /// Creates a new rewriter. `listen_ip` is the IP that the clients should connect to.
+    pub fn new(listen_ip: Ipv4Addr, factory: impl ListenerFactory + Send + 'static) -> Self {
+        Self {
+            listen_ip,
+            factory: Box::new(factory),
+            mappings: HashMap::new(),
+        }
+    }
+
+    /// Rewrites all server addresses in the packet. Returns `true` if the packet was changed.
+    pub fn rewrite(&mut self, packet: &mut Packet) -> std::io::Result<bool> {
+        let mut changed = false;
+        if let Packet::ShipList(p) = packet {
+            for ship in p.ships.iter_mut().filter(|s| !s.ip.is_unspecified()) {
+                let upstream = SocketAddrV4::new(ship.ip, ship_port(ship.id));
+                ship.ip = *self.get_or_listen(upstream)?.ip();
+                changed = true;
+            }
+            return Ok(changed);
+        }
+        for (ip, port) in packet_addresses(packet) {
+            if ip.is_unspecified() {
+                continue;
+            }
+            match port {
+                Some(port) => {
+                    let local = self.get_or_listen(SocketAddrV4::new(*ip, *port))?;
+                    *ip = *local.ip();
+                    *port = local.port();
+                }
+                None => *ip = self.listen_ip,
+            }
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Returns the local address for the upstream address, creating a new listener if needed.
+    pub fn get_or_listen(&mut self, upstream: SocketAddrV4) -> std::io::Result<SocketAddrV4> {
+        if let Some(local) = self.mappings.get(&upstream) {
+            return Ok(*local);
+        }
+        let local = self.factory.listen(upstream)?;
+        self.mappings.insert(upstream, local);
+        Ok(local)
+    }
+
+    /// Inserts a mapping without creating a listener.
+    pub fn insert_mapping(&mut self, upstream: SocketAddrV4, local: SocketAddrV4) {
+        self.mappings.insert(upstream, local);
+    }
+
+    /// Returns the local address for the upstream address.
+    pub fn get_local(&self, upstream: SocketAddrV4) -> Option<SocketAddrV4> {
+        self.mappings.get(&upstream).copied()
+    }
+
+    /// Returns the upstream address for the local address.
+    pub fn get_upstream(&self, local: SocketAddrV4) -> Option<SocketAddrV4> {
+        self.mappings
+            .iter()
+            .find(|(_, l)| **l == local)
+            .map(|(u, _)| *u)
+    }
+
+    /// Returns all known mappings in the form of (upstream, local).
+    pub fn mappings(&self) -> impl Iterator<Item = (SocketAddrV4, SocketAddrV4)> + '_ {
+        self.mappings.iter().map(|(u, l)| (*u, *l))
+    }
+}
+
+impl std::fmt::Debug for AddressRewriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AddressRewriter")
+            .field("listen_ip", &self.listen_ip)
+            .field("mappings", &self.mappings)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Rewrites packets heading to the client. If the listener creation fails, the packet is
+/// forwarded unchanged.
+impl ProxyHandler<Packet> for AddressRewriter {
+    fn on_packet(
+        &mut self,
+        _: &mut ProxyContext<Packet>,
+        direction: Direction,
+        mut packet: Packet,
+    ) -> Action<Packet> {
+        if matches!(direction, Direction::ToClient) {
+            let _ = self.rewrite(&mut packet);
+        }
+        Action::Forward(packet)
+    }
+}
+
+/// Returns the port of the ship with the provided ID (e.g. `12100` for ship `1000`). Ship 10 uses
+/// port `12000`.
+pub const fn ship_port(id: u32) -> u16 {
+    12000 + (id / 1000 % 10) as u16 * 100
+}
+
+/// Returns all server addresses embedded in the packet. Port is [`None`] if the packet contains
+/// only an IP.
+pub fn packet_addresses(packet: &mut Packet) -> Vec<(&mut Ipv4Addr, Option<&mut u16>)> {
+    match packet {
+        Packet::ShipList(p) => p.ships.iter_mut().map(|s| (&mut s.ip, None)).collect(),
+        Packet::BlockList(p) => p
+            .blocks
+            .iter_mut()
+            .map(|b| (&mut b.ip, Some(&mut b.port)))
+            .collect(),
+        Packet::AllBlocksList(p) => p
+            .blocks
+            .iter_mut()
+            .map(|b| (&mut b.ip, Some(&mut b.port)))
+            .collect(),
+        Packet::BlockSwitchResponse(p) => vec![(&mut p.ip, Some(&mut p.port))],
+        Packet::BlockBalance(p) => vec![(&mut p.ip, Some(&mut p.port))],
+        _ => vec![],
+    }
+}
@@ -0,0 +1,522 @@
+#![cfg(feature = "connection")]
+
+use pso2packetlib::{
+    connection::{Connection, ProtocolEngine},
+    protocol::{server::ServerHelloPacket, Packet, PacketType},
+    PrivateKey, PublicKey,
+};
+
+fn engine(packet_type: PacketType) -> ProtocolEngine<Packet> {
+    ProtocolEngine::new(packet_type, PrivateKey::None, PublicKey::None)
+}
+
+#[test]
+fn test_engine_roundtrip() {
+    let mut client = engine(PacketType::NGS);
+    let mut server = engine(PacketType::NGS);
+
+    let hello = Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid: 2,
+        unk2: 0x68,
+    });
+    server.send_packet(&hello).unwrap();
+    server.send_packet(&Packet::InitialLoad).unwrap();
+    let data = server.take_pending();
+    assert!(server.pending_data().is_empty());
+
+    // feed the data byte by byte to check partial reads
+    let mut packets = vec![];
+    for byte in data {
+        client.receive_data(&[byte]).unwrap();
+        while let Some(packet) = client.poll_packet().unwrap() {
+            packets.push(packet);
+        }
+    }
+    assert_eq!(packets, vec![hello, Packet::InitialLoad]);
+}
+
+#[test]
+fn test_engine_consume() {
+    let mut client = engine(PacketType::Classic);
+    let mut server = engine(PacketType::Classic);
+
+    client.send_packet(&Packet::InitialLoad).unwrap();
+    let len = client.pending_data().len();
+    server.receive_data(&client.pending_data()[..4]).unwrap();
+    client.consume(4);
+    assert_eq!(server.poll_packet().unwrap(), None);
+    server.receive_data(client.pending_data()).unwrap();
+    client.consume(len);
+    assert!(client.pending_data().is_empty());
+    assert_eq!(server.poll_packet().unwrap(), Some(Packet::InitialLoad));
+}
+
+#[cfg(feature = "base_enc")]
+#[test]
+fn test_engine_handshake() {
+    use pso2packetlib::protocol::login::EncryptionRequestPacket;
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = private_key.to_public_key();
+    // client side re-encrypts the request with the server's key
+    let mut client =
+        ProtocolEngine::<Packet>::new(PacketType::NA, PrivateKey::None, PublicKey::Key(public_key));
+    let mut server = ProtocolEngine::<Packet>::new(
+        PacketType::NA,
+        PrivateKey::Key(private_key),
+        PublicKey::None,
+    );
+
+    // aes encrypted secret + aes key
+    let key = [0x42u8; 0x20];
+    let iv: [u8; 0x10] = std::array::from_fn(|i| i as u8);
+    let mut blob = vec![0x13u8; 0x30];
+    {
+        use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+        cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut blob, 0x20)
+            .unwrap();
+    }
+    blob.extend_from_slice(&key);
+    let request = Packet::EncryptionRequest(EncryptionRequestPacket {
+        rsa_data: blob.clone().into(),
+    });
+    client.send_packet(&request).unwrap();
+    client.send_packet(&Packet::InitialLoad).unwrap();
+    server.receive_data(&client.take_pending()).unwrap();
+
+    let Some(Packet::EncryptionRequest(received)) = server.poll_packet().unwrap() else {
+        panic!("expected encryption request");
+    };
+    assert_eq!(&received.rsa_data[..], &blob[..]);
+    assert_eq!(server.get_key(), client.get_key());
+    assert!(!server.get_key().is_empty());
+    assert_eq!(server.poll_packet().unwrap(), Some(Packet::InitialLoad));
+}
+
+#[cfg(all(unix, not(feature = "tokio")))]
+#[test]
+fn test_connection_unix_stream() {
+    let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+
+    client.write_packet(&Packet::InitialLoad).unwrap();
+    assert_eq!(server.read_packet().unwrap(), Packet::InitialLoad);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_connection_duplex() {
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+
+    let hello = Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid: 2,
+        unk2: 0x68,
+    });
+    let send = async {
+        server.write_packet_async(&hello).await.unwrap();
+        server
+            .write_packet_async(&Packet::InitialLoad)
+            .await
+            .unwrap();
+    };
+    let recv = async {
+        let first = client.read_packet_async().await.unwrap();
+        let second = client.read_packet_async().await.unwrap();
+        (first, second)
+    };
+    let ((), packets) = tokio::join!(send, recv);
+    assert_eq!(packets, (hello, Packet::InitialLoad));
+}
+
+#[cfg(all(feature = "tokio", feature = "split_connection"))]
+#[tokio::test]
+async fn test_split_duplex() {
+    use pso2packetlib::connection::RingBuffer;
+
+    let (client, server) = tokio::io::duplex(64);
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let ring = RingBuffer::new(2);
+    client.add_sink(ring.clone());
+
+    let (mut read, mut write) = client.into_split().unwrap();
+    write
+        .write_packet_async(&Packet::InitialLoad)
+        .await
+        .unwrap();
+    assert_eq!(
+        server.read_packet_async().await.unwrap(),
+        Packet::InitialLoad
+    );
+    server
+        .write_packet_async(&Packet::InitialLoad)
+        .await
+        .unwrap();
+    assert_eq!(read.read_packet_async().await.unwrap(), Packet::InitialLoad);
+    let stored = ring.packets();
+    assert_eq!(stored.len(), 2);
+    assert!(stored.iter().all(|p| p.packet == Some(Packet::InitialLoad)));
+}
+
+#[cfg(all(feature = "tokio", feature = "split_connection", feature = "base_enc"))]
+#[tokio::test]
+async fn test_sinks_replaced_request() {
+    use pso2packetlib::{
+        connection::RingBuffer,
+        protocol::{login::EncryptionRequestPacket, ProtocolRW},
+    };
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    // aes encrypted secret + aes key
+    let key = [0x42u8; 0x20];
+    let iv: [u8; 0x10] = std::array::from_fn(|i| i as u8);
+    let mut blob = vec![0x13u8; 0x30];
+    {
+        use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+        cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+            .encrypt_padded_mut::<Pkcs7>(&mut blob, 0x20)
+            .unwrap();
+    }
+    blob.extend_from_slice(&key);
+    let request = Packet::EncryptionRequest(EncryptionRequestPacket {
+        rsa_data: blob.into(),
+    });
+
+    // split and unsplit connections pass the request that was actually sent
+    let ring = RingBuffer::new(2);
+    for split in [false, true] {
+        let (client, _server) = tokio::io::duplex(4096);
+        let mut client = Connection::<Packet, _>::from_stream(
+            client,
+            PacketType::NA,
+            PrivateKey::None,
+            PublicKey::Key(private_key.to_public_key()),
+        );
+        client.add_sink(ring.clone());
+        if split {
+            let (_read, mut write) = client.into_split().unwrap();
+            write.write_packet_async(&request).await.unwrap();
+        } else {
+            client.write_packet_async(&request).await.unwrap();
+        }
+    }
+    let stored = ring.packets();
+    assert_eq!(stored.len(), 2);
+    for captured in stored {
+        let sent = Packet::read(&captured.data, PacketType::NA).unwrap();
+        assert_eq!(captured.packet.as_ref(), sent.first());
+        assert_ne!(captured.packet, Some(request.clone()));
+    }
+}
+
+#[cfg(any(feature = "base_enc", feature = "ngs_enc", feature = "vita_enc"))]
+fn client_handshake(packet_type: PacketType) {
+    use pso2packetlib::protocol::login::EncryptionResponsePacket;
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = private_key.to_public_key();
+    let mut client =
+        ProtocolEngine::<Packet>::new(packet_type, PrivateKey::None, PublicKey::Key(public_key));
+    let mut server =
+        ProtocolEngine::<Packet>::new(packet_type, PrivateKey::Key(private_key), PublicKey::None);
+
+    client.start_handshake().unwrap();
+    assert!(client.is_handshake_pending());
+    client.send_packet(&Packet::InitialLoad).unwrap();
+    server.receive_data(&client.take_pending()).unwrap();
+    let Some(Packet::EncryptionRequest(_)) = server.poll_packet().unwrap() else {
+        panic!("expected encryption request");
+    };
+    assert_eq!(server.poll_packet().unwrap(), Some(Packet::InitialLoad));
+    assert_eq!(server.get_key(), client.get_key());
+
+    let response = Packet::EncryptionResponse(EncryptionResponsePacket {
+        data: server.get_key().into(),
+    });
+    server.send_packet(&response).unwrap();
+    server.send_packet(&Packet::InitialLoad).unwrap();
+    client.receive_data(&server.take_pending()).unwrap();
+    assert_eq!(client.poll_packet().unwrap(), Some(response));
+    assert!(!client.is_handshake_pending());
+    assert_eq!(client.poll_packet().unwrap(), Some(Packet::InitialLoad));
+}
+
+#[cfg(feature = "base_enc")]
+#[test]
+fn test_client_handshake_na() {
+    client_handshake(PacketType::NA);
+}
+
+#[cfg(feature = "ngs_enc")]
+#[test]
+fn test_client_handshake_ngs() {
+    client_handshake(PacketType::NGS);
+}
+
+#[cfg(feature = "vita_enc")]
+#[test]
+fn test_client_handshake_vita() {
+    client_handshake(PacketType::Vita);
+}
+
+#[cfg(feature = "base_enc")]
+#[test]
+fn test_client_handshake_mismatch() {
+    use pso2packetlib::{connection::ConnectionError, protocol::login::EncryptionResponsePacket};
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = private_key.to_public_key();
+    let mut client =
+        ProtocolEngine::<Packet>::new(PacketType::NA, PrivateKey::None, PublicKey::Key(public_key));
+    let mut server = ProtocolEngine::<Packet>::new(
+        PacketType::NA,
+        PrivateKey::Key(private_key),
+        PublicKey::None,
+    );
+
+    client.start_handshake().unwrap();
+    server.receive_data(&client.take_pending()).unwrap();
+    server.poll_packet().unwrap();
+    server
+        .send_packet(&Packet::EncryptionResponse(EncryptionResponsePacket {
+            data: vec![0; 0x30].into(),
+        }))
+        .unwrap();
+    client.receive_data(&server.take_pending()).unwrap();
+    assert!(matches!(
+        client.poll_packet(),
+        Err(ConnectionError::InvalidEncryptionResponse)
+    ));
+}
+
+#[cfg(all(unix, not(feature = "tokio"), feature = "base_enc"))]
+#[test]
+fn test_key_log() {
+    use pso2packetlib::connection::{read_key_log, KeyLog};
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = private_key.to_public_key();
+    let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NA,
+        PrivateKey::None,
+        PublicKey::Key(public_key),
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        PacketType::NA,
+        PrivateKey::Key(private_key),
+        PublicKey::None,
+    );
+    let path = std::env::temp_dir().join(format!("key_log_test_{}.log", std::process::id()));
+    let key_log = KeyLog::create(&path).unwrap();
+    let peer = "40.91.76.146:12100".parse().unwrap();
+    client.set_key_log_with_peer(key_log.clone(), Some(peer));
+    server.set_key_log_with_peer(key_log, None);
+
+    client.start_handshake().unwrap();
+    let Packet::EncryptionRequest(request) = server.read_packet().unwrap() else {
+        panic!("expected encryption request");
+    };
+    let entries =
+        read_key_log(std::io::BufReader::new(std::fs::File::open(&path).unwrap())).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].peer, Some(peer));
+    assert_eq!(entries[1].peer, None);
+    assert_eq!(entries[0].request_id, entries[1].request_id);
+    // server replaces the request data with the decrypted secret
+    assert_eq!(entries[0].secret, request.rsa_data.to_vec());
+    assert_eq!(entries[1].secret, request.rsa_data.to_vec());
+    assert_eq!(
+        entries[0].to_string().parse::<_>().ok(),
+        Some(entries[0].clone())
+    );
+}
+
+#[cfg(all(feature = "codec", feature = "base_enc"))]
+#[tokio::test]
+async fn test_codec_handshake() {
+    use futures::{SinkExt, StreamExt};
+    use pso2packetlib::{connection::PacketCodec, protocol::login::EncryptionResponsePacket};
+    use tokio_util::codec::Framed;
+
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let public_key = private_key.to_public_key();
+    let (client, server) = tokio::io::duplex(4096);
+    let mut client = Framed::new(
+        client,
+        PacketCodec::<Packet>::new(PacketType::NA, PrivateKey::None, PublicKey::Key(public_key)),
+    );
+    let mut server = Framed::new(
+        server,
+        PacketCodec::<Packet>::new(
+            PacketType::NA,
+            PrivateKey::Key(private_key),
+            PublicKey::None,
+        ),
+    );
+
+    client.codec_mut().engine_mut().start_handshake().unwrap();
+    client.send(Packet::InitialLoad).await.unwrap();
+    let Some(Ok(Packet::EncryptionRequest(_))) = server.next().await else {
+        panic!("expected encryption request");
+    };
+    assert_eq!(server.next().await.unwrap().unwrap(), Packet::InitialLoad);
+
+    let key = server.codec().engine().get_key();
+    let response = Packet::EncryptionResponse(EncryptionResponsePacket { data: key.into() });
+    server.send(response.clone()).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), response);
+    assert!(!client.codec().engine().is_handshake_pending());
+}
+
+#[cfg(feature = "codec")]
+#[tokio::test]
+async fn test_codec_truncated() {
+    use futures::StreamExt;
+    use pso2packetlib::{connection::PacketCodec, protocol::ProtocolRW};
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::FramedRead;
+
+    let (mut client, server) = tokio::io::duplex(1024);
+    let mut server = FramedRead::new(
+        server,
+        PacketCodec::<Packet>::new(PacketType::NGS, PrivateKey::None, PublicKey::None),
+    );
+    let data = Packet::InitialLoad.write(PacketType::NGS);
+    client.write_all(&data).await.unwrap();
+    client.write_all(&data[..4]).await.unwrap();
+    drop(client);
+
+    assert_eq!(server.next().await.unwrap().unwrap(), Packet::InitialLoad);
+    assert!(server.next().await.unwrap().is_err());
+}
+
+#[cfg(all(unix, not(feature = "tokio")))]
+#[test]
+fn test_sinks() {
+    use pso2packetlib::{
+        connection::{ChannelSink, RingBuffer},
+        protocol::Direction,
+    };
+
+    let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let ring = RingBuffer::new(2);
+    server.add_sink(ring.clone());
+    server.set_direction(Direction::ToClient);
+    let (sink, receiver) = ChannelSink::new();
+    client.add_sink(sink);
+
+    let hello = Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid: 2,
+        unk2: 0x68,
+    });
+    server.write_packet(&Packet::ServerPing).unwrap();
+    server.write_packet(&hello).unwrap();
+    server.write_packet(&Packet::InitialLoad).unwrap();
+    client.read_packet().unwrap();
+    client.read_packet().unwrap();
+    client.write_packet(&Packet::ServerPong).unwrap();
+
+    let stored = ring.packets();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].packet, Some(hello.clone()));
+    assert_eq!(stored[1].packet, Some(Packet::InitialLoad));
+    assert_eq!(stored[1].direction, Direction::ToClient);
+
+    let received: Vec<_> = receiver.try_iter().collect();
+    assert_eq!(received.len(), 3);
+    assert_eq!(received[0].direction, Direction::ToClient);
+    assert_eq!(received[0].packet, Some(Packet::ServerPing));
+    assert_eq!(received[1].data, stored[0].data);
+    assert_eq!(received[2].direction, Direction::ToServer);
+    assert_eq!(received[2].packet, Some(Packet::ServerPong));
+}
+
+#[cfg(all(unix, feature = "json", not(feature = "tokio")))]
+#[test]
+fn test_json_sink() {
+    use pso2packetlib::connection::JsonSink;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+    impl std::io::Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let (client, _server) = std::os::unix::net::UnixStream::pair().unwrap();
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        PacketType::NGS,
+        PrivateKey::None,
+        PublicKey::None,
+    );
+    let output = Shared::default();
+    client.add_sink(JsonSink::new(output.clone()));
+    client.write_packet(&Packet::ServerPong).unwrap();
+    client.write_packet(&Packet::InitialLoad).unwrap();
+
+    let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<_> = output.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains(r#""direction":"ToServer""#));
+    assert!(lines[0].contains(r#""packet":"ServerPong""#));
+    assert!(lines[1].contains(r#""data":null"#));
+}
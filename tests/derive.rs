@@ -296,6 +296,87 @@ fn test_attrs() {
     assert_eq!(data, data2);
 }
 
+#[test]
+fn test_spans() {
+    use pso2packetlib::protocol::spans::{SpanKind, SpanRecorder};
+
+    let packet = Attributes {
+        a: 1,
+        b: 2,
+        c: 0,
+        d: 3,
+    };
+    let mut data = Packet::Attributes(packet).write(PacketType::NA);
+    data.extend(
+        Packet::Variables(Variables {
+            vec: vec![1, 2, 3].into(),
+            fixed_vec: vec![].into(),
+            str: String::new(),
+            fixed_str: String::new().into(),
+            astr: String::new().into(),
+            fixed_astr: String::new().into(),
+            var_1: vec![14].into(),
+            var_2: vec![].into(),
+        })
+        .write(PacketType::NA),
+    );
+    let mut spans = SpanRecorder::new();
+    let packets = Packet::read_spanned(&data, PacketType::NA, &mut spans)
+        .expect("Failed to read the packets");
+    let Some(Packet::Attributes(packet)) = packets.first() else {
+        panic!("Got incorrect packet")
+    };
+    assert_eq!(packet.d, 3);
+    let spans = spans.into_spans();
+    assert_eq!(spans.len(), 2);
+
+    let attributes = &spans[0];
+    assert_eq!(attributes.name, "Attributes");
+    assert_eq!(attributes.range(), 0..20);
+    let children: Vec<_> = attributes
+        .children
+        .iter()
+        .map(|s| (s.name.as_ref(), s.kind, s.range()))
+        .collect();
+    assert_eq!(
+        children,
+        [
+            ("len", SpanKind::Length, 0..4),
+            ("header", SpanKind::Value, 4..8),
+            ("padding", SpanKind::Padding, 8..10),
+            ("a", SpanKind::Value, 10..11),
+            ("padding", SpanKind::Padding, 11..13),
+            ("const", SpanKind::Constant, 13..15),
+            ("b", SpanKind::Value, 15..16),
+            ("d", SpanKind::Value, 16..17),
+        ]
+    );
+
+    let variables = &spans[1];
+    assert_eq!(variables.name, "Variables");
+    assert_eq!(variables.offset, 20);
+    let vec = variables.find("vec").expect("Span should be recorded");
+    assert_eq!(vec.range(), 28..36);
+    assert_eq!(
+        variables.find("vec.len").map(|s| s.kind),
+        Some(SpanKind::Length)
+    );
+    assert_eq!(variables.find("vec.data").map(|s| s.range()), Some(32..35));
+    let var_1 = variables.find("var_1").expect("Span should be recorded");
+    let children: Vec<_> = var_1
+        .children
+        .iter()
+        .map(|s| (s.name.as_ref(), s.range()))
+        .collect();
+    assert_eq!(
+        children,
+        [
+            ("len", var_1.offset..var_1.offset + 2),
+            ("0", var_1.offset + 2..var_1.offset + 3)
+        ]
+    );
+}
+
 #[test]
 fn test_helpers() {
     let mut data = vec![
@@ -323,3 +404,429 @@ fn test_helpers() {
     let data2 = Packet::Helpers(packet).write(PacketType::Classic);
     assert_eq!(data, data2);
 }
+
+#[test]
+fn test_trailing() {
+    use pso2packetlib::protocol::PacketError;
+    let mut variables = vec![
+        0, 0, 0, 0, // len
+        1, 2, 4, 0, // id
+        3, 0, 0, 0, // len
+        1, 2, 3, // vec
+        0, // padding
+        4, 5, 6, 7, 8, 9, 10, 11, 12, 13, // fixed_vec
+        0, 0, // padding
+        4, 0, 0, 0, // len
+        0x41, 0x00, 0x42, 0x00, 0x43, 0x00, 0x00, 0x00, // str
+        0x41, 0x00, 0x42, 0x00, 0x43, 0x00, 0x00, 0x00, 0x00, 0x00, // fixed_str
+        4, 0, 0, 0, // len
+        0x41, 0x42, 0x43, 0x00, // astr
+        0x41, 0x42, 0x43, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // fixed_astr
+        1, 0,  // len
+        14, // var_1
+        2, 0, 0, 0, // len
+        15, 16, // var_2
+        0, 0, 0, // padding
+    ];
+    let len = variables.len() as u32;
+    variables[..4].copy_from_slice(&len.to_le_bytes());
+    let helpers = [
+        20, 0, 0, 0, // len
+        1, 5, 0, 0, // id
+        6, // flags,
+        5, 0, // bitflags,
+        1, // enum,
+        0xAA, 0, 0, 0, 0, 0, 0, 0, // unknown
+    ];
+    let unknown = [
+        8, 0, 0, 0, // len
+        1, 6, 0, 0, // id
+    ];
+    let data = [&variables[..], &helpers, &unknown].concat();
+
+    let packets = Packet::read(&data, PacketType::Classic).expect("Failed to read the packets");
+    assert_eq!(packets.len(), 3);
+
+    // alignment padding is not trailing data
+    Packet::read_strict(&variables, PacketType::Classic).expect("Failed to read the packet");
+    let error = Packet::read_strict(&data, PacketType::Classic)
+        .err()
+        .expect("Trailing data was not detected");
+    let PacketError::TrailingDataError { packet_name, len } = error else {
+        panic!("Incorrect error: {error}")
+    };
+    assert_eq!(packet_name, "Helpers");
+    assert_eq!(len, 8);
+
+    let packets =
+        Packet::read_lenient(&data, PacketType::Classic).expect("Failed to read the packets");
+    assert!(matches!(packets[0], (Packet::Variables(_), ref data) if data.is_empty()));
+    assert!(matches!(packets[1], (Packet::Helpers(_), ref data) if data[..] == helpers[12..]));
+    assert!(matches!(packets[2], (Packet::Unknown(_), ref data) if data.is_empty()));
+}
+
+#[test]
+fn test_limits() {
+    use pso2packetlib::protocol::{
+        limits::{DecodeLimits, LimitKind},
+        PacketError,
+    };
+    let packet = Packet::Variables(Variables {
+        vec: vec![0; 100].into(),
+        fixed_vec: Default::default(),
+        str: String::from("ABCDEFGH"),
+        fixed_str: Default::default(),
+        astr: String::from("ABC").into(),
+        fixed_astr: Default::default(),
+        var_1: vec![1, 2, 3].into(),
+        var_2: vec![].into(),
+    });
+    let data = [
+        packet.write(PacketType::Classic),
+        packet.write(PacketType::Classic),
+    ]
+    .concat();
+    let read_limited = |limits: DecodeLimits| {
+        Packet::read_limited(&data, PacketType::Classic, &limits).map(|p| p.len())
+    };
+    let limit_error = |limits: DecodeLimits| match read_limited(limits) {
+        Err(PacketError::LimitError { kind, len, max, .. }) => (kind, len, max),
+        Err(e) => panic!("Incorrect error: {e}"),
+        Ok(_) => panic!("Limit was not applied"),
+    };
+
+    let limits = DecodeLimits {
+        max_elements: 100,
+        max_string_len: 9,
+        max_alloc: 100 + 9 * 2 + 4 + 3,
+    };
+    assert_eq!(read_limited(limits).expect("Failed to read the packets"), 2);
+    assert_eq!(
+        limit_error(DecodeLimits {
+            max_elements: 99,
+            ..limits
+        }),
+        (LimitKind::Elements, 100, 99)
+    );
+    assert_eq!(
+        limit_error(DecodeLimits {
+            max_string_len: 8,
+            ..limits
+        }),
+        (LimitKind::StringLength, 9, 8)
+    );
+    assert_eq!(
+        limit_error(DecodeLimits {
+            max_alloc: 120,
+            ..limits
+        }),
+        (LimitKind::Allocation, 122, 120)
+    );
+    assert_eq!(read_limited(DecodeLimits::unlimited()).unwrap(), 2);
+    // limits are not applied to normal reads
+    assert_eq!(Packet::read(&data, PacketType::Classic).unwrap().len(), 2);
+}
+
+#[test]
+fn test_limits_manual() {
+    use pso2packetlib::protocol::{
+        items::{ItemId, LoadItemPacket, NamedId},
+        limits::{DecodeLimits, LimitKind},
+        Packet, PacketError,
+    };
+    let packet = Packet::LoadItem(LoadItemPacket {
+        items: vec![
+            NamedId {
+                name: "a".repeat(50),
+                id: ItemId::default(),
+            };
+            100
+        ],
+    });
+    let data = packet.write(PacketType::Classic);
+    let limit_kind =
+        |limits: DecodeLimits| match Packet::read_limited(&data, PacketType::Classic, &limits) {
+            Err(PacketError::LimitError { kind, .. }) => kind,
+            Err(e) => panic!("Incorrect error: {e}"),
+            Ok(_) => panic!("Limit was not applied"),
+        };
+
+    let limits = DecodeLimits {
+        max_elements: 10,
+        max_string_len: 10,
+        max_alloc: 64,
+    };
+    assert_eq!(limit_kind(limits), LimitKind::Elements);
+    assert_eq!(
+        limit_kind(DecodeLimits {
+            max_elements: 100,
+            max_alloc: usize::MAX,
+            ..limits
+        }),
+        LimitKind::StringLength
+    );
+    let read = Packet::read_limited(&data, PacketType::Classic, &DecodeLimits::unlimited())
+        .expect("Failed to read the packet");
+    assert_eq!(read, vec![packet]);
+}
+
+#[test]
+fn test_reflect() {
+    use pso2packetlib::protocol::reflect::{Value, ValueMut};
+    let mut packet = Packet::Helpers(Helpers {
+        flags: HelperFlags { a: true, b: false },
+        bitflags: HelperBitFlags::A | HelperBitFlags::C,
+        e: Enum::B,
+    });
+    let reflect = packet.as_reflect().expect("No reflection for packet");
+    assert_eq!(reflect.type_name(), "Helpers");
+    let mut names = vec![];
+    reflect.visit_fields(&mut |name, value: Value| {
+        match (name, value) {
+            ("flags", Value::Struct(flags)) => {
+                assert_eq!(flags.type_name(), "HelperFlags");
+                flags.visit_fields(&mut |name, value: Value| match (name, value) {
+                    ("a", Value::Bool(true)) | ("b", Value::Bool(false)) => {}
+                    (name, value) => panic!("Incorrect flag {name}: {value:?}"),
+                });
+            }
+            ("bitflags", Value::U16(5)) | ("e", Value::Enum("B")) => {}
+            (name, value) => panic!("Incorrect field {name}: {value:?}"),
+        }
+        names.push(name);
+    });
+    assert_eq!(names, ["flags", "bitflags", "e"]);
+
+    let mut packet2 = Packet::Numbers(Numbers {
+        uint8: 1,
+        int8: -1,
+        uint16: 2,
+        int16: -2,
+        uint32: 3,
+        int32: -3,
+        uint64: 4,
+        int64: -4,
+        uint128: 5,
+        int128: -5,
+        float16: half::f16::from_f32(1.5),
+        float32: 2.5,
+        float64: 3.5,
+    });
+    packet2
+        .as_reflect_mut()
+        .expect("No reflection for packet")
+        .visit_fields_mut(&mut |_, value: ValueMut| match value {
+            ValueMut::U32(x) => *x = 10,
+            ValueMut::F64(x) => *x = 0.5,
+            _ => {}
+        });
+    let Packet::Numbers(numbers) = &packet2 else {
+        panic!("Got incorrect packet")
+    };
+    assert_eq!(numbers.uint32, 10);
+    assert_eq!(numbers.float64, 0.5);
+    let mut sum = 0;
+    packet2
+        .as_reflect()
+        .unwrap()
+        .visit_fields(&mut |_, value: Value| sum += value.as_i128().unwrap_or_default());
+    assert_eq!(sum, -15);
+
+    assert!(Packet::None.as_reflect().is_none());
+    assert!(packet.as_reflect_mut().is_some());
+}
+
+#[test]
+fn test_diff() {
+    use pso2packetlib::protocol::{
+        diff::{diff, ChangeKind, FieldChange},
+        questlist::{MinimapRevealPacket, RevealedRegions},
+        PacketHeader,
+    };
+    let variables = |str: &str, vec: Vec<u8>, var_2: Vec<u8>| {
+        Packet::Variables(Variables {
+            vec: vec.into(),
+            fixed_vec: vec![4, 5, 6, 7, 8, 9, 10, 11, 12, 13].into(),
+            str: String::from(str),
+            fixed_str: String::from("ABC").into(),
+            astr: String::from("ABC").into(),
+            fixed_astr: String::from("ABC").into(),
+            var_1: vec![14].into(),
+            var_2: var_2.into(),
+        })
+    };
+    let a = variables("ABC", vec![1, 2, 3], vec![15, 16]);
+    assert!(diff(&a, &a).is_empty());
+    let b = variables("ABD", vec![1, 5, 3, 4], vec![15, 17, 18]);
+    let changes = diff(&a, &b);
+    assert_eq!(
+        changes,
+        [
+            FieldChange {
+                path: "vec".into(),
+                kind: ChangeKind::Bytes {
+                    offset: 1,
+                    old: vec![2],
+                    new: vec![5]
+                }
+            },
+            FieldChange {
+                path: "vec".into(),
+                kind: ChangeKind::Bytes {
+                    offset: 3,
+                    old: vec![],
+                    new: vec![4]
+                }
+            },
+            FieldChange {
+                path: "str".into(),
+                kind: ChangeKind::Changed {
+                    old: "\"ABC\"".into(),
+                    new: "\"ABD\"".into()
+                }
+            },
+            FieldChange {
+                path: "var_2[1]".into(),
+                kind: ChangeKind::Changed {
+                    old: "16".into(),
+                    new: "17".into()
+                }
+            },
+            FieldChange {
+                path: "var_2[2]".into(),
+                kind: ChangeKind::Added("18".into())
+            },
+        ]
+    );
+
+    let helpers = |b| {
+        Packet::Helpers(Helpers {
+            flags: HelperFlags { a: true, b },
+            bitflags: HelperBitFlags::A,
+            e: Enum::A,
+        })
+    };
+    let changes = diff(&helpers(false), &helpers(true));
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].to_string(), "flags.b: false -> true");
+
+    let changes = diff(&a, &helpers(true));
+    assert_eq!(changes[0].to_string(), "<packet>: Variables -> Helpers");
+
+    let unknown = |data| Packet::Unknown((PacketHeader::new(2, 1, Default::default()), data));
+    let changes = diff(&unknown(vec![1, 2, 3, 4]), &unknown(vec![1, 2, 0, 4]));
+    assert_eq!(
+        changes,
+        [FieldChange {
+            path: "".into(),
+            kind: ChangeKind::Bytes {
+                offset: 10,
+                old: vec![3],
+                new: vec![0]
+            }
+        }]
+    );
+
+    // manually implemented fields are compared using the packet data
+    let reveal = |data| {
+        pso2packetlib::protocol::Packet::MinimapReveal(MinimapRevealPacket {
+            revealed_zones: RevealedRegions::new(data),
+            ..Default::default()
+        })
+    };
+    assert!(diff(&reveal([1; 10]), &reveal([1; 10])).is_empty());
+    let changes = diff(&reveal([1; 10]), &reveal([2; 10]));
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].to_string(), "revealed_zones: not comparable");
+}
+
+#[test]
+fn test_schema() {
+    use pso2packetlib::protocol::{
+        schema::{Padding, TypeSchema},
+        Flags, PacketCategory, PacketReadWrite,
+    };
+
+    let variants: Vec<_> = Packet::PACKETS
+        .iter()
+        .map(|p| (p.name, p.id, p.subid))
+        .collect();
+    assert_eq!(
+        variants,
+        [
+            ("Numbers", 1, 1),
+            ("Variables", 1, 2),
+            ("Misc", 1, 3),
+            ("Attributes", 1, 4),
+            ("Helpers", 1, 5),
+        ]
+    );
+    assert_eq!(Packet::PACKETS[0].packet_types.len(), 5);
+    assert_eq!(Packet::PACKETS[0].category, PacketCategory::Unknown);
+    assert_eq!(Numbers::SCHEMA.and_then(|s| s.fixed_size()), Some(76));
+
+    let variables = Variables::SCHEMA.expect("Schema should be derived");
+    assert_eq!(variables.magic, Some((0x10, 0x10)));
+    assert_eq!(variables.flags, Flags::PACKED);
+    assert_eq!(
+        variables.fields[1].type_name,
+        "pso2packetlib::fixed_types::FixedBytes<10>"
+    );
+    assert_eq!(variables.fields[1].fixed_size(), Some(12));
+    assert_eq!(variables.fields[0].ty, &TypeSchema::Bytes { padding: true });
+    assert_eq!(
+        variables.fields[6].ty,
+        &TypeSchema::VecUSize {
+            len: &TypeSchema::U16,
+            value: &TypeSchema::U8
+        }
+    );
+
+    let attributes = Attributes::SCHEMA.expect("Schema should be derived");
+    assert_eq!(attributes.fields[0].padding_before, &[Padding::Seek(2)]);
+    assert_eq!(attributes.fields[0].padding_after, 2);
+    assert_eq!(attributes.fields[1].padding_before, &[Padding::ConstU16(5)]);
+    assert_eq!(attributes.fields[2].only_on, Some(&[PacketType::JP][..]));
+    assert_eq!(attributes.fields[3].not_on, Some(&[PacketType::JP][..]));
+
+    let helpers = Helpers::SCHEMA.expect("Schema should be derived");
+    let TypeSchema::Flags(flags) = helpers.fields[0].ty else {
+        panic!("Incorrect flags schema")
+    };
+    assert_eq!(flags.flags, &[("a", 2), ("b", 4)]);
+    assert_eq!(
+        helpers.fields[1].ty,
+        &TypeSchema::BitFlags(&TypeSchema::U16)
+    );
+    let TypeSchema::Enum(e) = helpers.fields[2].ty else {
+        panic!("Incorrect enum schema")
+    };
+    assert_eq!(e.variants, &[("B", 1)]);
+    assert_eq!(e.default, Some("A"));
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_schema_json() {
+    let json = pso2packetlib::protocol::schema::to_json::<Packet>();
+    let schema: serde_json::Value =
+        serde_json::from_str(&json).expect("Schema should be valid JSON");
+    assert_eq!(schema[1]["name"], "Variables");
+    assert_eq!(schema[1]["schema"]["magic"], serde_json::json!([16, 16]));
+    assert_eq!(
+        schema[3]["schema"]["fields"][2]["only_on"],
+        serde_json::json!(["JP"])
+    );
+}
+
+#[test]
+fn test_dissector() {
+    let lua = pso2packetlib::dissector::wireshark_lua::<Packet>(PacketType::NGS);
+    assert!(lua.contains(
+        "add_packet({ \"NGS\", \"Classic\", \"NA\", \"JP\", \"Vita\" }, 0x01, 0x0002, \"Variables\", \"Variables\", 16, 16)"
+    ));
+    assert!(
+        lua.contains("f[\"Numbers.uint8\"] = ProtoField.uint8(\"pso2.numbers.uint8\", \"uint8\")")
+    );
+    assert!(lua.contains("if ctx.packet_type == \"JP\" then"));
+}
@@ -0,0 +1,79 @@
+#![cfg(all(feature = "connection", any(feature = "ngs_enc", feature = "vita_enc")))]
+
+use pso2packetlib::{
+    connection::ProtocolEngine,
+    encryption::{Encryption, StreamDecryptor},
+    protocol::{login::EncryptionResponsePacket, Packet, PacketType, ProtocolRW},
+    PrivateKey, PublicKey,
+};
+
+fn decrypt_all(decryptor: &mut StreamDecryptor, packet_type: PacketType) -> Vec<Packet> {
+    let mut packets = vec![];
+    while let Some(data) = decryptor.next_packet().unwrap() {
+        packets.append(&mut Packet::read(&data, packet_type).unwrap());
+    }
+    packets
+}
+
+// captures a session and decrypts it offline
+fn offline_decryption(packet_type: PacketType) {
+    let private_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let mut client = ProtocolEngine::<Packet>::new(
+        packet_type,
+        PrivateKey::None,
+        PublicKey::Key(private_key.to_public_key()),
+    );
+    let mut server = ProtocolEngine::<Packet>::new(
+        packet_type,
+        PrivateKey::Key(private_key.clone()),
+        PublicKey::None,
+    );
+    client.start_handshake().unwrap();
+    client.send_packet(&Packet::InitialLoad).unwrap();
+    let to_server = client.take_pending();
+    server.receive_data(&to_server).unwrap();
+    while server.poll_packet().unwrap().is_some() {}
+    let response = Packet::EncryptionResponse(EncryptionResponsePacket {
+        data: server.get_key().into(),
+    });
+    server.send_packet(&response).unwrap();
+    server.send_packet(&Packet::ServerPing).unwrap();
+    let to_client = server.take_pending();
+
+    let key = PrivateKey::Key(private_key);
+    let is_ngs = matches!(packet_type, PacketType::NGS);
+    let mut client_stream = StreamDecryptor::new();
+    client_stream.push_data(&to_server).unwrap();
+    let data = client_stream.next_packet().unwrap().unwrap();
+    let Packet::EncryptionRequest(request) = Packet::read(&data, packet_type).unwrap().remove(0)
+    else {
+        panic!("expected encryption request");
+    };
+    let dec_data = Encryption::decrypt_rsa_data(&request.rsa_data, &key).unwrap();
+    client_stream.set_encryption(Encryption::from_dec_data(&dec_data, is_ngs).unwrap());
+    assert_eq!(
+        decrypt_all(&mut client_stream, packet_type),
+        vec![Packet::InitialLoad]
+    );
+
+    let mut server_stream = StreamDecryptor::with_encryption(
+        Encryption::from_rsa_data(&request.rsa_data, &key, is_ngs).unwrap(),
+    );
+    server_stream.push_data(&to_client).unwrap();
+    assert_eq!(
+        decrypt_all(&mut server_stream, packet_type),
+        vec![response, Packet::ServerPing]
+    );
+}
+
+#[cfg(feature = "ngs_enc")]
+#[test]
+fn test_offline_decryption_ngs() {
+    offline_decryption(PacketType::NGS);
+}
+
+#[cfg(feature = "vita_enc")]
+#[test]
+fn test_offline_decryption_vita() {
+    offline_decryption(PacketType::Vita);
+}
@@ -0,0 +1,337 @@
+#![cfg(all(feature = "pcap", any(feature = "ngs_enc", feature = "vita_enc")))]
+
+use pcap_file::{
+    pcap::{PcapPacket, PcapWriter},
+    pcapng::{
+        blocks::{
+            enhanced_packet::EnhancedPacketBlock, interface_description::InterfaceDescriptionBlock,
+        },
+        Block, PcapNgWriter,
+    },
+    DataLink,
+};
+use pso2packetlib::{
+    connection::{KeyLogEntry, ProtocolEngine},
+    pcap::PcapImporter,
+    ppac::{Direction, PPACReader, PPACWriter},
+    protocol::{
+        login::EncryptionResponsePacket, server::ServerHelloPacket, Packet, PacketType, ProtocolRW,
+    },
+    PrivateKey, PublicKey,
+};
+use std::{net::Ipv4Addr, time::Duration};
+
+const CLIENT: (Ipv4Addr, u16) = (Ipv4Addr::new(192, 168, 0, 2), 50000);
+const SERVER: (Ipv4Addr, u16) = (Ipv4Addr::new(40, 91, 76, 146), 12100);
+const SYN: u8 = 0x02;
+const ACK: u8 = 0x10;
+const PSH_ACK: u8 = 0x18;
+
+struct Capture {
+    // (timestamp in microseconds, frame data)
+    frames: Vec<(u64, Vec<u8>)>,
+    expected: Vec<(Direction, Packet)>,
+    key_log: KeyLogEntry,
+}
+
+// ethernet frame with an IPv4 TCP segment
+fn frame(direction: Direction, seq: u32, flags: u8, data: &[u8]) -> Vec<u8> {
+    let (src, dst) = match direction {
+        Direction::ToServer => (CLIENT, SERVER),
+        Direction::ToClient => (SERVER, CLIENT),
+    };
+    let mut out = vec![0u8; 12];
+    out.extend_from_slice(&[0x08, 0x00]);
+    out.extend_from_slice(&[0x45, 0x00]);
+    out.extend_from_slice(&(40 + data.len() as u16).to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+    out.extend_from_slice(&src.0.octets());
+    out.extend_from_slice(&dst.0.octets());
+    out.extend_from_slice(&src.1.to_be_bytes());
+    out.extend_from_slice(&dst.1.to_be_bytes());
+    out.extend_from_slice(&seq.to_be_bytes());
+    out.extend_from_slice(&[0, 0, 0, 0, 0x50, flags, 0xFF, 0xFF, 0, 0, 0, 0]);
+    out.extend_from_slice(data);
+    out
+}
+
+fn hello() -> Packet {
+    Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid: 2,
+        unk2: 0x68,
+    })
+}
+
+fn capture_session(packet_type: PacketType, key: &rsa::RsaPrivateKey) -> Capture {
+    let mut client = ProtocolEngine::<Packet>::new(
+        packet_type,
+        PrivateKey::None,
+        PublicKey::Key(key.to_public_key()),
+    );
+    let mut server =
+        ProtocolEngine::<Packet>::new(packet_type, PrivateKey::Key(key.clone()), PublicKey::None);
+    server.send_packet(&hello()).unwrap();
+    let server_hello = server.take_pending();
+    client.start_handshake().unwrap();
+    client.send_packet(&Packet::InitialLoad).unwrap();
+    let to_server = client.take_pending();
+    let key_log = client.take_key_log_entry().unwrap();
+    // request is sent unencrypted
+    let request_len = u32::from_le_bytes(to_server[..4].try_into().unwrap()) as usize;
+    let request = Packet::read(&to_server[..request_len], packet_type)
+        .unwrap()
+        .remove(0);
+    server.receive_data(&to_server).unwrap();
+    while server.poll_packet().unwrap().is_some() {}
+    let response = Packet::EncryptionResponse(EncryptionResponsePacket {
+        data: server.get_key().into(),
+    });
+    server.send_packet(&response).unwrap();
+    server.send_packet(&Packet::ServerPing).unwrap();
+    let to_client = server.take_pending();
+
+    let (client_seq, server_seq) = (1000u32, 5000u32);
+    let (first, second) = to_server.split_at(to_server.len() / 2);
+    let second_seq = client_seq + 1 + first.len() as u32;
+    let frames = vec![
+        frame(Direction::ToServer, client_seq, SYN, &[]),
+        frame(Direction::ToClient, server_seq, SYN | ACK, &[]),
+        frame(Direction::ToClient, server_seq + 1, PSH_ACK, &server_hello),
+        // out of order segments and a retransmission
+        frame(Direction::ToServer, second_seq, PSH_ACK, second),
+        frame(Direction::ToServer, client_seq + 1, PSH_ACK, first),
+        frame(Direction::ToServer, client_seq + 1, PSH_ACK, first),
+        frame(
+            Direction::ToClient,
+            server_seq + 1 + server_hello.len() as u32,
+            PSH_ACK,
+            &to_client,
+        ),
+    ];
+    let frames = frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| (1_700_000_000_000_000 + i as u64 * 1000, f))
+        .collect();
+    let expected = vec![
+        (Direction::ToClient, hello()),
+        (Direction::ToServer, request),
+        (Direction::ToServer, Packet::InitialLoad),
+        (Direction::ToClient, response),
+        (Direction::ToClient, Packet::ServerPing),
+    ];
+    Capture {
+        frames,
+        expected,
+        key_log,
+    }
+}
+
+fn write_pcap(frames: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut writer = PcapWriter::new(vec![]).unwrap();
+    for (time, data) in frames {
+        writer
+            .write_packet(&PcapPacket::new(
+                Duration::from_micros(*time),
+                data.len() as u32,
+                data,
+            ))
+            .unwrap();
+    }
+    writer.into_writer()
+}
+
+fn write_pcapng(frames: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut writer = PcapNgWriter::new(vec![]).unwrap();
+    writer
+        .write_pcapng_block(InterfaceDescriptionBlock {
+            linktype: DataLink::ETHERNET,
+            snaplen: 0,
+            options: vec![],
+        })
+        .unwrap();
+    for (time, data) in frames {
+        writer
+            .write_pcapng_block(EnhancedPacketBlock {
+                interface_id: 0,
+                // raw value in the default (microsecond) resolution
+                timestamp: Duration::from_nanos(*time),
+                original_len: data.len() as u32,
+                data: data.into(),
+                options: vec![],
+            })
+            .unwrap();
+    }
+    writer.into_inner()
+}
+
+fn import(packet_type: PacketType, use_pcapng: bool, use_key_log: bool) {
+    let key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let Capture {
+        frames,
+        expected,
+        key_log,
+    } = capture_session(packet_type, &key);
+    let capture = match use_pcapng {
+        true => write_pcapng(&frames),
+        false => write_pcap(&frames),
+    };
+
+    let mut writer = PPACWriter::new(vec![], packet_type, false).unwrap();
+    let importer = if use_key_log {
+        let mut importer = PcapImporter::<Packet>::new(packet_type, PrivateKey::None);
+        importer.add_key_log([key_log]);
+        importer
+    } else {
+        PcapImporter::<Packet>::new(packet_type, PrivateKey::Key(key))
+    };
+    let stats = importer
+        .import_to_ppac(capture.as_slice(), &mut writer)
+        .unwrap();
+    assert_eq!(stats.sessions, 1);
+    assert_eq!(stats.failed_sessions, 0);
+    assert_eq!(stats.packets, expected.len());
+
+    let ppac = writer.into_inner().unwrap();
+    let mut reader = PPACReader::<_, Packet>::open(ppac.as_slice()).unwrap();
+    let mut packets = vec![];
+    while let Some(data) = reader.read().unwrap() {
+        packets.push((data.time, data.direction, data.packet.unwrap()));
+    }
+    assert_eq!(packets.len(), expected.len());
+    for ((_, direction, packet), (exp_direction, exp_packet)) in packets.iter().zip(&expected) {
+        assert_eq!(*direction as u8, *exp_direction as u8);
+        assert_eq!(packet, exp_packet);
+    }
+    // hello is completed by the third frame
+    assert_eq!(packets[0].0, Duration::from_micros(frames[2].0));
+    // request is completed by the reordered segment
+    assert_eq!(packets[1].0, Duration::from_micros(frames[4].0));
+}
+
+#[cfg(feature = "ngs_enc")]
+#[test]
+fn test_import_ngs() {
+    import(PacketType::NGS, false, false);
+    import(PacketType::NGS, true, false);
+}
+
+#[cfg(feature = "vita_enc")]
+#[test]
+fn test_import_vita() {
+    import(PacketType::Vita, false, false);
+    import(PacketType::Vita, true, false);
+}
+
+#[cfg(feature = "vita_enc")]
+#[test]
+fn test_import_classic() {
+    import(PacketType::Classic, false, false);
+}
+
+#[cfg(feature = "ngs_enc")]
+#[test]
+fn test_import_key_log() {
+    import(PacketType::NGS, false, true);
+}
+
+#[cfg(feature = "ngs_enc")]
+#[test]
+fn test_import_wrong_key() {
+    let key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let wrong_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let capture = write_pcap(&capture_session(PacketType::NGS, &key).frames);
+    let importer = PcapImporter::<Packet>::new(PacketType::NGS, PrivateKey::Key(wrong_key));
+    let mut packets = 0;
+    let stats = importer
+        .import(capture.as_slice(), |_, _, _, _| {
+            packets += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(stats.failed_sessions, 1);
+    // only unencrypted packets
+    assert_eq!(packets, 2);
+}
+
+#[test]
+fn test_import_gap() {
+    let mut frames = vec![(0, frame(Direction::ToServer, 0, SYN, &[]))];
+    // first segment is missing
+    for i in 0..2000 {
+        frames.push((i, frame(Direction::ToServer, 2 + i as u32, ACK, &[0])));
+    }
+    let capture = write_pcap(&frames);
+    let importer = PcapImporter::<Packet>::new(PacketType::NGS, PrivateKey::None);
+    let stats = importer
+        .import(capture.as_slice(), |_, _, _, _| Ok(()))
+        .unwrap();
+    assert_eq!(stats.sessions, 1);
+    assert_eq!(stats.failed_sessions, 1);
+}
+
+#[test]
+fn test_export_pcapng() {
+    use pcap_file::pcapng::blocks::section_header::SectionHeaderOption;
+    use pso2packetlib::{pcap::export_to_pcapng, ppac::Metadata};
+
+    let metadata = Metadata {
+        server: Some("40.91.76.146:12100".parse().unwrap()),
+        ..Default::default()
+    };
+    let mut writer = PPACWriter::with_metadata(vec![], PacketType::NGS, false, &metadata).unwrap();
+    let mut large = vec![0xAB; 70000];
+    large[..4].copy_from_slice(&70000u32.to_le_bytes());
+    let start = Duration::from_secs(1_700_000_000);
+    writer
+        .write_packet(start, Direction::ToClient, &hello())
+        .unwrap();
+    writer.set_stream_id(1);
+    writer
+        .write_data_unchecked(start * 2, Direction::ToServer, &large)
+        .unwrap();
+    let ppac = writer.into_inner().unwrap();
+
+    let reader = PPACReader::<_, Packet>::open(ppac.as_slice()).unwrap();
+    let (capture, packets) = export_to_pcapng(reader, vec![]).unwrap();
+    assert_eq!(packets, 2);
+
+    let mut reader = pcap_file::pcapng::PcapNgReader::new(capture.as_slice()).unwrap();
+    assert!(reader
+        .section()
+        .options
+        .contains(&SectionHeaderOption::Comment("packet type: NGS".into())));
+    // (timestamp, source, destination, payload)
+    let mut segments = vec![];
+    while let Some(block) = reader.next_block() {
+        let Block::EnhancedPacket(epb) = block.unwrap() else {
+            continue;
+        };
+        let ip = &epb.data;
+        // valid IP header checksum
+        let sum = ip[..20]
+            .chunks(2)
+            .map(|w| u16::from_be_bytes([w[0], w[1]]) as u32)
+            .sum::<u32>();
+        assert_eq!((sum & 0xFFFF) + (sum >> 16), 0xFFFF);
+        let port = |i: usize| u16::from_be_bytes([ip[i], ip[i + 1]]);
+        segments.push((epb.timestamp, port(20), port(22), ip[40..].to_vec()));
+    }
+    // two handshakes, one hello and the large packet split into two segments
+    assert_eq!(segments.len(), 9);
+    assert_eq!(segments[3].0, start);
+    assert_eq!(segments[3].1, 12100);
+    assert_eq!(segments[3].3, hello().write(PacketType::NGS));
+    let client_port = segments[4].1;
+    assert_ne!(client_port, segments[3].2);
+    let data: Vec<u8> = segments[7..]
+        .iter()
+        .flat_map(|(time, src, _, data)| {
+            assert_eq!(*time, start * 2);
+            assert_eq!(*src, client_port);
+            data.clone()
+        })
+        .collect();
+    assert_eq!(data, large);
+}
@@ -0,0 +1,480 @@
+#![cfg(feature = "ppac")]
+
+use pso2packetlib::{
+    ppac::{
+        edit::{self, SplitBy},
+        Direction, Metadata, OutputType, PPACError, PPACReader, PPACWriter, PacketData,
+        PacketFilter,
+    },
+    protocol::{server::ServerHelloPacket, Packet, PacketCategory, PacketType, ProtocolRW},
+};
+use std::{io::Cursor, time::Duration};
+
+const COUNT: u16 = 3000;
+
+fn hello(blockid: u16) -> Packet {
+    Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid,
+        unk2: 0x68,
+    })
+}
+
+fn time(i: u16) -> Duration {
+    Duration::from_secs(1_700_000_000) + Duration::from_millis(i as u64 * 10)
+}
+
+fn write_file(is_packed: bool, index: bool) -> Vec<u8> {
+    let mut writer = PPACWriter::new(Cursor::new(vec![]), PacketType::NGS, is_packed).unwrap();
+    if index {
+        writer.enable_index(4096);
+    }
+    for i in 0..COUNT {
+        writer
+            .write_packet(time(i), Direction::ToClient, &hello(i))
+            .unwrap();
+    }
+    writer.into_inner().unwrap().into_inner()
+}
+
+fn next_blockid(reader: &mut PPACReader<Cursor<Vec<u8>>, Packet>) -> Option<u16> {
+    match reader.read().unwrap()?.packet {
+        Some(Packet::ServerHello(p)) => Some(p.blockid),
+        p => panic!("unexpected packet: {p:?}"),
+    }
+}
+
+fn check_seek(data: Vec<u8>) {
+    let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    for i in 0..COUNT {
+        assert_eq!(next_blockid(&mut reader), Some(i));
+    }
+    assert_eq!(next_blockid(&mut reader), None);
+
+    for n in [2500, 0, 1025, 1024, 1023, 7] {
+        reader.seek_to_packet(n as u64).unwrap();
+        assert_eq!(next_blockid(&mut reader), Some(n));
+        assert_eq!(next_blockid(&mut reader), Some(n + 1));
+    }
+    reader.seek_to_packet(COUNT as u64 + 10).unwrap();
+    assert_eq!(next_blockid(&mut reader), None);
+
+    reader.seek_to_time(time(1500)).unwrap();
+    assert_eq!(next_blockid(&mut reader), Some(1500));
+    reader
+        .seek_to_time(time(42) + Duration::from_millis(1))
+        .unwrap();
+    assert_eq!(next_blockid(&mut reader), Some(43));
+    reader.seek_to_time(Duration::ZERO).unwrap();
+    assert_eq!(next_blockid(&mut reader), Some(0));
+}
+
+#[test]
+fn test_seek_indexed() {
+    let data = write_file(true, true);
+    assert!(data.ends_with(b"PIDX"));
+    check_seek(data);
+}
+
+#[test]
+fn test_seek_unindexed() {
+    check_seek(write_file(true, false));
+}
+
+#[test]
+fn test_seek_unpacked() {
+    check_seek(write_file(false, false));
+}
+
+#[test]
+fn test_seek_truncated() {
+    let mut data = write_file(false, false);
+    // cut the last record in the middle of its length
+    let packet_len = hello(0).write(PacketType::NGS).len();
+    data.truncate(data.len() - packet_len - 4);
+    let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    for i in 0..10 {
+        assert_eq!(next_blockid(&mut reader), Some(i));
+    }
+    assert!(reader.seek_to_packet(100).is_err());
+    // the reader continues where it stopped
+    for i in 10..COUNT - 1 {
+        assert_eq!(next_blockid(&mut reader), Some(i));
+    }
+    assert!(reader.read().is_err());
+}
+
+#[test]
+fn test_metadata() {
+    let metadata = Metadata {
+        client_build: Some("7.0100.0".into()),
+        start_time: Some(time(0)),
+        server: Some("40.91.76.146:12000".parse().unwrap()),
+        client: Some("192.168.0.2:50000".parse().unwrap()),
+        tool: Some("test".into()),
+        notes: None,
+    };
+    for is_packed in [false, true] {
+        let mut writer =
+            PPACWriter::with_metadata(Cursor::new(vec![]), PacketType::NGS, is_packed, &metadata)
+                .unwrap();
+        for i in 0..4 {
+            writer.set_stream_id(i as u32 % 2);
+            writer
+                .write_packet(time(i), Direction::ToServer, &hello(i))
+                .unwrap();
+        }
+        let data = writer.into_inner().unwrap().into_inner();
+
+        let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+        assert_eq!(reader.get_metadata(), &metadata);
+        let mut i = 0;
+        while let Some(packet) = reader.read().unwrap() {
+            assert_eq!(packet.stream_id, i as u32 % 2);
+            assert_eq!(packet.time, time(i));
+            assert_eq!(packet.packet, Some(hello(i)));
+            i += 1;
+        }
+        assert_eq!(i, 4);
+    }
+}
+
+#[test]
+fn test_read_v4() {
+    let packet = hello(5).write(PacketType::NGS);
+    let mut data = b"PPAC".to_vec();
+    // version, NGS, unpacked
+    data.extend_from_slice(&[4, 1, 0]);
+    data.extend_from_slice(&time(5).as_nanos().to_le_bytes());
+    data.push(Direction::ToClient as u8);
+    data.extend_from_slice(&(packet.len() as u64).to_le_bytes());
+    data.extend_from_slice(&packet);
+
+    let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    assert_eq!(reader.get_metadata(), &Metadata::default());
+    let packet = reader.read().unwrap().unwrap();
+    assert_eq!(packet.stream_id, 0);
+    assert_eq!(packet.time, time(5));
+    assert_eq!(packet.packet, Some(hello(5)));
+    assert!(reader.read().unwrap().is_none());
+}
+
+fn filter_file() -> Vec<u8> {
+    let mut writer = PPACWriter::new(Cursor::new(vec![]), PacketType::NGS, true).unwrap();
+    writer
+        .write_packet(time(0), Direction::ToClient, &hello(0))
+        .unwrap();
+    writer
+        .write_packet(time(1), Direction::ToClient, &Packet::ServerPing)
+        .unwrap();
+    writer
+        .write_packet(time(2), Direction::ToServer, &Packet::ServerPong)
+        .unwrap();
+    // two packets in one record
+    let mut data = Packet::SetTag(Default::default()).write(PacketType::NGS);
+    data.extend(hello(3).write(PacketType::NGS));
+    writer
+        .write_data_unchecked(time(3), Direction::ToClient, &data)
+        .unwrap();
+    // truncated hello
+    let mut data = hello(4).write(PacketType::NGS);
+    data.truncate(data.len() - 2);
+    let len = data.len() as u32;
+    data[..4].copy_from_slice(&len.to_le_bytes());
+    writer
+        .write_data_unchecked(time(4), Direction::ToClient, &data)
+        .unwrap();
+    writer.into_inner().unwrap().into_inner()
+}
+
+fn filtered(filter: PacketFilter, out_type: OutputType) -> Vec<PacketData<Packet>> {
+    let mut reader = PPACReader::<_, Packet>::open(Cursor::new(filter_file())).unwrap();
+    reader.set_out_type(out_type);
+    reader.set_filter(Some(filter));
+    reader.collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_filter() {
+    let packets = |filter| -> Vec<_> {
+        filtered(filter, OutputType::Packet)
+            .into_iter()
+            .map(|p| p.packet.unwrap())
+            .collect()
+    };
+    let parsed = PacketFilter::new().parse_failed(false);
+    assert_eq!(packets(parsed.clone()).len(), 5);
+    assert_eq!(
+        packets(parsed.clone().direction(Direction::ToServer)),
+        vec![Packet::ServerPong]
+    );
+    assert_eq!(
+        packets(PacketFilter::new().category(PacketCategory::Object)),
+        vec![Packet::SetTag(Default::default())]
+    );
+    assert_eq!(
+        packets(
+            parsed
+                .clone()
+                .id(0x03, 0x0B)
+                .category(PacketCategory::Object)
+        ),
+        vec![Packet::ServerPing, Packet::SetTag(Default::default())]
+    );
+    assert_eq!(
+        packets(parsed.clone().id(0x03, 0x08).time_range(time(1)..=time(3))),
+        vec![hello(3)]
+    );
+    assert_eq!(
+        packets(PacketFilter::new().time_range(..time(1))),
+        vec![hello(0)]
+    );
+
+    let failed = filtered(PacketFilter::new().parse_failed(true), OutputType::Raw);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].time, time(4));
+    assert!(failed[0].parse_error.is_some());
+    assert!(failed[0].data.is_some());
+
+    // parse errors are returned if failed packets weren't requested
+    let reader = PPACReader::<_, Packet>::open(Cursor::new(filter_file())).unwrap();
+    assert_eq!(reader.filter_map(Result::ok).count(), 5);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_background_writer() {
+    use pso2packetlib::ppac::BackgroundPPACWriter;
+
+    let writer = PPACWriter::new(Cursor::new(vec![]), PacketType::NGS, true).unwrap();
+    let mut writer = BackgroundPPACWriter::new(writer);
+    writer.change_packet_type(PacketType::JP).unwrap();
+    for i in 0..10 {
+        writer
+            .write_packet(time(i), Direction::ToClient, &hello(i))
+            .unwrap();
+    }
+    let data = writer.finish().await.unwrap().into_inner();
+
+    let reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    assert_eq!(reader.get_protocol_type(), PacketType::JP);
+    let packets = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(packets.len(), 10);
+    assert_eq!(packets[9].packet, Some(hello(9)));
+}
+
+/// Writes a packed file with a frame for every 10 packets. Returns the file and frame offsets.
+fn framed_file(count: u16) -> (Vec<u8>, Vec<usize>) {
+    let mut writer = PPACWriter::new(Cursor::new(vec![]), PacketType::NGS, true).unwrap();
+    for i in 0..count {
+        writer
+            .write_packet(time(i), Direction::ToClient, &hello(i))
+            .unwrap();
+        if i % 10 == 9 {
+            writer.flush_frame().unwrap();
+        }
+    }
+    let data = writer.into_inner().unwrap().into_inner();
+    let frames = data
+        .windows(4)
+        .enumerate()
+        .filter(|(_, w)| *w == [0x28, 0xB5, 0x2F, 0xFD])
+        .map(|(i, _)| i)
+        .collect();
+    (data, frames)
+}
+
+fn salvage(data: Vec<u8>) -> (Vec<u16>, PPACReader<Cursor<Vec<u8>>, Packet>) {
+    let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    reader.set_salvage(true);
+    let mut blockids = vec![];
+    while let Some(packet) = reader.read().unwrap() {
+        match packet.packet {
+            Some(Packet::ServerHello(p)) => blockids.push(p.blockid),
+            p => panic!("unexpected packet: {p:?}"),
+        }
+    }
+    (blockids, reader)
+}
+
+#[test]
+fn test_salvage_truncated() {
+    let (mut data, frames) = framed_file(30);
+    // the last frame is empty
+    assert_eq!(frames.len(), 4);
+    data.truncate(frames[2] + 20);
+
+    let reader = PPACReader::<_, Packet>::open(Cursor::new(data.clone())).unwrap();
+    let packets = reader.collect::<Vec<_>>();
+    assert_eq!(packets.len(), 21);
+    assert!(packets[20].is_err());
+
+    let (blockids, reader) = salvage(data);
+    assert_eq!(blockids, (0..20).collect::<Vec<_>>());
+    let damage = reader.get_damage();
+    assert_eq!(damage.len(), 1);
+    assert_eq!(damage[0].record, 20);
+    assert_eq!(damage[0].offset, frames[2] as u64);
+
+    // unpacked files are read up to the damaged record
+    let mut data = write_file(false, false);
+    let len = data.len();
+    data.truncate(len - 5);
+    let (blockids, reader) = salvage(data);
+    assert_eq!(blockids.len(), COUNT as usize - 1);
+    let damage = reader.get_damage();
+    assert_eq!(damage.len(), 1);
+    assert_eq!(damage[0].record, COUNT as u64 - 1);
+    assert_eq!(
+        damage[0].offset,
+        (len - hello(0).write(PacketType::NGS).len() - 29) as u64
+    );
+}
+
+#[test]
+fn test_salvage_corrupted() {
+    let (mut data, frames) = framed_file(30);
+    for byte in &mut data[frames[1] + 10..frames[1] + 30] {
+        *byte = 0xAA;
+    }
+    let (blockids, reader) = salvage(data);
+    assert_eq!(blockids, (0..10).chain(20..30).collect::<Vec<_>>());
+    let damage = reader.get_damage();
+    assert_eq!(damage.len(), 1);
+    assert_eq!(damage[0].record, 10);
+    assert_eq!(damage[0].offset, frames[1] as u64);
+}
+
+#[test]
+fn test_flush_interval() {
+    let mut data = vec![];
+    let mut writer = PPACWriter::new(&mut data, PacketType::NGS, true).unwrap();
+    writer.set_flush_interval(Some(Duration::from_millis(100)));
+    for i in 0..25 {
+        writer
+            .write_packet(time(i), Direction::ToClient, &hello(i))
+            .unwrap();
+    }
+    // simulate a crash
+    std::mem::forget(writer);
+
+    let (blockids, _) = salvage(data);
+    assert_eq!(blockids, (0..22).collect::<Vec<_>>());
+}
+
+fn read_all(data: Vec<u8>) -> Vec<PacketData<Packet>> {
+    let reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+    reader.collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn test_edit() {
+    let write = |packet_type, stream_id, times: &[u16]| {
+        let mut writer = PPACWriter::new(Cursor::new(vec![]), packet_type, false).unwrap();
+        writer.set_stream_id(stream_id);
+        for &i in times {
+            writer
+                .write_packet(time(i), Direction::ToClient, &hello(i))
+                .unwrap();
+        }
+        writer.into_inner().unwrap().into_inner()
+    };
+    let a = write(PacketType::JP, 0, &[0, 2, 4, 6]);
+    let b = write(PacketType::JP, 0, &[1, 3, 5]);
+
+    let merged = edit::merge(vec![&a[..], &b[..]], Cursor::new(vec![]))
+        .unwrap()
+        .into_inner();
+    let reader = PPACReader::<_, Packet>::open(Cursor::new(merged.clone())).unwrap();
+    assert_eq!(reader.get_protocol_type(), PacketType::JP);
+    let packets = read_all(merged.clone());
+    assert_eq!(
+        packets.iter().map(|p| p.packet.clone()).collect::<Vec<_>>(),
+        (0..7).map(|i| Some(hello(i))).collect::<Vec<_>>()
+    );
+    assert_eq!(packets[0].stream_id, 0);
+    assert_eq!(packets[1].stream_id, 1);
+
+    let c = write(PacketType::NGS, 0, &[7]);
+    assert!(matches!(
+        edit::merge(vec![&a[..], &c[..]], vec![]),
+        Err(PPACError::PacketTypeMismatch(
+            PacketType::JP,
+            PacketType::NGS
+        ))
+    ));
+
+    let split = |by| -> Vec<Vec<u16>> {
+        edit::split(Cursor::new(merged.clone()), by, |_| Ok(vec![]))
+            .unwrap()
+            .into_iter()
+            .map(|part| {
+                read_all(part)
+                    .into_iter()
+                    .map(|p| match p.packet {
+                        Some(Packet::ServerHello(p)) => p.blockid,
+                        p => panic!("unexpected packet: {p:?}"),
+                    })
+                    .collect()
+            })
+            .collect()
+    };
+    assert_eq!(
+        split(SplitBy::Stream),
+        vec![vec![0, 2, 4, 6], vec![1, 3, 5]]
+    );
+    assert_eq!(
+        split(SplitBy::Records(3)),
+        vec![vec![0, 1, 2], vec![3, 4, 5], vec![6]]
+    );
+    assert_eq!(
+        split(SplitBy::Time(Duration::from_millis(25))),
+        vec![vec![0, 1, 2], vec![3, 4], vec![5, 6]]
+    );
+
+    let trimmed = edit::trim(&a[..], vec![], time(1)..=time(4)).unwrap();
+    let packets = read_all(trimmed);
+    assert_eq!(packets.len(), 2);
+    assert_eq!(packets[1].packet, Some(hello(4)));
+}
+
+#[cfg(any(feature = "json", feature = "msgpack"))]
+#[test]
+fn test_export() {
+    use pso2packetlib::ppac::export;
+
+    let raw = |data: Vec<u8>| -> Vec<_> {
+        let mut reader = PPACReader::<_, Packet>::open(Cursor::new(data)).unwrap();
+        reader.set_out_type(OutputType::Raw);
+        reader
+            .map(|p| {
+                let p = p.unwrap();
+                (p.time, p.direction, p.stream_id, p.data.unwrap())
+            })
+            .collect()
+    };
+    let open = || PPACReader::<_, Packet>::open(Cursor::new(filter_file())).unwrap();
+    let records = export::records(open())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 6);
+    assert_eq!(records[0].packet, Some(hello(0)));
+    assert!(records[0].data.is_none());
+    assert!(records[5].packet.is_none());
+    assert!(records[5].data.is_some());
+    assert!(records[5].parse_error.is_some());
+
+    #[cfg(feature = "json")]
+    {
+        let json = export::to_json_lines(open(), vec![]).unwrap();
+        assert_eq!(json.iter().filter(|&&b| b == b'\n').count(), 6);
+        let data = export::from_json_lines::<Packet, _>(&json[..], vec![], true).unwrap();
+        assert_eq!(raw(data), raw(filter_file()));
+    }
+    #[cfg(feature = "msgpack")]
+    {
+        let msgpack = export::to_msgpack(open(), vec![]).unwrap();
+        let data = export::from_msgpack::<Packet, _>(&msgpack[..], vec![], false).unwrap();
+        assert_eq!(raw(data), raw(filter_file()));
+    }
+}
@@ -0,0 +1,273 @@
+#![cfg(feature = "proxy")]
+
+use pso2packetlib::{
+    ppac::Direction,
+    protocol::{server::ServerHelloPacket, Packet},
+    proxy::{Action, ProxyContext, ProxyHandler},
+};
+
+// answers pings on behalf of the client and changes the block id
+struct Handler;
+
+impl ProxyHandler<Packet> for Handler {
+    fn on_packet(
+        &mut self,
+        ctx: &mut ProxyContext<Packet>,
+        _: Direction,
+        packet: Packet,
+    ) -> Action<Packet> {
+        match packet {
+            Packet::ServerPing => {
+                ctx.inject(Direction::ToServer, Packet::ServerPong);
+                Action::Drop
+            }
+            Packet::ServerHello(mut hello) => {
+                hello.blockid = 10;
+                Action::Forward(Packet::ServerHello(hello))
+            }
+            packet => Action::Forward(packet),
+        }
+    }
+}
+
+fn hello(blockid: u16) -> Packet {
+    Packet::ServerHello(ServerHelloPacket {
+        unk1: 3,
+        blockid,
+        unk2: 0x68,
+    })
+}
+
+#[cfg(all(unix, not(feature = "tokio"), feature = "base_enc"))]
+#[test]
+fn test_proxy() {
+    use pso2packetlib::{
+        ppac::PPACReader,
+        protocol::{login::EncryptionResponsePacket, PacketType},
+        proxy::Proxy,
+        Connection, PrivateKey, PublicKey,
+    };
+    use std::os::unix::net::UnixStream;
+
+    fn wait_for(proxy: &mut Proxy<Packet, Handler, UnixStream, UnixStream>) {
+        while !proxy.poll().unwrap() {
+            std::thread::yield_now();
+        }
+    }
+
+    let proxy_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let server_key = rsa::RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+    let (client, proxy_client) = UnixStream::pair().unwrap();
+    let (proxy_server, server) = UnixStream::pair().unwrap();
+    proxy_client.set_nonblocking(true).unwrap();
+    proxy_server.set_nonblocking(true).unwrap();
+
+    let packet_type = PacketType::NA;
+    let mut client = Connection::<Packet, _>::from_stream(
+        client,
+        packet_type,
+        PrivateKey::None,
+        PublicKey::Key(proxy_key.to_public_key()),
+    );
+    let mut server = Connection::<Packet, _>::from_stream(
+        server,
+        packet_type,
+        PrivateKey::Key(server_key.clone()),
+        PublicKey::None,
+    );
+    let mut proxy = Proxy::new(
+        Connection::from_stream(
+            proxy_client,
+            packet_type,
+            PrivateKey::Key(proxy_key),
+            PublicKey::None,
+        ),
+        Connection::from_stream(
+            proxy_server,
+            packet_type,
+            PrivateKey::None,
+            PublicKey::Key(server_key.to_public_key()),
+        ),
+        Handler,
+    );
+    let ppac_path = std::env::temp_dir().join(format!("proxy_test_{}.pak", std::process::id()));
+    proxy.create_ppac(&ppac_path).unwrap();
+
+    client.start_handshake().unwrap();
+    wait_for(&mut proxy);
+    let Packet::EncryptionRequest(_) = server.read_packet().unwrap() else {
+        panic!("expected encryption request");
+    };
+    assert_eq!(server.get_key(), client.get_key());
+    let key = server.get_key();
+    server
+        .write_packet(&Packet::EncryptionResponse(EncryptionResponsePacket {
+            data: key.into(),
+        }))
+        .unwrap();
+    wait_for(&mut proxy);
+    let Packet::EncryptionResponse(_) = client.read_packet().unwrap() else {
+        panic!("expected encryption response");
+    };
+
+    server.write_packet(&Packet::ServerPing).unwrap();
+    server.write_packet(&hello(2)).unwrap();
+    wait_for(&mut proxy);
+    assert_eq!(server.read_packet().unwrap(), Packet::ServerPong);
+    assert_eq!(client.read_packet().unwrap(), hello(10));
+
+    drop(proxy);
+    let mut reader =
+        PPACReader::<_, Packet>::open(std::fs::File::open(&ppac_path).unwrap()).unwrap();
+    let mut packets = vec![];
+    while let Some(data) = reader.read().unwrap() {
+        packets.push((data.direction, data.packet.unwrap()));
+    }
+    std::fs::remove_file(&ppac_path).unwrap();
+    assert_eq!(packets.len(), 4);
+    assert!(matches!(
+        packets[2],
+        (Direction::ToClient, Packet::ServerPing)
+    ));
+    assert_eq!(packets[3].1, hello(2));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_proxy_async() {
+    use pso2packetlib::{protocol::PacketType, proxy::Proxy, Connection, PrivateKey, PublicKey};
+
+    let (client, proxy_client) = tokio::io::duplex(1024);
+    let (proxy_server, server) = tokio::io::duplex(1024);
+    let packet_type = PacketType::NGS;
+    let new_conn = |stream| {
+        Connection::<Packet, _>::from_stream(stream, packet_type, PrivateKey::None, PublicKey::None)
+    };
+    let mut client = new_conn(client);
+    let mut server = new_conn(server);
+    let mut proxy = Proxy::new(new_conn(proxy_client), new_conn(proxy_server), Handler);
+
+    let test = async {
+        server
+            .write_packet_async(&Packet::ServerPing)
+            .await
+            .unwrap();
+        server.write_packet_async(&hello(2)).await.unwrap();
+        assert_eq!(
+            server.read_packet_async().await.unwrap(),
+            Packet::ServerPong
+        );
+        assert_eq!(client.read_packet_async().await.unwrap(), hello(10));
+        client
+            .write_packet_async(&Packet::InitialLoad)
+            .await
+            .unwrap();
+        assert_eq!(
+            server.read_packet_async().await.unwrap(),
+            Packet::InitialLoad
+        );
+    };
+    tokio::select! {
+        result = proxy.run_async() => panic!("proxy stopped: {result:?}"),
+        _ = test => {}
+    }
+}
+
+#[test]
+fn test_address_rewriter() {
+    use pso2packetlib::{
+        protocol::login::{
+            BlockInfo, BlockListPacket, BlockSwitchResponsePacket, ShipEntry, ShipListPacket,
+        },
+        proxy::AddressRewriter,
+    };
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    let listen_ip = Ipv4Addr::new(127, 0, 0, 1);
+    let mut next_port = 20000;
+    let mut rewriter = AddressRewriter::new(listen_ip, move |_| {
+        next_port += 1;
+        Ok(SocketAddrV4::new(listen_ip, next_port))
+    });
+    let upstream_ip = Ipv4Addr::new(40, 91, 76, 146);
+    let block = |port| BlockInfo {
+        ip: upstream_ip,
+        port,
+        ..Default::default()
+    };
+
+    let mut packet = Packet::BlockList(BlockListPacket {
+        blocks: vec![block(12001), block(12002), block(12001)].into(),
+        ..Default::default()
+    });
+    assert!(rewriter.rewrite(&mut packet).unwrap());
+    let Packet::BlockList(list) = &packet else {
+        unreachable!()
+    };
+    let ports: Vec<_> = list.blocks.iter().map(|b| (b.ip, b.port)).collect();
+    assert_eq!(
+        ports,
+        vec![(listen_ip, 20001), (listen_ip, 20002), (listen_ip, 20001)]
+    );
+    assert_eq!(
+        rewriter.get_upstream(SocketAddrV4::new(listen_ip, 20002)),
+        Some(SocketAddrV4::new(upstream_ip, 12002))
+    );
+
+    // known target reuses the listener
+    let mut packet = Packet::BlockSwitchResponse(BlockSwitchResponsePacket {
+        ip: upstream_ip,
+        port: 12002,
+        ..Default::default()
+    });
+    rewriter.rewrite(&mut packet).unwrap();
+    let Packet::BlockSwitchResponse(switch) = &packet else {
+        unreachable!()
+    };
+    assert_eq!((switch.ip, switch.port), (listen_ip, 20002));
+    assert_eq!(rewriter.mappings().count(), 2);
+
+    let mut packet = Packet::ShipList(ShipListPacket {
+        ships: vec![
+            ShipEntry {
+                id: 1000,
+                ip: upstream_ip,
+                ..Default::default()
+            },
+            ShipEntry {
+                id: 10000,
+                ip: upstream_ip,
+                ..Default::default()
+            },
+        ],
+        ..Default::default()
+    });
+    rewriter.rewrite(&mut packet).unwrap();
+    let Packet::ShipList(ships) = &packet else {
+        unreachable!()
+    };
+    assert!(ships.ships.iter().all(|s| s.ip == listen_ip));
+    assert_eq!(
+        rewriter.get_upstream(SocketAddrV4::new(listen_ip, 20003)),
+        Some(SocketAddrV4::new(upstream_ip, 12100))
+    );
+    assert_eq!(
+        rewriter.get_upstream(SocketAddrV4::new(listen_ip, 20004)),
+        Some(SocketAddrV4::new(upstream_ip, 12000))
+    );
+
+    assert!(!rewriter.rewrite(&mut Packet::InitialLoad).unwrap());
+}
+
+#[test]
+fn test_channel_listeners() {
+    use pso2packetlib::proxy::{ChannelListeners, ListenerFactory};
+    use std::net::{Ipv4Addr, SocketAddrV4};
+
+    let (mut factory, receiver) = ChannelListeners::new(Ipv4Addr::LOCALHOST);
+    let upstream = SocketAddrV4::new(Ipv4Addr::new(40, 91, 76, 146), 12000);
+    let local = factory.listen(upstream).unwrap();
+    let (listener, target) = receiver.try_recv().unwrap();
+    assert_eq!(target, upstream);
+    assert_eq!(listener.local_addr().unwrap(), local.into());
+}